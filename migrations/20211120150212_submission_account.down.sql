@@ -0,0 +1,16 @@
+CREATE TABLE submission_site (
+    site TEXT NOT NULL,
+    id INTEGER NOT NULL,
+
+    title TEXT NOT NULL,
+    posted_at DATETIME NOT NULL,
+    tags TEXT NOT NULL,
+
+    PRIMARY KEY (site, id)
+);
+
+INSERT OR IGNORE INTO submission_site (site, id, title, posted_at, tags)
+    SELECT site, id, title, posted_at, tags FROM submission;
+
+DROP TABLE submission;
+ALTER TABLE submission_site RENAME TO submission;
@@ -0,0 +1,17 @@
+CREATE TABLE submission_account (
+    account TEXT NOT NULL,
+    site TEXT NOT NULL,
+    id INTEGER NOT NULL,
+
+    title TEXT NOT NULL,
+    posted_at DATETIME NOT NULL,
+    tags TEXT NOT NULL,
+
+    PRIMARY KEY (account, site, id)
+);
+
+INSERT INTO submission_account (account, site, id, title, posted_at, tags)
+    SELECT 'default', site, id, title, posted_at, tags FROM submission;
+
+DROP TABLE submission;
+ALTER TABLE submission_account RENAME TO submission;
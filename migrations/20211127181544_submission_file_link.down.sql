@@ -0,0 +1,4 @@
+DROP TABLE submission_link;
+DROP TABLE submission_file;
+
+ALTER TABLE submission DROP COLUMN file_url;
@@ -0,0 +1,26 @@
+ALTER TABLE submission ADD COLUMN file_url TEXT;
+
+CREATE TABLE submission_file (
+    site TEXT NOT NULL,
+    id INTEGER NOT NULL,
+
+    url TEXT NOT NULL,
+    path TEXT NOT NULL,
+    hash INTEGER,
+
+    PRIMARY KEY (site, id)
+);
+
+CREATE INDEX submission_file_hash_idx ON submission_file (hash);
+
+CREATE TABLE submission_link (
+    site TEXT NOT NULL,
+    id INTEGER NOT NULL,
+
+    linked_site TEXT NOT NULL,
+    linked_id TEXT NOT NULL,
+    url TEXT,
+    source TEXT NOT NULL,
+
+    PRIMARY KEY (site, id, linked_site, linked_id)
+);
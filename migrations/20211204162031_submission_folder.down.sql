@@ -0,0 +1,2 @@
+ALTER TABLE submission DROP COLUMN folder_name;
+ALTER TABLE submission DROP COLUMN folder_id;
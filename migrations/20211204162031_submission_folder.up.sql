@@ -0,0 +1,2 @@
+ALTER TABLE submission ADD COLUMN folder_id INTEGER;
+ALTER TABLE submission ADD COLUMN folder_name TEXT;
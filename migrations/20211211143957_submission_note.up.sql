@@ -0,0 +1,9 @@
+CREATE TABLE submission_note (
+    site TEXT NOT NULL,
+    id INTEGER NOT NULL,
+
+    local_tags TEXT NOT NULL DEFAULT '[]',
+    notes TEXT,
+
+    PRIMARY KEY (site, id)
+);
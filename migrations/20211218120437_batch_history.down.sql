@@ -0,0 +1,2 @@
+DROP TABLE batch_change;
+DROP TABLE batch;
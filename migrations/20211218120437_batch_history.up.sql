@@ -0,0 +1,21 @@
+CREATE TABLE batch (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account TEXT NOT NULL,
+
+    command TEXT NOT NULL,
+    search TEXT,
+    changes TEXT,
+    started_at TIMESTAMP NOT NULL
+);
+
+CREATE TABLE batch_change (
+    batch_id INTEGER NOT NULL REFERENCES batch (id) ON DELETE CASCADE,
+    site TEXT NOT NULL,
+    id INTEGER NOT NULL,
+
+    old_tags TEXT NOT NULL,
+    new_tags TEXT NOT NULL,
+    changed_at TIMESTAMP NOT NULL,
+
+    PRIMARY KEY (batch_id, site, id)
+);
@@ -0,0 +1 @@
+ALTER TABLE submission ADD COLUMN deleted_at DATETIME;
@@ -0,0 +1,16 @@
+CREATE TABLE submission_tag (
+    account TEXT NOT NULL,
+    site TEXT NOT NULL,
+    id INTEGER NOT NULL,
+
+    tag TEXT NOT NULL,
+
+    PRIMARY KEY (account, site, id, tag)
+);
+
+CREATE INDEX submission_tag_lookup ON submission_tag (account, tag);
+
+INSERT OR IGNORE INTO submission_tag (account, site, id, tag)
+    SELECT submission.account, submission.site, submission.id, lower(replace(trim(json_each.value), ' ', '_'))
+    FROM submission, json_each(submission.tags)
+    WHERE json_valid(submission.tags);
@@ -0,0 +1,42 @@
+ALTER TABLE submission ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
+
+UPDATE submission SET tags = (
+    SELECT json_group_array(name) FROM (
+        SELECT tag.name FROM submission_tag
+        JOIN tag ON tag.id = submission_tag.tag_id
+        WHERE submission_tag.account = submission.account AND submission_tag.site = submission.site AND submission_tag.id = submission.id
+        ORDER BY submission_tag.position
+    )
+);
+
+UPDATE submission SET tags = (
+    SELECT tags FROM submission_tags_unparsed
+    WHERE submission_tags_unparsed.account = submission.account AND submission_tags_unparsed.site = submission.site AND submission_tags_unparsed.id = submission.id
+)
+WHERE EXISTS (
+    SELECT 1 FROM submission_tags_unparsed
+    WHERE submission_tags_unparsed.account = submission.account AND submission_tags_unparsed.site = submission.site AND submission_tags_unparsed.id = submission.id
+);
+
+DROP TABLE submission_tags_unparsed;
+
+DROP TABLE submission_tag;
+
+CREATE TABLE submission_tag (
+    account TEXT NOT NULL,
+    site TEXT NOT NULL,
+    id INTEGER NOT NULL,
+
+    tag TEXT NOT NULL,
+
+    PRIMARY KEY (account, site, id, tag)
+);
+
+CREATE INDEX submission_tag_lookup ON submission_tag (account, tag);
+
+INSERT OR IGNORE INTO submission_tag (account, site, id, tag)
+    SELECT submission.account, submission.site, submission.id, lower(replace(trim(json_each.value), ' ', '_'))
+    FROM submission, json_each(submission.tags)
+    WHERE json_valid(submission.tags);
+
+DROP TABLE tag;
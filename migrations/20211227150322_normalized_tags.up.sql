@@ -0,0 +1,52 @@
+-- Tags that aren't a JSON array can't be split into rows, so they are kept
+-- here as they were instead of being lost with the column.
+CREATE TABLE submission_tags_unparsed (
+    account TEXT NOT NULL,
+    site TEXT NOT NULL,
+    id INTEGER NOT NULL,
+
+    tags TEXT NOT NULL,
+
+    PRIMARY KEY (account, site, id)
+);
+
+INSERT INTO submission_tags_unparsed (account, site, id, tags)
+    SELECT account, site, id, tags FROM submission
+    WHERE NOT json_valid(tags) OR json_type(tags) <> 'array';
+
+CREATE TABLE tag (
+    id INTEGER PRIMARY KEY,
+
+    name TEXT NOT NULL UNIQUE,
+    normalized TEXT NOT NULL
+);
+
+CREATE INDEX tag_normalized ON tag (normalized);
+
+INSERT OR IGNORE INTO tag (name, normalized)
+    SELECT json_each.value, lower(replace(trim(json_each.value), ' ', '_'))
+    FROM submission, json_each(submission.tags)
+    WHERE json_valid(submission.tags) AND json_type(submission.tags) = 'array';
+
+DROP TABLE submission_tag;
+
+CREATE TABLE submission_tag (
+    account TEXT NOT NULL,
+    site TEXT NOT NULL,
+    id INTEGER NOT NULL,
+
+    position INTEGER NOT NULL,
+    tag_id INTEGER NOT NULL REFERENCES tag (id),
+
+    PRIMARY KEY (account, site, id, position)
+);
+
+CREATE INDEX submission_tag_tag ON submission_tag (tag_id);
+
+INSERT INTO submission_tag (account, site, id, position, tag_id)
+    SELECT submission.account, submission.site, submission.id, json_each.key, tag.id
+    FROM submission, json_each(submission.tags)
+    JOIN tag ON tag.name = json_each.value
+    WHERE json_valid(submission.tags) AND json_type(submission.tags) = 'array';
+
+ALTER TABLE submission DROP COLUMN tags;
@@ -0,0 +1,8 @@
+CREATE TABLE tag_casing (
+    account TEXT NOT NULL,
+    normalized TEXT NOT NULL,
+
+    name TEXT NOT NULL,
+
+    PRIMARY KEY (account, normalized)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE submission_pin (
+    account TEXT NOT NULL,
+    site TEXT NOT NULL,
+    id INTEGER NOT NULL,
+
+    reason TEXT,
+    pinned_at TIMESTAMP NOT NULL,
+
+    PRIMARY KEY (account, site, id)
+);
@@ -0,0 +1 @@
+ALTER TABLE submission ADD COLUMN site_data TEXT;
@@ -0,0 +1,9 @@
+CREATE TABLE site_credential (
+    account TEXT NOT NULL,
+    site TEXT NOT NULL,
+    name TEXT NOT NULL,
+
+    value TEXT NOT NULL,
+
+    PRIMARY KEY (account, site, name)
+);
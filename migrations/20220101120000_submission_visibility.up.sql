@@ -0,0 +1 @@
+ALTER TABLE submission ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public';
@@ -0,0 +1,19 @@
+CREATE TABLE run (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account TEXT NOT NULL,
+
+    command TEXT NOT NULL,
+    args TEXT NOT NULL,
+    log_id TEXT NOT NULL,
+    started_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL,
+    finished_at TIMESTAMP,
+    status TEXT NOT NULL DEFAULT 'running',
+    error TEXT,
+
+    total INTEGER NOT NULL DEFAULT 0,
+    completed INTEGER NOT NULL DEFAULT 0,
+    failed INTEGER NOT NULL DEFAULT 0,
+    last_submission TEXT,
+    batch_id INTEGER REFERENCES batch (id) ON DELETE SET NULL
+);
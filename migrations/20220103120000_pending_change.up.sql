@@ -0,0 +1,12 @@
+CREATE TABLE pending_change (
+    account TEXT NOT NULL,
+    site TEXT NOT NULL,
+    id INTEGER NOT NULL,
+
+    old_tags TEXT NOT NULL,
+    new_tags TEXT NOT NULL,
+    changes TEXT NOT NULL,
+    queued_at TIMESTAMP NOT NULL,
+
+    PRIMARY KEY (account, site, id)
+);
@@ -0,0 +1,434 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+use crate::{config, sites::SubmissionSite};
+
+const WEASYL_AUTHORIZE_URL: &str = "https://www.weasyl.com/api/oauth2/authorize";
+const WEASYL_TOKEN_URL: &str = "https://www.weasyl.com/api/oauth2/token";
+const FURAFFINITY_LOGIN_URL: &str = "https://www.furaffinity.net/login/";
+
+/// How many minutes before an access token expires it is refreshed.
+const REFRESH_MARGIN_MINUTES: i64 = 5;
+
+/// Credentials saved for a site, such as OAuth2 tokens, by name.
+pub type Credentials = HashMap<String, String>;
+
+pub async fn load_credentials(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    site: SubmissionSite,
+) -> anyhow::Result<Credentials> {
+    let site = site.as_str();
+
+    let credentials = sqlx::query!(
+        "SELECT name, value FROM site_credential WHERE account = $1 AND site = $2",
+        account,
+        site
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| (row.name, row.value))
+    .collect();
+
+    Ok(credentials)
+}
+
+/// Save credentials for a site, replacing any with the same names.
+pub async fn store_credentials(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    site: SubmissionSite,
+    credentials: &Credentials,
+) -> anyhow::Result<()> {
+    let site = site.as_str();
+
+    let mut tx = pool.begin().await?;
+    for (name, value) in credentials {
+        sqlx::query!(
+            "INSERT INTO site_credential (account, site, name, value) VALUES ($1, $2, $3, $4)
+                ON CONFLICT (account, site, name) DO UPDATE SET value = excluded.value",
+            account,
+            site,
+            name,
+            value
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Tokens returned by an OAuth2 token endpoint.
+#[derive(Debug, serde::Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+}
+
+impl TokenResponse {
+    fn into_credentials(self) -> Credentials {
+        let mut credentials = Credentials::new();
+        credentials.insert("access_token".to_string(), self.access_token);
+        if let Some(refresh_token) = self.refresh_token {
+            credentials.insert("refresh_token".to_string(), refresh_token);
+        }
+        if let Some(expires_in) = self.expires_in {
+            let expires_at = chrono::Utc::now() + chrono::Duration::seconds(expires_in);
+            credentials.insert("expires_at".to_string(), expires_at.to_rfc3339());
+        }
+
+        credentials
+    }
+}
+
+/// Client ID and secret of the application registered with Weasyl.
+fn weasyl_client(profile: &config::Profile) -> anyhow::Result<(&str, &str)> {
+    match (&profile.weasyl_client_id, &profile.weasyl_client_secret) {
+        (Some(id), Some(secret)) => Ok((id.as_str(), secret.as_str())),
+        _ => anyhow::bail!("Weasyl client ID and secret are required for OAuth2"),
+    }
+}
+
+/// Authorize with Weasyl in the browser, receiving the redirect on a local
+/// port, and save the resulting tokens.
+pub async fn authorize_weasyl(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    profile: &config::Profile,
+    port: u16,
+    scope: &str,
+) -> anyhow::Result<()> {
+    let (client_id, client_secret) = weasyl_client(profile)?;
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+        .await
+        .with_context(|| format!("Could not listen on port {}", port))?;
+    let redirect_uri = format!("http://127.0.0.1:{}/callback", port);
+    let state = uuid::Uuid::new_v4().to_string();
+
+    let url = reqwest::Url::parse_with_params(
+        WEASYL_AUTHORIZE_URL,
+        &[
+            ("response_type", "code"),
+            ("client_id", client_id),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("scope", scope),
+            ("state", state.as_str()),
+        ],
+    )?;
+
+    println!(
+        "Open this page to authorize batch-tagger on Weasyl:\n{}",
+        url
+    );
+    if let Err(err) = crate::open_url(url.as_str()) {
+        tracing::debug!("Could not open browser: {:?}", err);
+    }
+
+    let code = receive_code(listener, &state).await?;
+
+    let tokens: TokenResponse = reqwest::Client::default()
+        .post(WEASYL_TOKEN_URL)
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("client_id", client_id),
+            ("client_secret", client_secret),
+        ])
+        .send()
+        .await
+        .context("Could not make request for tokens")?
+        .error_for_status()
+        .context("Weasyl did not issue tokens")?
+        .json()
+        .await
+        .context("Could not decode tokens")?;
+
+    store_credentials(
+        pool,
+        account,
+        SubmissionSite::Weasyl,
+        &tokens.into_credentials(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Wait for the browser to be redirected back with an authorization code,
+/// ignoring unrelated requests such as for a favicon.
+async fn receive_code(listener: tokio::net::TcpListener, state: &str) -> anyhow::Result<String> {
+    loop {
+        let (stream, _addr) = listener.accept().await?;
+        let mut stream = tokio::io::BufReader::new(stream);
+
+        let mut request_line = String::new();
+        stream.read_line(&mut request_line).await?;
+        let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+        let params: HashMap<String, String> =
+            reqwest::Url::parse(&format!("http://127.0.0.1{}", path))?
+                .query_pairs()
+                .into_owned()
+                .collect();
+
+        let result = match (params.get("code"), params.get("error")) {
+            (_, Some(error)) => Err(anyhow::anyhow!("Authorization was denied: {}", error)),
+            (Some(_), _) if params.get("state").map(String::as_str) != Some(state) => {
+                Err(anyhow::anyhow!("Authorization state did not match"))
+            }
+            (Some(code), _) => Ok(code.clone()),
+            (None, None) => {
+                respond(stream.get_mut(), "404 Not Found", "Not found").await?;
+                continue;
+            }
+        };
+
+        let message = match &result {
+            Ok(_) => "Authorized, this page may be closed.",
+            Err(_) => "Authorization failed, check the terminal for details.",
+        };
+        respond(stream.get_mut(), "200 OK", message).await?;
+
+        return result;
+    }
+}
+
+async fn respond(
+    stream: &mut tokio::net::TcpStream,
+    status: &str,
+    body: &str,
+) -> anyhow::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+
+    Ok(())
+}
+
+/// Get a saved Weasyl access token, refreshing it first if it is about to
+/// expire.
+pub async fn weasyl_token(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    profile: &config::Profile,
+) -> anyhow::Result<Option<String>> {
+    let credentials = load_credentials(pool, account, SubmissionSite::Weasyl).await?;
+    let access_token = match credentials.get("access_token") {
+        Some(access_token) => access_token,
+        None => return Ok(None),
+    };
+
+    let expiring = credentials
+        .get("expires_at")
+        .and_then(|expires_at| chrono::DateTime::parse_from_rfc3339(expires_at).ok())
+        .is_some_and(|expires_at| {
+            expires_at.with_timezone(&chrono::Utc)
+                < chrono::Utc::now() + chrono::Duration::minutes(REFRESH_MARGIN_MINUTES)
+        });
+    let refresh_token = match credentials.get("refresh_token") {
+        Some(refresh_token) if expiring => refresh_token,
+        _ => return Ok(Some(access_token.clone())),
+    };
+
+    tracing::debug!("Refreshing Weasyl access token");
+    let (client_id, client_secret) = weasyl_client(profile)?;
+
+    let tokens: TokenResponse = reqwest::Client::default()
+        .post(WEASYL_TOKEN_URL)
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+            ("client_id", client_id),
+            ("client_secret", client_secret),
+        ])
+        .send()
+        .await
+        .context("Could not make request to refresh token")?
+        .error_for_status()
+        .context("Weasyl refused to refresh token, run auth weasyl again")?
+        .json()
+        .await
+        .context("Could not decode refreshed token")?;
+
+    let access_token = tokens.access_token.clone();
+    store_credentials(
+        pool,
+        account,
+        SubmissionSite::Weasyl,
+        &tokens.into_credentials(),
+    )
+    .await?;
+
+    Ok(Some(access_token))
+}
+
+/// Log in to FurAffinity with a username and password, asking for a
+/// two-factor code if the account needs one, and save the session cookies.
+/// Returns the new `a` and `b` cookies.
+pub async fn login_furaffinity(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    username: &str,
+    password: &str,
+    totp_code: Option<String>,
+) -> anyhow::Result<(String, String)> {
+    let jar = std::sync::Arc::new(reqwest::cookie::Jar::default());
+    let client = reqwest::Client::builder()
+        .cookie_provider(jar.clone())
+        .build()?;
+    let login_url = reqwest::Url::parse(FURAFFINITY_LOGIN_URL)?;
+
+    // Loading the page first gets any cookies the login form expects.
+    let page = client
+        .get(login_url.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let mut form = LoginForm::find(&page, &login_url, "pass")
+        .context("Could not find FurAffinity login form")?;
+    form.set("name", username);
+    form.set("pass", password);
+
+    let mut page = form.submit(&client).await?;
+
+    if furaffinity_cookies(&jar).is_none() {
+        if let Some(mut form) = LoginForm::find(&page, &login_url, "code") {
+            let code = match totp_code {
+                Some(code) => code,
+                None => crate::prompt("Two-factor code", false)?,
+            };
+            form.set("code", &code);
+
+            page = form.submit(&client).await?;
+        }
+    }
+
+    let (cookie_a, cookie_b) = match furaffinity_cookies(&jar) {
+        Some(cookies) => cookies,
+        None if page.to_lowercase().contains("captcha") => anyhow::bail!(
+            "FurAffinity asked for a captcha, log in with a browser and use its cookies instead"
+        ),
+        None => anyhow::bail!("FurAffinity did not accept the login"),
+    };
+
+    let mut credentials = Credentials::new();
+    credentials.insert("cookie_a".to_string(), cookie_a.clone());
+    credentials.insert("cookie_b".to_string(), cookie_b.clone());
+    credentials.insert("username".to_string(), username.to_string());
+    store_credentials(pool, account, SubmissionSite::FurAffinity, &credentials).await?;
+
+    Ok((cookie_a, cookie_b))
+}
+
+/// Get the `a` and `b` cookies of a logged in FurAffinity session.
+fn furaffinity_cookies(jar: &reqwest::cookie::Jar) -> Option<(String, String)> {
+    use reqwest::cookie::CookieStore;
+
+    let url = reqwest::Url::parse(FURAFFINITY_LOGIN_URL).ok()?;
+    let cookies = jar.cookies(&url)?;
+    let cookies: HashMap<&str, &str> = cookies
+        .to_str()
+        .ok()?
+        .split("; ")
+        .filter_map(|cookie| cookie.split_once('='))
+        .collect();
+
+    Some((cookies.get("a")?.to_string(), cookies.get("b")?.to_string()))
+}
+
+/// A form from a login page with the values of its inputs.
+struct LoginForm {
+    action: reqwest::Url,
+    fields: Vec<(String, String)>,
+}
+
+impl LoginForm {
+    /// Find the form containing an input with this name.
+    fn find(page: &str, url: &reqwest::Url, input: &str) -> Option<Self> {
+        let form_selector = scraper::Selector::parse("form").unwrap();
+        let input_selector = scraper::Selector::parse("input[name]").unwrap();
+
+        let document = scraper::Html::parse_document(page);
+        let form = document.select(&form_selector).find(|form| {
+            form.select(&input_selector)
+                .any(|field| field.value().attr("name") == Some(input))
+        })?;
+
+        let action = match form.value().attr("action") {
+            Some(action) => url.join(action).ok()?,
+            None => url.clone(),
+        };
+        let fields = form
+            .select(&input_selector)
+            .filter(|field| {
+                !matches!(
+                    field.value().attr("type"),
+                    Some("submit" | "button" | "checkbox")
+                )
+            })
+            .filter_map(|field| {
+                let name = field.value().attr("name")?;
+                let value = field.value().attr("value").unwrap_or_default();
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+
+        Some(Self { action, fields })
+    }
+
+    fn set(&mut self, name: &str, value: &str) {
+        match self.fields.iter_mut().find(|(field, _)| field == name) {
+            Some((_, field_value)) => *field_value = value.to_string(),
+            None => self.fields.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    async fn submit(&self, client: &reqwest::Client) -> anyhow::Result<String> {
+        let page = client
+            .post(self.action.clone())
+            .form(&self.fields)
+            .send()
+            .await
+            .context("Could not submit login form")?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Ok(page)
+    }
+}
+
+/// Saved FurAffinity session cookies, if `login furaffinity` has been run
+/// for this profile.
+pub async fn furaffinity_session(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+) -> anyhow::Result<Option<(String, String, String)>> {
+    let mut credentials = load_credentials(pool, account, SubmissionSite::FurAffinity).await?;
+
+    Ok(
+        match (
+            credentials.remove("cookie_a"),
+            credentials.remove("cookie_b"),
+            credentials.remove("username"),
+        ) {
+            (Some(cookie_a), Some(cookie_b), Some(username)) => {
+                Some((cookie_a, cookie_b, username))
+            }
+            _ => None,
+        },
+    )
+}
@@ -0,0 +1,207 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::encryption;
+
+/// Every SQLite database file starts with this header.
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Default location for a new backup, in a `backups` directory next to the
+/// database and named after the current time.
+pub fn default_path(db_path: &Path, encrypted: bool) -> PathBuf {
+    let name = db_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("submissions");
+
+    db_path.with_file_name("backups").join(format!(
+        "{}-{}.{}",
+        name,
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        if encrypted { "db.enc" } else { "db.gz" }
+    ))
+}
+
+/// Snapshot the database, including history, into a gzip compressed file.
+/// With a passphrase, the backup is encrypted like the database itself.
+pub async fn backup(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    path: &Path,
+    passphrase: Option<&str>,
+) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).context("Could not create backup directory")?;
+    }
+
+    if let Some(passphrase) = passphrase {
+        let data = encryption::encrypt(passphrase, encryption::dump(pool).await?.as_bytes())?;
+        std::fs::write(path, data).context("Could not write backup file")?;
+
+        tracing::info!(path = %path.display(), "Backed up encrypted database");
+
+        return Ok(());
+    }
+
+    // Let SQLite write a consistent copy first, then compress it.
+    let snapshot = path.with_extension("tmp");
+    let _ = std::fs::remove_file(&snapshot);
+    sqlx::query("VACUUM INTO $1")
+        .bind(snapshot.to_string_lossy().as_ref())
+        .execute(pool)
+        .await
+        .context("Could not snapshot database")?;
+
+    let result = compress(&snapshot, path);
+    let _ = std::fs::remove_file(&snapshot);
+    result?;
+
+    tracing::info!(path = %path.display(), "Backed up database");
+
+    Ok(())
+}
+
+/// Replace the database with the contents of a backup. The database must not
+/// be open. Encrypted databases can only be restored from backups encrypted
+/// with the same passphrase.
+pub fn restore(path: &Path, db_path: &Path, passphrase: Option<&str>) -> anyhow::Result<()> {
+    let data = match passphrase {
+        Some(passphrase) => {
+            let data = std::fs::read(path).context("Could not open backup")?;
+            if !encryption::is_encrypted(path) {
+                anyhow::bail!("{} is not an encrypted database backup", path.display());
+            }
+            // Only checks the passphrase, the encrypted data is used as is.
+            encryption::decrypt(passphrase, &data)?;
+            data
+        }
+        None if encryption::is_encrypted(path) => {
+            anyhow::bail!(
+                "{} is an encrypted backup, give its passphrase with --db-passphrase",
+                path.display()
+            );
+        }
+        None => {
+            let mut data = Vec::new();
+            flate2::read::GzDecoder::new(
+                std::fs::File::open(path).context("Could not open backup")?,
+            )
+            .read_to_end(&mut data)
+            .context("Could not decompress backup")?;
+
+            if !data.starts_with(SQLITE_HEADER) {
+                anyhow::bail!("{} is not a database backup", path.display());
+            }
+            data
+        }
+    };
+
+    // Write next to the database so the final rename can't leave a partial
+    // file in its place.
+    let restored = db_path.with_extension("restore");
+    std::fs::write(&restored, data).context("Could not write restored database")?;
+    std::fs::rename(&restored, db_path).context("Could not replace database")?;
+
+    tracing::info!(path = %path.display(), "Restored database");
+
+    Ok(())
+}
+
+fn compress(input: &Path, output: &Path) -> anyhow::Result<()> {
+    let mut input = std::fs::File::open(input).context("Could not open database snapshot")?;
+    let mut encoder = flate2::write::GzEncoder::new(
+        std::fs::File::create(output).context("Could not create backup file")?,
+        flate2::Compression::default(),
+    );
+
+    std::io::copy(&mut input, &mut encoder).context("Could not compress backup")?;
+    encoder
+        .finish()
+        .and_then(|mut file| file.flush())
+        .context("Could not write backup file")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    #[test]
+    fn test_restore_rejects_other_files() {
+        let dir = std::env::temp_dir().join(format!("batch-tagger-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let backup = dir.join("backup.db.gz");
+        let mut encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&backup).unwrap(),
+            flate2::Compression::default(),
+        );
+        encoder.write_all(b"not a database").unwrap();
+        encoder.finish().unwrap();
+
+        let db_path = dir.join("submissions.db");
+        std::fs::write(&db_path, b"existing").unwrap();
+
+        assert!(super::restore(&backup, &db_path, None).is_err());
+        assert_eq!(std::fs::read(&db_path).unwrap(), b"existing");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_backup() {
+        let dir = std::env::temp_dir().join(format!("batch-tagger-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::Executor::execute(
+            &pool,
+            "CREATE TABLE tag (name TEXT); INSERT INTO tag VALUES ('wolf');",
+        )
+        .await
+        .unwrap();
+
+        let backup = dir.join("backup.db.enc");
+        super::backup(&pool, &backup, Some("hunter2"))
+            .await
+            .unwrap();
+        assert!(crate::encryption::is_encrypted(&backup));
+
+        let db_path = dir.join("submissions.db");
+        std::fs::write(&db_path, b"existing").unwrap();
+
+        assert!(super::restore(&backup, &db_path, None).is_err());
+        assert!(super::restore(&backup, &db_path, Some("hunter3")).is_err());
+        assert_eq!(std::fs::read(&db_path).unwrap(), b"existing");
+
+        super::restore(&backup, &db_path, Some("hunter2")).unwrap();
+        let dump =
+            crate::encryption::decrypt("hunter2", &std::fs::read(&db_path).unwrap()).unwrap();
+        assert!(String::from_utf8(dump).unwrap().contains("'wolf'"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_default_path() {
+        let path = super::default_path(std::path::Path::new("/data/submissions.db"), false);
+        assert_eq!(
+            path.parent().unwrap(),
+            std::path::Path::new("/data/backups")
+        );
+
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("submissions-") && name.ends_with(".db.gz"));
+
+        let path = super::default_path(std::path::Path::new("/data/submissions.db"), true);
+        assert!(path.to_str().unwrap().ends_with(".db.enc"));
+    }
+}
@@ -0,0 +1,96 @@
+use anyhow::Context;
+use regex::Regex;
+
+use crate::{config, normalize_tag};
+
+/// Tags that must never be added to submissions.
+#[derive(Debug, Default)]
+pub struct Blocklist {
+    tags: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Blocklist {
+    pub fn new(config: &config::Blocklist) -> anyhow::Result<Self> {
+        let tags = config.tags.iter().map(|tag| normalize_tag(tag)).collect();
+        let patterns = config
+            .patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .with_context(|| format!("Invalid blocklist pattern: {}", pattern))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { tags, patterns })
+    }
+
+    /// If a tag is blocked, checking the normalized tag against patterns.
+    pub fn is_blocked(&self, tag: &str) -> bool {
+        let tag = normalize_tag(tag);
+
+        self.tags.contains(&tag) || self.patterns.iter().any(|pattern| pattern.is_match(&tag))
+    }
+
+    /// Find blocked tags that are in the new tags but not the old tags.
+    pub fn added<'a>(&self, old: &[String], new: &'a [String]) -> Vec<&'a str> {
+        new.iter()
+            .filter(|tag| !old.contains(tag) && self.is_blocked(tag))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Refuse changes that would add any blocked tags.
+    pub fn check(&self, old: &[String], new: &[String]) -> anyhow::Result<()> {
+        let blocked = self.added(old, new);
+
+        if !blocked.is_empty() {
+            anyhow::bail!("changes would add blocked tags: {}", blocked.join(", "));
+        }
+
+        Ok(())
+    }
+
+    /// Remove any blocked tags that would be added by changes.
+    pub fn strip(&self, old: &[String], new: Vec<String>) -> Vec<String> {
+        new.into_iter()
+            .filter(|tag| old.contains(tag) || !self.is_blocked(tag))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Blocklist;
+    use crate::config;
+
+    #[test]
+    fn test_blocklist() {
+        let blocklist = Blocklist::new(&config::Blocklist {
+            tags: vec!["Bad Tag".to_string()],
+            patterns: vec!["^nsfw_".to_string()],
+        })
+        .unwrap();
+
+        let old = vec!["nsfw_old".to_string(), "wolf".to_string()];
+        let new = vec![
+            "nsfw_old".to_string(),
+            "wolf".to_string(),
+            "bad_tag".to_string(),
+            "nsfw_new".to_string(),
+            "fox".to_string(),
+        ];
+
+        assert_eq!(blocklist.added(&old, &new), vec!["bad_tag", "nsfw_new"]);
+        assert!(blocklist.check(&old, &new).is_err());
+        assert!(blocklist.check(&old, &old).is_ok());
+        assert_eq!(
+            blocklist.strip(&old, new),
+            vec![
+                "nsfw_old".to_string(),
+                "wolf".to_string(),
+                "fox".to_string()
+            ]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use futures::TryStreamExt;
+
+use crate::{config::AddedCasing, normalize_tag};
+
+/// Which casing to use for tags, keyed by their normalized form.
+#[derive(Debug, Default)]
+pub struct TagCasing {
+    /// Casing set with the `tag-casing` command, enforced on every change.
+    preferred: HashMap<String, String>,
+    /// Casing a tag was first stored with, used for newly added tags.
+    first_seen: HashMap<String, String>,
+    /// If casing already on a submission or first stored wins over the
+    /// casing of new tags.
+    added_casing: AddedCasing,
+}
+
+impl TagCasing {
+    pub async fn load(
+        pool: &sqlx::Pool<sqlx::Sqlite>,
+        account: &str,
+        added_casing: AddedCasing,
+    ) -> anyhow::Result<Self> {
+        let mut casing = Self {
+            added_casing,
+            ..Default::default()
+        };
+
+        let mut rows = sqlx::query!(
+            "SELECT normalized, name FROM tag_casing WHERE account = $1",
+            account
+        )
+        .fetch(pool);
+        while let Some(row) = rows.try_next().await? {
+            casing.preferred.insert(row.normalized, row.name);
+        }
+        drop(rows);
+
+        let mut rows = sqlx::query!("SELECT normalized, name FROM tag ORDER BY id").fetch(pool);
+        while let Some(row) = rows.try_next().await? {
+            casing.first_seen.entry(row.normalized).or_insert(row.name);
+        }
+
+        Ok(casing)
+    }
+
+    /// Adjust the casing of a submission's new tags. Preferred casing always
+    /// wins, then the casing already on the submission, then the casing the
+    /// tag was first seen with, unless the casing of new tags was requested
+    /// to win. Tags differing only by casing are merged.
+    pub fn apply(&self, old: &[String], new: Vec<String>) -> Vec<String> {
+        let mut seen = Vec::with_capacity(new.len());
+        let mut tags = Vec::with_capacity(new.len());
+
+        for tag in new {
+            let normalized = normalize_tag(&tag);
+            if seen.contains(&normalized) {
+                continue;
+            }
+
+            let tag = match self.preferred.get(&normalized) {
+                Some(preferred) => preferred.clone(),
+                None if self.added_casing == AddedCasing::Requested => tag,
+                None => old
+                    .iter()
+                    .find(|old| normalize_tag(old) == normalized)
+                    .or_else(|| self.first_seen.get(&normalized))
+                    .cloned()
+                    .unwrap_or(tag),
+            };
+
+            seen.push(normalized);
+            tags.push(tag);
+        }
+
+        tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TagCasing;
+    use crate::config::AddedCasing;
+
+    #[test]
+    fn test_apply_casing() {
+        let mut casing = TagCasing::default();
+        casing
+            .preferred
+            .insert("oc:rex".to_string(), "OC:Rex".to_string());
+        casing
+            .first_seen
+            .insert("blue_fur".to_string(), "Blue_Fur".to_string());
+
+        let old = vec!["Wolf".to_string(), "oc:rex".to_string()];
+        let new = vec![
+            "wolf".to_string(),
+            "oc:rex".to_string(),
+            "blue_fur".to_string(),
+            "sketch".to_string(),
+            "Sketch".to_string(),
+        ];
+
+        assert_eq!(
+            casing.apply(&old, new.clone()),
+            vec!["Wolf", "OC:Rex", "Blue_Fur", "sketch"]
+        );
+
+        casing.added_casing = AddedCasing::Requested;
+        assert_eq!(
+            casing.apply(&old, new),
+            vec!["wolf", "OC:Rex", "blue_fur", "sketch"]
+        );
+    }
+}
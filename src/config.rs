@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+
+/// Name of the profile used when none is specified.
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct Config {
+    /// Named account profiles.
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+    /// Tags that must never be applied, shared by all profiles.
+    #[serde(default)]
+    pub blocklist: Blocklist,
+    /// Policies checked by the lint command.
+    #[serde(default)]
+    pub lint: Lint,
+    /// Named groups of tags, used in changes as `@name`.
+    #[serde(default)]
+    pub bundles: Bundles,
+    /// Order tags are sent to sites in.
+    #[serde(default)]
+    pub sort: TagSort,
+    /// Sites handled by external programs, shared by all profiles.
+    #[serde(default, rename = "external_site")]
+    pub external_sites: Vec<ExternalSite>,
+    /// File of tags that mean the same thing in different languages, so
+    /// searching for one also matches the others.
+    pub translations: Option<String>,
+}
+
+/// Tags for each bundle name.
+pub type Bundles = HashMap<String, Vec<String>>;
+
+/// Credentials and usernames for one set of site accounts.
+///
+/// Any site missing its credentials is skipped for this profile.
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+pub struct Profile {
+    pub weasyl_api_key: Option<String>,
+    pub weasyl_user: Option<String>,
+    /// OAuth2 access token, used instead of an API key.
+    pub weasyl_token: Option<String>,
+    /// Application registered with Weasyl for OAuth2.
+    pub weasyl_client_id: Option<String>,
+    pub weasyl_client_secret: Option<String>,
+
+    pub furaffinity_cookie_a: Option<String>,
+    pub furaffinity_cookie_b: Option<String>,
+    pub furaffinity_user: Option<String>,
+
+    pub derpibooru_url: Option<String>,
+    pub derpibooru_api_key: Option<String>,
+    pub derpibooru_session: Option<String>,
+
+    pub mastodon_instance: Option<String>,
+    pub mastodon_token: Option<String>,
+
+    pub twitter_bearer_token: Option<String>,
+    pub twitter_user: Option<String>,
+
+    pub local_folder: Option<String>,
+
+    pub fuzzysearch_api_key: Option<String>,
+
+    pub szurubooru_url: Option<String>,
+    pub szurubooru_user: Option<String>,
+    pub szurubooru_token: Option<String>,
+}
+
+/// Tags that must never be applied to submissions.
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct Blocklist {
+    /// Exact tags, compared after normalizing.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Regular expressions matched against normalized tags.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+/// Policies every submission should follow.
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct Lint {
+    /// Minimum number of tags on each submission.
+    pub min_tags: Option<usize>,
+    /// Tags required on submissions matching a search.
+    #[serde(default)]
+    pub require: Vec<LintRequire>,
+    /// Groups of tags that may not be used together.
+    #[serde(default)]
+    pub conflict: Vec<LintConflict>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct LintRequire {
+    pub search: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct LintConflict {
+    pub tags: Vec<String>,
+}
+
+/// A site handled by an external program.
+#[derive(Debug, serde::Deserialize)]
+pub struct ExternalSite {
+    /// Name the site is stored and displayed as.
+    pub name: String,
+    /// Program to run for each request.
+    pub command: String,
+    /// Arguments given to the program.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Only load submissions, never send tag changes.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// How tags are ordered and cased when they are changed.
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+pub struct TagSort {
+    #[serde(default)]
+    pub order: SortOrder,
+    /// Category prefixes, such as `oc` for `oc:rex`, listed first in this
+    /// order when sorting by category.
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Where tags added by a change are placed among the existing tags.
+    #[serde(default)]
+    pub additions: AddPosition,
+    /// Casing kept when adding a tag that is already present with different
+    /// casing.
+    #[serde(default)]
+    pub added_casing: AddedCasing,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddPosition {
+    /// After every existing tag.
+    #[default]
+    End,
+    /// Before every existing tag.
+    Start,
+    /// Where the first tag removed by the same change was, or at the end if
+    /// nothing was removed.
+    Replace,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddedCasing {
+    /// Keep the casing the tag already has, or the casing it was first stored
+    /// with.
+    #[default]
+    Existing,
+    /// Use the casing given in the change, replacing any existing casing.
+    Requested,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Keep tags in the order they were added.
+    #[default]
+    None,
+    /// Sort tags alphabetically.
+    Alphabetical,
+    /// Group tags by their category prefix, then sort alphabetically.
+    Category,
+}
+
+impl Config {
+    /// Load configuration from a TOML file, using an empty configuration if
+    /// the file does not exist.
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let data = match std::fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                tracing::debug!(path, "Config file did not exist, using defaults");
+                return Ok(Self::default());
+            }
+            Err(err) => return Err(err).context("Could not read config file"),
+        };
+
+        toml::from_str(&data).context("Could not parse config file")
+    }
+
+    /// Get a profile by name. The default profile is allowed to be missing
+    /// from the configuration so everything may be provided as arguments.
+    pub fn profile(&self, name: &str) -> anyhow::Result<Profile> {
+        match self.profiles.get(name) {
+            Some(profile) => Ok(profile.clone()),
+            None if name == DEFAULT_PROFILE => Ok(Profile::default()),
+            None => anyhow::bail!("unknown profile: {}", name),
+        }
+    }
+}
+
+impl Profile {
+    /// Replace values in this profile with any provided overrides.
+    pub fn merge(self, other: Profile) -> Self {
+        Self {
+            weasyl_api_key: other.weasyl_api_key.or(self.weasyl_api_key),
+            weasyl_user: other.weasyl_user.or(self.weasyl_user),
+            weasyl_token: other.weasyl_token.or(self.weasyl_token),
+            weasyl_client_id: other.weasyl_client_id.or(self.weasyl_client_id),
+            weasyl_client_secret: other.weasyl_client_secret.or(self.weasyl_client_secret),
+            furaffinity_cookie_a: other.furaffinity_cookie_a.or(self.furaffinity_cookie_a),
+            furaffinity_cookie_b: other.furaffinity_cookie_b.or(self.furaffinity_cookie_b),
+            furaffinity_user: other.furaffinity_user.or(self.furaffinity_user),
+            derpibooru_url: other.derpibooru_url.or(self.derpibooru_url),
+            derpibooru_api_key: other.derpibooru_api_key.or(self.derpibooru_api_key),
+            derpibooru_session: other.derpibooru_session.or(self.derpibooru_session),
+            mastodon_instance: other.mastodon_instance.or(self.mastodon_instance),
+            mastodon_token: other.mastodon_token.or(self.mastodon_token),
+            twitter_bearer_token: other.twitter_bearer_token.or(self.twitter_bearer_token),
+            twitter_user: other.twitter_user.or(self.twitter_user),
+            local_folder: other.local_folder.or(self.local_folder),
+            fuzzysearch_api_key: other.fuzzysearch_api_key.or(self.fuzzysearch_api_key),
+            szurubooru_url: other.szurubooru_url.or(self.szurubooru_url),
+            szurubooru_user: other.szurubooru_user.or(self.szurubooru_user),
+            szurubooru_token: other.szurubooru_token.or(self.szurubooru_token),
+        }
+    }
+}
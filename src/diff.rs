@@ -0,0 +1,176 @@
+use std::io::IsTerminal;
+
+use crossterm::style::Stylize;
+
+/// How tag changes are displayed before being applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ArgEnum)]
+pub enum DiffFormat {
+    /// One line per submission with only added and removed tags.
+    Compact,
+    /// Every tag on its own line, including unchanged tags.
+    Full,
+    /// One JSON object per submission.
+    Json,
+}
+
+/// Displays tag changes and keeps totals for a summary.
+pub struct DiffRenderer {
+    format: DiffFormat,
+    color: bool,
+
+    submissions: usize,
+    added: usize,
+    removed: usize,
+}
+
+impl DiffRenderer {
+    pub fn new(format: DiffFormat) -> Self {
+        Self {
+            format,
+            color: std::io::stdout().is_terminal(),
+
+            submissions: 0,
+            added: 0,
+            removed: 0,
+        }
+    }
+
+    /// Print the changes for a single submission.
+    pub fn show(&mut self, site: &str, id: i64, old: &[String], new: &[String]) {
+        print!("{}", self.render(site, id, old, new));
+    }
+
+    /// Print totals for every submission shown.
+    pub fn summary(&self) {
+        if self.format == DiffFormat::Json {
+            println!(
+                "{}",
+                serde_json::json!({
+                    "submissions": self.submissions,
+                    "added": self.added,
+                    "removed": self.removed,
+                })
+            );
+        } else {
+            println!(
+                "{} submissions, {} tag additions, {} removals",
+                self.submissions, self.added, self.removed
+            );
+        }
+    }
+
+    fn render(&mut self, site: &str, id: i64, old: &[String], new: &[String]) -> String {
+        let added: Vec<_> = new.iter().filter(|tag| !old.contains(tag)).collect();
+        let removed: Vec<_> = old.iter().filter(|tag| !new.contains(tag)).collect();
+
+        self.submissions += 1;
+        self.added += added.len();
+        self.removed += removed.len();
+
+        match self.format {
+            DiffFormat::Compact => {
+                let changes: Vec<_> = added
+                    .iter()
+                    .map(|tag| self.added_tag(&format!("+{}", tag)))
+                    .chain(
+                        removed
+                            .iter()
+                            .map(|tag| self.removed_tag(&format!("-{}", tag))),
+                    )
+                    .collect();
+
+                format!("{}-{}: {}\n", site, id, changes.join(" "))
+            }
+            DiffFormat::Full => {
+                let mut out = format!("{}-{}\n", site, id);
+
+                for tag in old.iter().filter(|tag| new.contains(tag)) {
+                    out.push_str(&format!("  {}\n", tag));
+                }
+                for tag in removed {
+                    out.push_str(&self.removed_tag(&format!("- {}", tag)));
+                    out.push('\n');
+                }
+                for tag in added {
+                    out.push_str(&self.added_tag(&format!("+ {}", tag)));
+                    out.push('\n');
+                }
+
+                out
+            }
+            DiffFormat::Json => {
+                let line = serde_json::json!({
+                    "site": site,
+                    "id": id,
+                    "added": added,
+                    "removed": removed,
+                    "tags": new,
+                });
+
+                format!("{}\n", line)
+            }
+        }
+    }
+
+    fn added_tag(&self, text: &str) -> String {
+        if self.color {
+            text.green().to_string()
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn removed_tag(&self, text: &str) -> String {
+        if self.color {
+            text.red().to_string()
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiffFormat, DiffRenderer};
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_render() {
+        let old = tags(&["fox", "wolf", "sketch"]);
+        let new = tags(&["fox", "wolf", "digital"]);
+
+        let mut diff = DiffRenderer::new(DiffFormat::Compact);
+        diff.color = false;
+        assert_eq!(
+            diff.render("Weasyl", 1, &old, &new),
+            "Weasyl-1: +digital -sketch\n"
+        );
+
+        diff.format = DiffFormat::Full;
+        assert_eq!(
+            diff.render("Weasyl", 1, &old, &new),
+            "Weasyl-1\n  fox\n  wolf\n- sketch\n+ digital\n"
+        );
+
+        diff.format = DiffFormat::Json;
+        let line: serde_json::Value =
+            serde_json::from_str(&diff.render("Weasyl", 1, &old, &new)).unwrap();
+        assert_eq!(
+            line,
+            serde_json::json!({
+                "site": "Weasyl",
+                "id": 1,
+                "added": ["digital"],
+                "removed": ["sketch"],
+                "tags": ["fox", "wolf", "digital"],
+            })
+        );
+
+        assert_eq!(diff.submissions, 3);
+        assert_eq!(diff.added, 3);
+        assert_eq!(diff.removed, 3);
+    }
+}
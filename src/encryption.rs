@@ -0,0 +1,295 @@
+use std::{
+    io::{Read, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use sqlx::{Connection, Executor};
+
+/// Every encrypted database file starts with this header.
+const HEADER: &[u8] = b"batch-tagger encrypted database\0";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// If the file at this path is an encrypted database.
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut header = [0; HEADER.len()];
+
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map(|_| header == HEADER)
+        .unwrap_or(false)
+}
+
+/// Load an encrypted database file into a pool, which should be in memory so
+/// the decrypted contents never reach the disk.
+pub async fn load_file(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    path: &Path,
+    passphrase: &str,
+) -> anyhow::Result<()> {
+    let data = std::fs::read(path).context("Could not read encrypted database")?;
+    let dump = decrypt(passphrase, &data)?;
+    let dump = String::from_utf8(dump).context("Encrypted database was not valid")?;
+
+    load(pool, &dump).await?;
+    tracing::debug!(path = %path.display(), "Loaded encrypted database");
+
+    Ok(())
+}
+
+/// Encrypt the contents of a pool and replace the file at this path.
+pub async fn save_file(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    path: &Path,
+    passphrase: &str,
+) -> anyhow::Result<()> {
+    let data = encrypt(passphrase, dump(pool).await?.as_bytes())?;
+
+    // Write next to the database so the final rename can't leave a partial
+    // file in its place.
+    let saving = path.with_extension("saving");
+    std::fs::write(&saving, data).context("Could not write encrypted database")?;
+    std::fs::rename(&saving, path).context("Could not replace encrypted database")?;
+    tracing::debug!(path = %path.display(), "Saved encrypted database");
+
+    Ok(())
+}
+
+/// Write every table, row, index, and trigger as SQL statements that recreate
+/// the database, like the `.dump` command of the SQLite shell.
+pub async fn dump(pool: &sqlx::Pool<sqlx::Sqlite>) -> anyhow::Result<String> {
+    let mut conn = pool.acquire().await?;
+
+    let objects: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT type, name, sql FROM sqlite_master
+        WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%'
+        ORDER BY type <> 'table', rowid",
+    )
+    .fetch_all(&mut conn)
+    .await
+    .context("Could not list database schema")?;
+
+    let mut dump = String::new();
+
+    for (_, _, sql) in objects.iter().filter(|(kind, _, _)| kind == "table") {
+        dump.push_str(sql);
+        dump.push_str(";\n");
+    }
+
+    // AUTOINCREMENT counters are kept in a table SQLite creates by itself.
+    let tables: Vec<(String,)> = sqlx::query_as(
+        "SELECT name FROM sqlite_master
+        WHERE type = 'table' AND (name NOT LIKE 'sqlite_%' OR name = 'sqlite_sequence')
+        ORDER BY rowid",
+    )
+    .fetch_all(&mut conn)
+    .await?;
+
+    for (table,) in tables {
+        let columns: Vec<(String,)> = sqlx::query_as("SELECT name FROM pragma_table_info($1)")
+            .bind(&table)
+            .fetch_all(&mut conn)
+            .await?;
+
+        let values = columns
+            .iter()
+            .map(|(column,)| format!("quote({})", quote_identifier(column)))
+            .collect::<Vec<_>>()
+            .join(" || ',' || ");
+        let insert = format!("INSERT INTO {} VALUES(", quote_identifier(&table));
+
+        // Inserting rows with their IDs already updates the counters.
+        if table == "sqlite_sequence" {
+            dump.push_str("DELETE FROM sqlite_sequence;\n");
+        }
+
+        let rows: Vec<(String,)> = sqlx::query_as(&format!(
+            "SELECT {} || {} || ')' FROM {}",
+            quote_literal(&insert),
+            values,
+            quote_identifier(&table)
+        ))
+        .fetch_all(&mut conn)
+        .await
+        .with_context(|| format!("Could not read table {}", table))?;
+
+        for (row,) in rows {
+            dump.push_str(&row);
+            dump.push_str(";\n");
+        }
+    }
+
+    for (_, _, sql) in objects.iter().filter(|(kind, _, _)| kind != "table") {
+        dump.push_str(sql);
+        dump.push_str(";\n");
+    }
+
+    // Tracks which tag normalization rows were written with, so losing it
+    // would rewrite every tag again on the next start.
+    let (user_version,): (i64,) = sqlx::query_as("PRAGMA user_version")
+        .fetch_one(&mut conn)
+        .await?;
+    dump.push_str(&format!("PRAGMA user_version = {};\n", user_version));
+
+    Ok(dump)
+}
+
+/// Recreate a database from the output of [`dump`], including its
+/// `user_version`. The pool should be empty.
+pub async fn load(pool: &sqlx::Pool<sqlx::Sqlite>, dump: &str) -> anyhow::Result<()> {
+    let mut conn = pool.acquire().await?;
+
+    // Rows are inserted table by table, so references may not exist yet.
+    conn.execute("PRAGMA foreign_keys = OFF").await?;
+
+    let mut tx = conn.begin().await?;
+    tx.execute(dump).await.context("Could not load database")?;
+    tx.commit().await?;
+
+    conn.execute("PRAGMA foreign_keys = ON").await?;
+
+    Ok(())
+}
+
+/// Compress and encrypt data with a key derived from the passphrase.
+pub fn encrypt(passphrase: &str, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    let compressed = encoder.finish()?;
+
+    let ciphertext = cipher(passphrase, &salt)?
+        .encrypt(&nonce, compressed.as_slice())
+        .map_err(|_| anyhow::anyhow!("Could not encrypt database"))?;
+
+    let mut output = Vec::with_capacity(HEADER.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    output.extend_from_slice(HEADER);
+    output.extend_from_slice(&salt);
+    output.extend_from_slice(&nonce);
+    output.extend_from_slice(&ciphertext);
+
+    Ok(output)
+}
+
+/// Decrypt and decompress data written by [`encrypt`].
+pub fn decrypt(passphrase: &str, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let data = data
+        .strip_prefix(HEADER)
+        .context("Database is not encrypted")?;
+    if data.len() < SALT_LEN + NONCE_LEN {
+        anyhow::bail!("Encrypted database is truncated");
+    }
+
+    let (salt, data) = data.split_at(SALT_LEN);
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+
+    let compressed = cipher(passphrase, salt)?
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase or damaged encrypted database"))?;
+
+    let mut data = Vec::new();
+    flate2::read::GzDecoder::new(compressed.as_slice())
+        .read_to_end(&mut data)
+        .context("Could not decompress database")?;
+
+    Ok(data)
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> anyhow::Result<XChaCha20Poly1305> {
+    let mut key = [0; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| anyhow::anyhow!("Could not derive key from passphrase: {}", err))?;
+
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_round_trip() {
+        let data = b"CREATE TABLE submission (id INTEGER);".repeat(10);
+
+        let encrypted = encrypt("hunter2", &data).unwrap();
+        assert!(encrypted.starts_with(HEADER));
+        assert!(!encrypted
+            .windows(b"CREATE TABLE".len())
+            .any(|window| window == b"CREATE TABLE"));
+
+        assert_eq!(decrypt("hunter2", &encrypted).unwrap(), data);
+        assert!(decrypt("hunter3", &encrypted).is_err());
+        assert!(decrypt("hunter2", &encrypted[..40]).is_err());
+        assert!(decrypt("hunter2", &data).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dump_round_trip() {
+        let open = || async {
+            sqlx::sqlite::SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await
+                .unwrap()
+        };
+
+        let pool = open().await;
+        pool.execute(
+            "CREATE TABLE tag (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT, data BLOB);
+            CREATE INDEX tag_name ON tag (name);
+            INSERT INTO tag (name, data) VALUES ('it''s', X'00FF'), (NULL, NULL);
+            PRAGMA user_version = 7;",
+        )
+        .await
+        .unwrap();
+
+        let dump = dump(&pool).await.unwrap();
+
+        let restored = open().await;
+        load(&restored, &dump).await.unwrap();
+
+        let rows: Vec<(i64, Option<String>, Option<Vec<u8>>)> =
+            sqlx::query_as("SELECT id, name, data FROM tag ORDER BY id")
+                .fetch_all(&restored)
+                .await
+                .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (1, Some("it's".to_string()), Some(vec![0, 255])),
+                (2, None, None)
+            ]
+        );
+
+        let (seq,): (i64,) = sqlx::query_as("SELECT seq FROM sqlite_sequence WHERE name = 'tag'")
+            .fetch_one(&restored)
+            .await
+            .unwrap();
+        assert_eq!(seq, 2);
+
+        let (user_version,): (i64,) = sqlx::query_as("PRAGMA user_version")
+            .fetch_one(&restored)
+            .await
+            .unwrap();
+        assert_eq!(user_version, 7);
+
+        assert_eq!(super::dump(&restored).await.unwrap(), dump);
+    }
+}
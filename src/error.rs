@@ -0,0 +1,191 @@
+use std::{fmt::Display, time::Duration};
+
+use crate::sites::SubmissionSite;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors from sites and the database, with enough structure for callers to
+/// decide whether to retry, ask for new credentials, or give up.
+#[derive(Debug)]
+pub enum Error {
+    /// Credentials were missing, invalid, or expired.
+    Auth {
+        site: SubmissionSite,
+        reason: String,
+    },
+    /// The site is refusing requests until some time has passed.
+    RateLimited {
+        site: SubmissionSite,
+        retry_after: Option<Duration>,
+    },
+    /// A page from a site no longer had an expected element, usually because
+    /// the site's markup changed and the scraper needs to be updated.
+    ParseFailure {
+        site: SubmissionSite,
+        selector: &'static str,
+    },
+    /// The requested submission or page does not exist.
+    NotFound { site: SubmissionSite },
+    /// The account is logged in, but its settings hide the submission. Logging
+    /// in again won't help, the settings need to be changed on the site.
+    ContentFiltered { site: SubmissionSite },
+    /// A site returned a page that could not be used, such as during
+    /// maintenance or when requests are being challenged. Operations should
+    /// abort without changing any stored data when this occurs.
+    Unavailable {
+        site: SubmissionSite,
+        reason: &'static str,
+    },
+    /// A request could not be made, or returned an unexpected status.
+    Network(reqwest::Error),
+    /// Stored data could not be read or written.
+    Database(sqlx::Error),
+    /// Anything else, described by its message.
+    Other(anyhow::Error),
+}
+
+impl Error {
+    /// The kind of error for an unsuccessful response status, if there is a
+    /// more specific one than a network error.
+    pub fn from_status(
+        site: SubmissionSite,
+        status: reqwest::StatusCode,
+        headers: &reqwest::header::HeaderMap,
+    ) -> Option<Error> {
+        match status {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                Some(Error::Auth {
+                    site,
+                    reason: format!("got status {}", status),
+                })
+            }
+            reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::GONE => {
+                Some(Error::NotFound { site })
+            }
+            reqwest::StatusCode::TOO_MANY_REQUESTS => Some(Error::RateLimited {
+                site,
+                retry_after: headers
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse().ok())
+                    .map(Duration::from_secs),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Turn error statuses into the matching kind of [`Error`].
+pub trait CheckStatus: Sized {
+    fn check_status(self, site: SubmissionSite) -> Result<Self>;
+}
+
+impl CheckStatus for reqwest::Response {
+    fn check_status(self, site: SubmissionSite) -> Result<Self> {
+        match Error::from_status(site, self.status(), self.headers()) {
+            Some(err) => Err(err),
+            None => Ok(self.error_for_status()?),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Auth { site, reason } => write!(f, "{} rejected credentials: {}", site, reason),
+            Error::RateLimited {
+                site,
+                retry_after: Some(retry_after),
+            } => write!(
+                f,
+                "{} is rate limiting requests, retry after {} seconds",
+                site,
+                retry_after.as_secs()
+            ),
+            Error::RateLimited { site, .. } => write!(f, "{} is rate limiting requests", site),
+            Error::ParseFailure { site, selector } => write!(
+                f,
+                "{} markup changed, nothing matched selector {}",
+                site, selector
+            ),
+            Error::NotFound { site } => write!(f, "{} could not find the submission", site),
+            Error::ContentFiltered { site } => write!(
+                f,
+                "{} account hides mature or adult submissions, allow them in the account settings",
+                site
+            ),
+            Error::Unavailable { site, reason } => write!(f, "{} is unavailable: {}", site, reason),
+            Error::Network(err) => write!(f, "request failed: {}", err),
+            Error::Database(err) => write!(f, "database error: {}", err),
+            Error::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Network(err) => Some(err),
+            Error::Database(err) => Some(err),
+            Error::Other(err) => err.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Network(err)
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(err: sqlx::Error) -> Self {
+        Error::Database(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Other(err.into())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Other(err.into())
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    /// Keep the kind of errors that passed through code using [`anyhow`].
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<Error>() {
+            Ok(err) => err,
+            Err(err) => Error::Other(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anyhow_round_trip() {
+        let err: anyhow::Error = Error::NotFound {
+            site: SubmissionSite::Weasyl,
+        }
+        .into();
+        assert!(matches!(
+            Error::from(err),
+            Error::NotFound {
+                site: SubmissionSite::Weasyl
+            }
+        ));
+
+        let err = Error::from(anyhow::anyhow!("something else"));
+        assert!(matches!(err, Error::Other(_)));
+        assert_eq!(err.to_string(), "something else");
+    }
+}
@@ -0,0 +1,172 @@
+use std::io::Write;
+
+use anyhow::Context;
+
+use crate::sites::Submission;
+
+/// Every field that can be exported.
+pub const FIELDS: &[&str] = &[
+    "site",
+    "id",
+    "title",
+    "tags",
+    "posted_at",
+    "url",
+    "file_url",
+    "folder",
+    "local_tags",
+    "notes",
+    "deleted_at",
+    "visibility",
+    "site_data",
+];
+
+/// Parse a comma-separated list of field names, ignoring repeated fields.
+pub fn parse_fields(input: &str) -> anyhow::Result<Vec<&'static str>> {
+    let mut fields = Vec::new();
+
+    for name in input
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let field = FIELDS
+            .iter()
+            .find(|field| field.eq_ignore_ascii_case(name))
+            .with_context(|| {
+                format!(
+                    "Unknown export field {}, expected one of {}",
+                    name,
+                    FIELDS.join(", ")
+                )
+            })?;
+
+        if !fields.contains(field) {
+            fields.push(*field);
+        }
+    }
+
+    if fields.is_empty() {
+        anyhow::bail!("At least one field must be exported");
+    }
+
+    Ok(fields)
+}
+
+/// A submission as a JSON object with only the given fields.
+pub fn record(sub: &Submission, fields: &[&str]) -> serde_json::Value {
+    let record = fields
+        .iter()
+        .map(|field| {
+            let value = match *field {
+                "site" => serde_json::json!(sub.site),
+                "id" => serde_json::json!(sub.id),
+                "title" => serde_json::json!(sub.title),
+                "tags" => serde_json::json!(sub.tags),
+                "posted_at" => serde_json::json!(sub.posted_at.to_rfc3339()),
+                "url" => serde_json::json!(sub.url()),
+                "file_url" => serde_json::json!(sub.file_url),
+                "folder" => serde_json::json!(sub.folder_name),
+                "local_tags" => serde_json::json!(sub.local_tags),
+                "notes" => serde_json::json!(sub.notes),
+                "deleted_at" => {
+                    serde_json::json!(sub.deleted_at.map(|deleted_at| deleted_at.to_rfc3339()))
+                }
+                "visibility" => serde_json::json!(sub.visibility),
+                "site_data" => serde_json::json!(sub.site_data),
+                _ => serde_json::Value::Null,
+            };
+
+            (field.to_string(), value)
+        })
+        .collect();
+
+    serde_json::Value::Object(record)
+}
+
+/// Writes records as they are produced, either as a single JSON array or as
+/// one JSON object per line.
+pub struct Writer<W: Write> {
+    out: W,
+    ndjson: bool,
+    written: usize,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W, ndjson: bool) -> Self {
+        Self {
+            out,
+            ndjson,
+            written: 0,
+        }
+    }
+
+    pub fn write(&mut self, record: &serde_json::Value) -> anyhow::Result<()> {
+        if self.ndjson {
+            serde_json::to_writer(&mut self.out, record)?;
+            self.out.write_all(b"\n")?;
+        } else {
+            self.out
+                .write_all(if self.written == 0 { b"[\n" } else { b",\n" })?;
+            serde_json::to_writer(&mut self.out, record)?;
+        }
+
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Close the array if needed and flush everything written, returning how
+    /// many records there were.
+    pub fn finish(mut self) -> anyhow::Result<usize> {
+        if !self.ndjson {
+            self.out
+                .write_all(if self.written == 0 { b"[]\n" } else { b"\n]\n" })?;
+        }
+        self.out.flush().context("Could not write export")?;
+
+        Ok(self.written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_fields, record, Writer};
+    use crate::tests::submission;
+
+    #[test]
+    fn test_parse_fields() {
+        assert_eq!(
+            parse_fields("site, ID,tags,site").unwrap(),
+            vec!["site", "id", "tags"]
+        );
+        assert!(parse_fields("site,rating").is_err());
+        assert!(parse_fields(" , ").is_err());
+    }
+
+    #[test]
+    fn test_writer() {
+        let sub = submission(1, &["wolf", "canine"]);
+        let fields = parse_fields("id,tags").unwrap();
+
+        let mut out = Vec::new();
+        let mut writer = Writer::new(&mut out, true);
+        writer.write(&record(&sub, &fields)).unwrap();
+        writer.write(&record(&sub, &fields)).unwrap();
+        assert_eq!(writer.finish().unwrap(), 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"id\":1,\"tags\":[\"wolf\",\"canine\"]}\n".repeat(2)
+        );
+
+        let mut out = Vec::new();
+        let mut writer = Writer::new(&mut out, false);
+        writer.write(&record(&sub, &fields)).unwrap();
+        writer.finish().unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(parsed[0]["tags"][1], "canine");
+
+        let mut out = Vec::new();
+        Writer::new(&mut out, false).finish().unwrap();
+        assert_eq!(out, b"[]\n");
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::sites::SubmissionSite;
+
+/// A local directory of downloaded submission files.
+pub struct FileCache {
+    client: reqwest::Client,
+    dir: PathBuf,
+}
+
+impl FileCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            client: reqwest::Client::default(),
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Download a submission's file into the cache, returning the path it was
+    /// saved to.
+    pub async fn download(
+        &self,
+        site: SubmissionSite,
+        id: i64,
+        url: &str,
+    ) -> anyhow::Result<PathBuf> {
+        let ext = url
+            .rsplit('/')
+            .next()
+            .and_then(|name| Path::new(name).extension())
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("bin");
+
+        let dir = self.dir.join(site.as_str());
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .context("Could not create cache directory")?;
+        let path = dir.join(format!("{}.{}", id, ext));
+
+        let data = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .context("Could not make request for file")?
+            .error_for_status()
+            .context("Got bad file status code")?
+            .bytes()
+            .await
+            .context("Could not download file")?;
+
+        tokio::fs::write(&path, data)
+            .await
+            .context("Could not write file to cache")?;
+
+        Ok(path)
+    }
+}
+
+/// Calculate the perceptual hash of an image, using the same parameters as
+/// FuzzySearch. Returns `None` if the file was not a supported image.
+pub fn hash_file<P: AsRef<Path>>(path: P) -> Option<i64> {
+    let image = match img_hash::image::open(path) {
+        Ok(image) => image,
+        Err(err) => {
+            tracing::debug!("Could not open file as image: {}", err);
+            return None;
+        }
+    };
+
+    let hasher = img_hash::HasherConfig::with_bytes_type::<[u8; 8]>()
+        .hash_alg(img_hash::HashAlg::Gradient)
+        .hash_size(8, 8)
+        .preproc_dct()
+        .to_hasher();
+
+    let hash = hasher.hash_image(&image);
+    let bytes: [u8; 8] = hash.as_bytes().try_into().ok()?;
+
+    Some(i64::from_be_bytes(bytes))
+}
+
+/// Group the indices of hashes that are within `distance` bits of each other,
+/// returning only groups with more than one item.
+pub fn group_hashes(hashes: &[i64], distance: u32) -> Vec<Vec<usize>> {
+    fn find(parents: &mut [usize], index: usize) -> usize {
+        let mut root = index;
+        while parents[root] != root {
+            root = parents[root];
+        }
+        parents[index] = root;
+        root
+    }
+
+    let mut parents: Vec<usize> = (0..hashes.len()).collect();
+
+    for a in 0..hashes.len() {
+        for b in (a + 1)..hashes.len() {
+            if (hashes[a] ^ hashes[b]).count_ones() <= distance {
+                let (root_a, root_b) = (find(&mut parents, a), find(&mut parents, b));
+                parents[root_b] = root_a;
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for index in 0..hashes.len() {
+        let root = find(&mut parents, index);
+        groups.entry(root).or_default().push(index);
+    }
+
+    let mut groups: Vec<_> = groups
+        .into_values()
+        .filter(|items| items.len() > 1)
+        .collect();
+    groups.sort();
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::group_hashes;
+
+    #[test]
+    fn test_group_hashes() {
+        let hashes = [0b0000, 0b0001, 0b1111_0000, 0b0011, 0b1111_0000];
+
+        assert_eq!(group_hashes(&hashes, 0), vec![vec![2, 4]]);
+        assert_eq!(group_hashes(&hashes, 1), vec![vec![0, 1, 3], vec![2, 4]]);
+    }
+}
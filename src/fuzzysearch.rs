@@ -0,0 +1,122 @@
+use anyhow::Context;
+
+use crate::sites::Submission;
+
+/// Maximum number of hashes FuzzySearch accepts in a single request.
+const MAX_HASHES: usize = 10;
+
+/// A client for looking up images on FuzzySearch.
+pub struct FuzzySearch {
+    client: reqwest::Client,
+    api_key: String,
+}
+
+/// A file on another site that was found with a similar hash.
+#[derive(Debug, serde::Deserialize)]
+pub struct File {
+    pub site: String,
+    pub site_id_str: String,
+    pub url: String,
+    pub distance: Option<u64>,
+    pub searched_hash: i64,
+}
+
+impl FuzzySearch {
+    pub fn new(api_key: String) -> Self {
+        Self {
+            client: reqwest::Client::default(),
+            api_key,
+        }
+    }
+
+    /// Look up files within `distance` bits of any of the given hashes.
+    pub async fn lookup_hashes(&self, hashes: &[i64], distance: u32) -> anyhow::Result<Vec<File>> {
+        let mut files = Vec::new();
+
+        for chunk in hashes.chunks(MAX_HASHES) {
+            let hashes = chunk
+                .iter()
+                .map(|hash| hash.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+
+            let resp: Vec<File> = self
+                .client
+                .get("https://api.fuzzysearch.net/hashes")
+                .header("x-api-key", &self.api_key)
+                .query(&[("hashes", hashes), ("distance", distance.to_string())])
+                .send()
+                .await
+                .context("Could not make request to FuzzySearch")?
+                .error_for_status()
+                .context("Got bad FuzzySearch status code")?
+                .json()
+                .await
+                .context("Could not decode FuzzySearch response")?;
+
+            files.extend(resp);
+        }
+
+        Ok(files)
+    }
+}
+
+/// A submission in the format FuzzySearch ingests, so it can be seeded from
+/// stored submissions.
+#[derive(Debug, serde::Serialize)]
+pub struct IngestRecord {
+    pub site: &'static str,
+    pub site_id: i64,
+    pub site_id_str: String,
+    pub url: Option<String>,
+    pub artists: Vec<String>,
+    pub tags: Vec<String>,
+    pub rating: Option<String>,
+    pub posted_at: String,
+    pub deleted: bool,
+    /// Perceptual hash of the file, if it was downloaded.
+    pub hash: Option<i64>,
+    pub hash_str: Option<String>,
+}
+
+impl IngestRecord {
+    pub fn new(sub: &Submission, artist: Option<&str>, hash: Option<i64>) -> Self {
+        Self {
+            site: sub.site.as_str(),
+            site_id: sub.id,
+            site_id_str: sub.id.to_string(),
+            url: sub.file_url.clone(),
+            artists: artist.map(str::to_string).into_iter().collect(),
+            tags: sub.tags.clone(),
+            rating: sub.site_data.get("rating").cloned(),
+            posted_at: sub.posted_at.to_rfc3339(),
+            deleted: sub.deleted_at.is_some(),
+            hash,
+            hash_str: hash.map(|hash| hash.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IngestRecord;
+    use crate::tests::submission;
+
+    #[test]
+    fn test_ingest_record() {
+        let mut sub = submission(1, &["wolf"]);
+        sub.site_data
+            .insert("rating".to_string(), "general".to_string());
+
+        let record =
+            serde_json::to_value(IngestRecord::new(&sub, Some("artist"), Some(-12))).unwrap();
+        assert_eq!(record["site_id_str"], "1");
+        assert_eq!(record["artists"], serde_json::json!(["artist"]));
+        assert_eq!(record["rating"], "general");
+        assert_eq!(record["hash_str"], "-12");
+
+        let record = serde_json::to_value(IngestRecord::new(&sub, None, None)).unwrap();
+        assert_eq!(record["artists"], serde_json::json!([]));
+        assert!(record["hash"].is_null());
+    }
+}
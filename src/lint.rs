@@ -0,0 +1,135 @@
+use anyhow::Context;
+
+use crate::{config, normalize_tag, query::Query, sites::Submission, translate::Translations};
+
+/// Checks submissions against configured policies.
+#[derive(Debug)]
+pub struct Linter {
+    min_tags: Option<usize>,
+    require: Vec<(String, Query, Vec<String>)>,
+    conflict: Vec<Vec<String>>,
+}
+
+impl Linter {
+    pub fn new(config: &config::Lint, translations: &Translations) -> anyhow::Result<Self> {
+        let require = config
+            .require
+            .iter()
+            .map(|rule| -> anyhow::Result<_> {
+                let query = Query::parse_with(&rule.search, translations)
+                    .with_context(|| format!("Invalid lint search {:?}", rule.search))?;
+
+                Ok((
+                    rule.search.clone(),
+                    query,
+                    rule.tags.iter().map(|tag| normalize_tag(tag)).collect(),
+                ))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let conflict = config
+            .conflict
+            .iter()
+            .map(|rule| rule.tags.iter().map(|tag| normalize_tag(tag)).collect())
+            .collect();
+
+        Ok(Self {
+            min_tags: config.min_tags,
+            require,
+            conflict,
+        })
+    }
+
+    /// If no policies were configured.
+    pub fn is_empty(&self) -> bool {
+        self.min_tags.is_none() && self.require.is_empty() && self.conflict.is_empty()
+    }
+
+    /// Describe each policy the submission violates.
+    pub fn check(&self, sub: &Submission) -> Vec<String> {
+        let tags: Vec<_> = sub.tags.iter().map(|tag| normalize_tag(tag)).collect();
+        let mut violations = Vec::new();
+
+        if let Some(min_tags) = self.min_tags {
+            if tags.len() < min_tags {
+                violations.push(format!(
+                    "has {} tags, at least {} are required",
+                    tags.len(),
+                    min_tags
+                ));
+            }
+        }
+
+        for (search, query, required) in &self.require {
+            if !query.matches(sub) {
+                continue;
+            }
+
+            let missing: Vec<_> = required
+                .iter()
+                .filter(|tag| !tags.contains(tag))
+                .map(String::as_str)
+                .collect();
+
+            if !missing.is_empty() {
+                violations.push(format!(
+                    "matches \"{}\" but is missing: {}",
+                    search,
+                    missing.join(", ")
+                ));
+            }
+        }
+
+        for conflicting in &self.conflict {
+            let present: Vec<_> = conflicting
+                .iter()
+                .filter(|tag| tags.contains(tag))
+                .map(String::as_str)
+                .collect();
+
+            if present.len() > 1 {
+                violations.push(format!("has conflicting tags: {}", present.join(", ")));
+            }
+        }
+
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Linter;
+    use crate::config;
+
+    #[test]
+    fn test_lint() {
+        let linter = Linter::new(
+            &config::Lint {
+                min_tags: Some(3),
+                require: vec![config::LintRequire {
+                    search: "adult".to_string(),
+                    tags: vec!["mature content".to_string()],
+                }],
+                conflict: vec![config::LintConflict {
+                    tags: vec!["sfw".to_string(), "nsfw".to_string()],
+                }],
+            },
+            &Default::default(),
+        )
+        .unwrap();
+
+        let sub = crate::tests::submission(1, &["adult", "sfw", "nsfw"]);
+        assert_eq!(
+            linter.check(&sub),
+            vec![
+                "matches \"adult\" but is missing: mature_content".to_string(),
+                "has conflicting tags: sfw, nsfw".to_string(),
+            ]
+        );
+
+        let sub = crate::tests::submission(2, &["adult", "mature_content"]);
+        assert_eq!(
+            linter.check(&sub),
+            vec!["has 2 tags, at least 3 are required".to_string()]
+        );
+    }
+}
@@ -1,50 +1,385 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use clap::Parser;
+use anyhow::Context;
+use clap::{CommandFactory, Parser};
+use futures::{StreamExt, TryStreamExt};
+use tracing::Instrument;
+use unicode_normalization::UnicodeNormalization;
 
-use sites::{Site, Submission, SubmissionSite};
+use sites::{Submission, SubmissionSite, Visibility};
 
+mod auth;
+mod backup;
+mod blocklist;
+mod casing;
+mod config;
+mod diff;
+mod encryption;
+mod error;
+mod export;
+mod files;
+mod fuzzysearch;
+mod lint;
+mod pending;
+mod plan;
+mod query;
+mod report;
+mod runs;
+#[cfg(all(test, feature = "site-simulation"))]
+mod simulation;
 mod sites;
+mod stats;
+mod summary;
+mod syncstate;
+mod szurubooru;
+mod tagger;
+mod taggraph;
+mod tagsort;
+mod translate;
+mod tui;
+mod web;
+mod xmp;
 
 #[derive(clap::Parser)]
 #[clap(version = env!("CARGO_PKG_VERSION"), author = env!("CARGO_PKG_AUTHORS"))]
 struct Opts {
     /// Path to database file to store information about loaded submissions.
-    #[clap(long, default_value = "submissions.db")]
-    submissions_database: String,
+    /// Defaults to `submissions.db` in the platform data directory. Use
+    /// `:memory:` to keep everything in memory, like `--ephemeral`.
+    #[clap(long, alias = "submissions-database")]
+    db_path: Option<std::path::PathBuf>,
+    /// Keep the database in memory for this run only, so nothing about
+    /// submissions is left on disk afterwards.
+    #[clap(long, conflicts_with = "db-path")]
+    ephemeral: bool,
+    /// Create the database if it does not exist. This is always done for the
+    /// default database path.
+    #[clap(long)]
+    create_db: bool,
+    /// Open the database read-only, for commands that only query it. These
+    /// can run alongside a long load or update without waiting on it.
+    #[clap(long, conflicts_with_all = &["ephemeral", "create-db"])]
+    db_readonly: bool,
+    /// Keep the database encrypted with this passphrase. It is decrypted into
+    /// memory for each run and encrypted again when the run finishes.
+    #[clap(long, env = "BATCH_TAGGER_DB_PASSPHRASE", hide_env_values = true)]
+    db_passphrase: Option<String>,
+    /// Command that prints the database passphrase, such as one reading it
+    /// from the system keyring.
+    #[clap(long)]
+    db_passphrase_command: Option<String>,
+
+    /// Path to configuration file containing account profiles.
+    #[clap(long, default_value = "batch-tagger.toml")]
+    config: String,
+    /// Name of account profile to use. Submissions and history are stored
+    /// separately for each profile, so one database can hold the galleries of
+    /// several owners.
+    #[clap(
+        long,
+        visible_alias = "owner",
+        default_value = config::DEFAULT_PROFILE,
+        global = true
+    )]
+    profile: String,
+
+    /// Directory to store downloaded submission files.
+    #[clap(long, default_value = "cache")]
+    cache_dir: String,
+    /// Append JSON formatted logs to this file, in addition to the console.
+    #[clap(long)]
+    log_file: Option<std::path::PathBuf>,
+    /// How tag changes are displayed by dry runs and history.
+    #[clap(long, arg_enum, default_value = "compact", global = true)]
+    diff_format: diff::DiffFormat,
 
     /// API key to access Weasyl submissions.
     #[clap(long)]
-    weasyl_api_key: String,
+    weasyl_api_key: Option<String>,
+    /// OAuth2 access token to access Weasyl submissions, instead of an API
+    /// key. Tokens saved by `auth weasyl` are used if neither is given.
+    #[clap(long)]
+    weasyl_token: Option<String>,
+    /// Client ID of the application registered with Weasyl for OAuth2.
+    #[clap(long)]
+    weasyl_client_id: Option<String>,
+    /// Client secret of the application registered with Weasyl for OAuth2.
+    #[clap(long)]
+    weasyl_client_secret: Option<String>,
     /// FurAffinity cookie 'a'.
     #[clap(long)]
-    furaffinity_cookie_a: String,
+    furaffinity_cookie_a: Option<String>,
     /// FurAffinity cookie 'b'.
     #[clap(long)]
-    furaffinity_cookie_b: String,
+    furaffinity_cookie_b: Option<String>,
 
     /// Weasyl username.
     #[clap(long)]
-    weasyl_user: String,
+    weasyl_user: Option<String>,
     /// FurAffinity username.
     #[clap(long)]
-    furaffinity_user: String,
+    furaffinity_user: Option<String>,
+
+    /// Base URL of a Philomena booru, defaulting to Derpibooru.
+    #[clap(long)]
+    derpibooru_url: Option<String>,
+    /// API key to access Derpibooru uploads.
+    #[clap(long)]
+    derpibooru_api_key: Option<String>,
+    /// Derpibooru session cookie, needed to edit tags.
+    #[clap(long)]
+    derpibooru_session: Option<String>,
+
+    /// Mastodon instance, such as `mastodon.social`.
+    #[clap(long)]
+    mastodon_instance: Option<String>,
+    /// Mastodon access token with read access.
+    #[clap(long)]
+    mastodon_token: Option<String>,
+
+    /// Twitter API bearer token.
+    #[clap(long)]
+    twitter_bearer_token: Option<String>,
+    /// Twitter username.
+    #[clap(long)]
+    twitter_user: Option<String>,
+
+    /// Directory of image files with tags in sidecar files, managed like a
+    /// site.
+    #[clap(long)]
+    local_folder: Option<String>,
+
+    /// API key to look up sources on FuzzySearch.
+    #[clap(long)]
+    fuzzysearch_api_key: Option<String>,
+
+    /// URL of a szurubooru instance to push submissions to.
+    #[clap(long)]
+    szurubooru_url: Option<String>,
+    /// szurubooru username.
+    #[clap(long)]
+    szurubooru_user: Option<String>,
+    /// szurubooru login token.
+    #[clap(long)]
+    szurubooru_token: Option<String>,
 
     #[clap(subcommand)]
     command: Command,
 }
 
-/// A tool to add or remove tags from FurAffinity and Weasyl submissions based
-/// on existing tags.
+/// A tool to add or remove tags from art site submissions based on existing
+/// tags.
 #[derive(clap::Parser)]
 enum Command {
     /// Download all submissions from sites.
-    LoadSubmissions,
+    LoadSubmissions {
+        /// Download submission files into the cache directory and calculate
+        /// their perceptual hashes.
+        #[clap(long)]
+        download_files: bool,
+        /// Percentage of previously loaded submissions a site may lose before
+        /// its stored submissions are left untouched.
+        #[clap(long, default_value = "50")]
+        max_drop: f64,
+        /// Replace stored submissions even if a site lost more than
+        /// `--max-drop` percent of them.
+        #[clap(long)]
+        allow_drop: bool,
+        /// Only load submissions from these sites.
+        #[clap(long)]
+        site: Vec<SubmissionSite>,
+        /// Skip backing up the database before loading every site.
+        #[clap(long)]
+        no_backup: bool,
+        /// Only report new, removed, and retagged submissions compared to the
+        /// stored data, without writing anything.
+        #[clap(short, long, conflicts_with = "download-files")]
+        dry_run: bool,
+    },
+    /// Re-fetch only the tags of stored submissions and report any that
+    /// changed on the site since the last load, such as edits by other users
+    /// or moderators.
+    RefreshTags {
+        /// Only refresh submissions matching this search.
+        #[clap(long)]
+        search: Option<String>,
+        /// Only refresh submissions from these sites.
+        #[clap(long)]
+        site: Vec<SubmissionSite>,
+        /// Only report changed tags without storing them.
+        #[clap(short, long)]
+        dry_run: bool,
+    },
+    /// Find visually identical submissions using cached files and link copies
+    /// posted to different sites.
+    FindDuplicates {
+        /// Maximum number of bits that may differ between perceptual hashes.
+        #[clap(long, default_value = "0")]
+        distance: u32,
+        /// Only print out duplicates instead of linking them.
+        #[clap(short, long)]
+        dry_run: bool,
+    },
+    /// Look up cached files on FuzzySearch and link copies posted to other
+    /// sites.
+    FindSources {
+        /// Maximum number of bits that may differ between perceptual hashes.
+        #[clap(long, default_value = "3")]
+        distance: u32,
+        /// Only print out sources instead of linking them.
+        #[clap(short, long)]
+        dry_run: bool,
+    },
+    /// Upload files, tags, and source links of submissions to a szurubooru
+    /// instance. Submissions that were already pushed have their tags and
+    /// source updated instead.
+    PushSzurubooru {
+        /// Search for submissions to push.
+        #[clap(long)]
+        search: String,
+        /// Safety rating given to new posts.
+        #[clap(long, arg_enum, default_value = "safe")]
+        safety: szurubooru::Safety,
+        /// Only print out which submissions would be pushed.
+        #[clap(short, long)]
+        dry_run: bool,
+    },
+    /// Suggest new tags for submissions using an external tagger command.
+    SuggestTags {
+        /// Search for submissions to suggest tags for.
+        #[clap(long)]
+        search: String,
+        /// Command to run for each submission, with the path to its file added
+        /// as the last argument. It should print one tag per line, optionally
+        /// followed by a confidence between 0 and 1.
+        #[clap(long)]
+        tagger_cmd: String,
+        /// Minimum confidence required to add a suggested tag.
+        #[clap(long, default_value = "0.5")]
+        threshold: f32,
+        /// Only print out suggested changes instead of applying them.
+        #[clap(short, long)]
+        dry_run: bool,
+        /// Write suggested changes to a plan file for review instead of
+        /// applying them.
+        #[clap(long)]
+        output: Option<String>,
+    },
     /// Locally query submissions based on tags.
     QueryTags {
         /// Tags to include in search results.
         #[clap(long)]
-        search: String
+        search: String,
+        /// Include submissions that were deleted from their site.
+        #[clap(long)]
+        include_deleted: bool,
+        /// What to print for each matched submission. `ids` prints one
+        /// `Site:id` per line, which can be given to `apply-tags --ids -`.
+        #[clap(long, arg_enum, default_value = "full")]
+        output: QueryOutput,
+        /// Order matched submissions by a field instead of how they were
+        /// stored. Submissions without a view or favorite count are always
+        /// listed last.
+        #[clap(long, arg_enum)]
+        sort: Option<QuerySort>,
+        /// List the largest or newest first.
+        #[clap(long, requires = "sort")]
+        desc: bool,
+    },
+    /// Print everything stored about a single submission, including copies
+    /// on other sites and its change history.
+    Show {
+        /// Site the submission was posted to.
+        #[clap(long)]
+        site: SubmissionSite,
+        /// ID of the submission on the site.
+        #[clap(long)]
+        id: i64,
+        /// Also load the submission from its site and compare it with the
+        /// stored copy.
+        #[clap(long)]
+        remote: bool,
+    },
+    /// Open submissions matching a search in the browser for manual review.
+    Open {
+        /// Tags to include in search results.
+        #[clap(long)]
+        search: String,
+        /// Refuse to open more than this many submissions at once.
+        #[clap(long, default_value = "20")]
+        limit: usize,
+    },
+    /// Summarize stored submissions, such as how many were posted each year
+    /// and which have the most tags.
+    Stats {
+        /// Also write the summary as an HTML page to this path.
+        #[clap(long)]
+        html: Option<std::path::PathBuf>,
+    },
+    /// Write a standalone HTML page with every submission, a tag cloud, and
+    /// recent changes, which can be shared with people not running this tool.
+    Report {
+        /// Path to write the page to.
+        #[clap(long)]
+        out: std::path::PathBuf,
+        /// Number of recent batches to include changes from.
+        #[clap(long, default_value = "20")]
+        batches: i64,
+    },
+    /// Check submissions against the policies configured in the lint section
+    /// of the config file.
+    Lint {
+        /// Only check submissions matching a search.
+        #[clap(long)]
+        search: Option<String>,
+    },
+    /// Export stored submissions as JSON, with only the chosen fields.
+    Export {
+        /// Only export submissions matching a search.
+        #[clap(long)]
+        search: Option<String>,
+        /// Include submissions that were deleted from their site.
+        #[clap(long)]
+        include_deleted: bool,
+        /// Comma-separated fields to export, from site, id, title, tags,
+        /// posted_at, url, file_url, folder, local_tags, notes, deleted_at,
+        /// visibility, and site_data.
+        #[clap(long, default_value = "site,id,title,tags,posted_at,url")]
+        fields: String,
+        /// Write a single JSON array, or one JSON object per line.
+        #[clap(long, arg_enum, default_value = "json")]
+        format: ExportFormat,
+        /// File to write the export to, instead of standard output.
+        #[clap(long)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Export stored submissions in the format FuzzySearch ingests, one JSON
+    /// object per line, including file hashes for downloaded files.
+    ExportFuzzysearch {
+        /// Only export submissions matching a search.
+        #[clap(long)]
+        search: Option<String>,
+        /// Include submissions that were deleted from their site.
+        #[clap(long)]
+        include_deleted: bool,
+        /// File to write the export to, instead of standard output.
+        #[clap(long)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Export how often tags are used together as a graph.
+    TagGraph {
+        /// Only include submissions matching a search.
+        #[clap(long)]
+        search: Option<String>,
+        /// Format of the exported graph.
+        #[clap(long, arg_enum, default_value = "dot")]
+        format: GraphFormat,
+        /// Only include pairs of tags used together at least this many times.
+        #[clap(long, default_value = "1")]
+        min_count: usize,
+        /// File to write the graph to, instead of standard output.
+        #[clap(long)]
+        output: Option<std::path::PathBuf>,
     },
     /// Update submissions matching a given search to include new tags.
     ApplyTags {
@@ -52,279 +387,6016 @@ enum Command {
         #[clap(short, long)]
         dry_run: bool,
         /// Search for submissions with given tags to update.
+        #[clap(
+            long,
+            required_unless_present_any = &["ids", "ids-file", "plan"],
+            conflicts_with_all = &["ids", "ids-file", "plan"]
+        )]
+        search: Option<String>,
+        /// Comma separated list of submissions to update, such as
+        /// `FurAffinity:123,Weasyl:456`, or `-` to read them from stdin.
+        #[clap(long, conflicts_with_all = &["ids-file", "plan"])]
+        ids: Option<String>,
+        /// Path to a file containing submissions to update, in the same format
+        /// as `--ids` or one per line.
+        #[clap(long, conflicts_with = "plan")]
+        ids_file: Option<String>,
+        /// Path to a plan file with several searches and the changes to apply
+        /// to each, such as one saved by `suggest-tags --output`. Entries are
+        /// applied in order as a single batch.
+        #[clap(long)]
+        plan: Option<String>,
+        /// New tags to apply to matched submissions. Placeholders such as
+        /// `{posted_year}` or `{site}` are expanded for each submission.
+        #[clap(long, required_unless_present = "plan", conflicts_with = "plan")]
+        tags: Option<String>,
+        /// Refuse to run if the changes would remove any tags.
+        #[clap(long, conflicts_with = "remove-only")]
+        add_only: bool,
+        /// Refuse to run if the changes would add any tags.
+        #[clap(long)]
+        remove_only: bool,
+        /// Order in which matched submissions are updated.
+        #[clap(long, arg_enum, default_value = "posted_at")]
+        order: SubmissionOrder,
+        /// Number of matched submissions to skip.
+        #[clap(long, default_value = "0")]
+        offset: usize,
+        /// Maximum number of matched submissions to update.
+        #[clap(long)]
+        limit: Option<usize>,
+        /// Remove blocked tags from changes instead of refusing to run.
+        #[clap(long)]
+        strip_blocked: bool,
+        /// Only update submissions on these sites.
+        #[clap(long)]
+        site: Vec<SubmissionSite>,
+        /// Update each site in chunks of this many submissions, pausing
+        /// between chunks.
+        #[clap(long)]
+        chunk_size: Option<usize>,
+        /// How long to pause between chunks, such as `30s`, `10m`, or `1h`.
+        #[clap(long, default_value = "10m", parse(try_from_str = parse_pause))]
+        chunk_pause: std::time::Duration,
+        /// Re-fetch each submission's tags before updating it, and decide what
+        /// to do if they were changed on the site since they were loaded.
+        #[clap(long, arg_enum)]
+        conflict: Option<ConflictPolicy>,
+        /// Only update submissions during this time of day, such as
+        /// `02:00-06:00`, sleeping until the window opens again otherwise.
+        #[clap(long)]
+        only_between: Option<TimeWindow>,
+        /// Also update pinned submissions.
+        #[clap(long)]
+        force: bool,
+        /// Add matched FurAffinity submissions to this folder, by name or ID,
+        /// in the same update as their new tags.
+        #[clap(long)]
+        folder: Option<String>,
+        /// Ask for confirmation before updating more than this many
+        /// submissions.
+        #[clap(long, default_value = "50")]
+        confirm_over: usize,
+        /// Update submissions without asking for confirmation.
+        #[clap(short, long)]
+        yes: bool,
+        /// Only record the new tags locally, to be pushed to sites later with
+        /// `flush`. Sites don't need to be configured or reachable.
+        #[clap(
+            long,
+            conflicts_with_all = &["dry-run", "chunk-size", "conflict", "only-between", "folder"]
+        )]
+        queue: bool,
+    },
+    /// Push tag changes queued with `apply-tags --queue` to sites.
+    Flush {
+        /// Only print out queued changes instead of applying them.
+        #[clap(short, long)]
+        dry_run: bool,
+        /// Re-fetch each submission's tags before updating it, and decide what
+        /// to do if they were changed on the site since they were queued.
+        #[clap(long, arg_enum)]
+        conflict: Option<ConflictPolicy>,
+        /// Remove every queued change without applying it.
+        #[clap(long, conflicts_with_all = &["dry-run", "conflict"])]
+        discard: bool,
+        /// Update submissions without asking for confirmation.
+        #[clap(short, long)]
+        yes: bool,
+    },
+    /// Move queued changes and change history between databases, so changes
+    /// can be planned on one machine and pushed from another.
+    SyncState {
+        #[clap(subcommand)]
+        action: SyncStateAction,
+    },
+    /// Add every tag used on submissions matching one search to submissions
+    /// matching another.
+    CopyTags {
+        /// Only print out changes instead of applying them.
+        #[clap(short, long)]
+        dry_run: bool,
+        /// Search for submissions to copy tags from.
+        #[clap(long)]
+        from_search: String,
+        /// Search for submissions to add the tags to.
+        #[clap(long)]
+        to_search: String,
+        /// Also update pinned submissions.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Add the translations of each tag to submissions matching a search,
+    /// using the configured translations file.
+    TranslateTags {
+        /// Only print out changes instead of applying them.
+        #[clap(short, long)]
+        dry_run: bool,
+        /// Tags to include in search results.
         #[clap(long)]
         search: String,
+        /// Also update pinned submissions.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Apply tag changes to submissions matching a rule, such as adding a tag to
+    /// everything posted more than three years ago.
+    RunRule {
+        /// Only print out changes instead of applying them.
+        #[clap(short, long)]
+        dry_run: bool,
+        /// Only match submissions posted longer ago than this, such as `90d`,
+        /// `6m`, or `3y`.
+        #[clap(long)]
+        older_than: Age,
+        /// Only match submissions that also match this search.
+        #[clap(long)]
+        search: Option<String>,
         /// New tags to apply to matched submissions.
         #[clap(long)]
         tags: String,
+        /// Also update pinned submissions.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Add a tag for the date each submission was posted, such as its year.
+    BackfillDates {
+        /// Only print out changes instead of applying them.
+        #[clap(short, long)]
+        dry_run: bool,
+        /// Format of the date tag, using the same placeholders as tag changes.
+        #[clap(long, default_value = "{posted_year}")]
+        format: String,
+        /// Only update submissions on these sites.
+        #[clap(long)]
+        site: Vec<SubmissionSite>,
+        /// Also update pinned submissions.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Move submissions matching a given search into a folder on a site that
+    /// supports folders.
+    MoveFolder {
+        /// Only print out changes instead of applying them.
+        #[clap(short, long)]
+        dry_run: bool,
+        /// Search for submissions to move.
+        #[clap(long)]
+        search: String,
+        /// Name or ID of the folder to move submissions into.
+        #[clap(long)]
+        folder: String,
+        /// Site to move submissions on.
+        #[clap(long, default_value = "weasyl")]
+        site: SubmissionSite,
+    },
+    /// Change the title, description, rating, or site-specific fields of
+    /// submissions matching a given search.
+    SetMetadata {
+        /// Only print out changes instead of applying them.
+        #[clap(short, long)]
+        dry_run: bool,
+        /// Search for submissions to update.
+        #[clap(long)]
+        search: String,
+        /// New title for matched submissions.
+        #[clap(long)]
+        title: Option<String>,
+        /// New description for matched submissions.
+        #[clap(long)]
+        description: Option<String>,
+        /// New rating, such as `general` or `mature`, or the site's own code.
+        #[clap(long)]
+        rating: Option<String>,
+        /// Site-specific field to change, such as `species=wolf`. May be
+        /// given more than once.
+        #[clap(long = "field", parse(try_from_str = parse_field))]
+        fields: Vec<(String, String)>,
+        /// Only update submissions on these sites.
+        #[clap(long)]
+        site: Vec<SubmissionSite>,
+        /// Also update pinned submissions.
+        #[clap(long)]
+        force: bool,
+    },
+    /// Edit the tags of a single submission in `$EDITOR`, starting from its
+    /// current tags on the site.
+    Edit {
+        /// Site the submission was posted to.
+        #[clap(long)]
+        site: SubmissionSite,
+        /// ID of the submission on the site.
+        #[clap(long)]
+        id: i64,
+    },
+    /// Protect submissions with intentionally unusual tags from bulk tag
+    /// changes. Lists pinned submissions when run without IDs.
+    #[clap(visible_alias = "skip")]
+    Pin {
+        /// Comma separated list of submissions to pin, such as
+        /// `FurAffinity:123,Weasyl:456`, or `-` to read them from stdin.
+        #[clap(long)]
+        ids: Option<String>,
+        /// Why the submissions are pinned.
+        #[clap(long, requires = "ids")]
+        reason: Option<String>,
+        /// Unpin the given submissions instead.
+        #[clap(long, requires = "ids", conflicts_with = "reason")]
+        remove: bool,
+    },
+    /// Set local tags or notes on submissions. These are only stored locally
+    /// and never sent to sites.
+    Note {
+        /// Search for submissions to update.
+        #[clap(long, required_unless_present = "ids", conflicts_with = "ids")]
+        search: Option<String>,
+        /// Comma separated list of submissions to update, such as
+        /// `FurAffinity:123,Weasyl:456`, or `-` to read them from stdin.
+        #[clap(long)]
+        ids: Option<String>,
+        /// Changes to local tags, in the same format as `apply-tags --tags`.
+        #[clap(long)]
+        tags: Option<String>,
+        /// Note to set on submissions.
+        #[clap(long, conflicts_with = "clear-note")]
+        note: Option<String>,
+        /// Remove existing notes from submissions.
+        #[clap(long)]
+        clear_note: bool,
+    },
+    /// Embed each submission's tags as XMP keywords in its cached file, so
+    /// photo managers such as Lightroom and digiKam show the same tags.
+    /// Files must already be cached with `load-submissions --download-files`.
+    WriteMetadata {
+        /// Only update files of submissions matching this search.
+        #[clap(long)]
+        search: Option<String>,
+        /// Show which files would change without writing them.
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Set the preferred casing of tags, which is enforced whenever tags are
+    /// changed. Lists the preferred casing already set when run without tags.
+    TagCasing {
+        /// Tags written in their preferred casing, such as `OC:Rex`.
+        #[clap(long)]
+        tags: Option<String>,
+        /// Remove the preferred casing of the given tags instead.
+        #[clap(long, requires = "tags")]
+        clear: bool,
+    },
+    /// Authorize with a site in the browser and save the resulting tokens,
+    /// currently only supported for Weasyl.
+    Auth {
+        /// Site to authorize with.
+        site: SubmissionSite,
+        /// Local port the site redirects back to, which must match the
+        /// redirect URI registered with the site.
+        #[clap(long, default_value = "8765")]
+        port: u16,
+        /// Scopes to request.
+        #[clap(long, default_value = "wholesite")]
+        scope: String,
+    },
+    /// Log in to a site with a username and password and save the session,
+    /// currently only supported for FurAffinity.
+    Login {
+        /// Site to log in to.
+        site: SubmissionSite,
+        /// Username to log in with, defaults to the profile's user.
+        #[clap(long)]
+        username: Option<String>,
+        /// Password to log in with, asked for if not given.
+        #[clap(long, env = "BATCH_TAGGER_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+        /// Code from an authenticator app, asked for if the account needs
+        /// one and it is not given.
+        #[clap(long)]
+        totp_code: Option<String>,
     },
+    /// Start a local web server for searching submissions and applying tag
+    /// changes.
+    Serve {
+        /// Address to listen on.
+        #[clap(long, default_value = "127.0.0.1:3000")]
+        address: std::net::SocketAddr,
+        /// Only serve the JSON API, without the web interface.
+        #[clap(long)]
+        api_only: bool,
+        /// Password to require with HTTP basic auth, needed to listen on
+        /// anything but a loopback address.
+        #[clap(long, env = "BATCH_TAGGER_SERVE_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+    },
+    /// Show previously applied batches of changes.
+    History {
+        /// Show each change made by a single batch.
+        #[clap(long)]
+        batch: Option<i64>,
+        /// Show each run of the tool instead of batches, including how far
+        /// runs that were killed or crashed got.
+        #[clap(long, conflicts_with = "batch")]
+        runs: bool,
+        /// Maximum number of batches or runs to show.
+        #[clap(long, default_value = "20")]
+        limit: i64,
+    },
+    /// Find corrupt rows in the database that are skipped by other commands.
+    Doctor {
+        /// Fix rows where possible, such as site names with the wrong case.
+        #[clap(long)]
+        repair: bool,
+        /// Delete rows that could not be repaired.
+        #[clap(long)]
+        delete: bool,
+    },
+    /// Save a compressed snapshot of the database, including history.
+    Backup {
+        /// Path to write the backup to. Defaults to a timestamped file in a
+        /// `backups` directory next to the database.
+        #[clap(long)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Replace the database with a backup. The current database is backed up
+    /// first.
+    Restore {
+        /// Path to the backup to restore.
+        #[clap(long)]
+        input: std::path::PathBuf,
+    },
+    /// Interactively browse submissions and stage tag changes to apply.
+    Tui {
+        /// Only print out staged changes instead of applying them.
+        #[clap(short, long)]
+        dry_run: bool,
+    },
+    /// Run commands from a file in order, one per line, sharing the database
+    /// and site sessions. Blank lines and lines starting with `#` are ignored.
+    RunScript {
+        /// Path to the script file.
+        path: std::path::PathBuf,
+    },
+    /// Print shell completions.
+    Completions {
+        /// Shell to generate completions for.
+        #[clap(arg_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Print a man page.
+    Man,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
-
-    let opts = Opts::parse();
+impl Command {
+    /// If the command never changes stored data, so it can use a read-only
+    /// database.
+    fn reads_only(&self) -> bool {
+        matches!(
+            self,
+            Command::QueryTags { .. }
+                | Command::Show { remote: false, .. }
+                | Command::Open { .. }
+                | Command::Stats { .. }
+                | Command::Report { .. }
+                | Command::Lint { .. }
+                | Command::TagGraph { .. }
+                | Command::Export { .. }
+                | Command::ExportFuzzysearch { .. }
+                | Command::History { .. }
+                | Command::SyncState {
+                    action: SyncStateAction::Export { .. }
+                }
+        )
+    }
+}
 
-    let pool = sqlx::sqlite::SqlitePoolOptions::new()
-        .connect(&format!("sqlite://{}?mode=rwc", opts.submissions_database))
-        .await
-        .unwrap();
+#[derive(clap::Subcommand)]
+enum SyncStateAction {
+    /// Write queued changes and change history to a file.
+    Export {
+        /// Path to write the sync state to.
+        #[clap(long)]
+        output: std::path::PathBuf,
+    },
+    /// Add queued changes and change history from a file, skipping anything
+    /// already in this database.
+    Import {
+        /// Path of a sync state written by `sync-state export`.
+        #[clap(long)]
+        input: std::path::PathBuf,
+    },
+}
 
-    sqlx::migrate!().run(&pool).await.unwrap();
+#[derive(Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+enum QueryOutput {
+    /// Tags, notes, and links logged for each submission.
+    Full,
+    /// Only submission IDs, printed to stdout.
+    Ids,
+}
 
-    let weasyl = sites::Weasyl::new(&opts.weasyl_api_key, opts.weasyl_user);
-    let furaffinity = sites::FurAffinity::new(
-        &opts.furaffinity_cookie_a,
-        &opts.furaffinity_cookie_b,
-        opts.furaffinity_user,
-    );
+#[derive(Clone, Copy, PartialEq, Eq, clap::ArgEnum)]
+enum ExportFormat {
+    Json,
+    Ndjson,
+}
 
-    match opts.command {
-        Command::LoadSubmissions => {
-            let submissions = weasyl
-                .get_all_submissions()
-                .await?
-                .into_iter()
-                .chain(furaffinity.get_all_submissions().await?.into_iter());
+#[derive(Clone, Copy, clap::ArgEnum)]
+enum GraphFormat {
+    Dot,
+    Graphml,
+}
 
-            let mut tx = pool.begin().await?;
-            sqlx::query!("DELETE FROM submission")
-                .execute(&mut tx)
-                .await?;
+/// What to do when a submission's tags were changed on its site since they
+/// were loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ArgEnum)]
+enum ConflictPolicy {
+    /// Leave the submission untouched.
+    Skip,
+    /// Combine changes made on the site with the new changes.
+    Merge,
+    /// Replace the site's tags with the new tags anyway.
+    Overwrite,
+}
 
-            for submission in submissions {
-                let site = submission.site.as_str();
-                let tags = serde_json::to_value(submission.tags)?;
+#[derive(Clone, Copy, clap::ArgEnum)]
+#[clap(rename_all = "snake_case")]
+enum SubmissionOrder {
+    /// Oldest submissions first.
+    PostedAt,
+    /// Lowest submission IDs first, grouped by site.
+    Id,
+}
 
-                let posted_at = chrono::DateTime::<chrono::Utc>::from(submission.posted_at);
+#[derive(Clone, Copy, clap::ArgEnum)]
+#[clap(rename_all = "snake_case")]
+enum QuerySort {
+    /// When the submission was posted.
+    PostedAt,
+    /// Favorites on the submission's site.
+    Faves,
+    /// Views on the submission's site.
+    Views,
+    /// Number of tags.
+    Tagcount,
+}
 
-                sqlx::query!(
-                    "INSERT OR IGNORE INTO submission (site, id, title, posted_at, tags) VALUES ($1, $2, $3, $4, $5)",
-                    site, submission.id, submission.title, posted_at, tags
-                ).execute(&mut tx).await?;
-            }
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    register_external_sites();
+    let opts = Opts::parse();
 
-            tx.commit().await?;
-        }
-        Command::QueryTags { search } => {
-            let submissions = get_submissions(&pool).await?;
-            let filtered_submissions = query_submissions(&submissions, &search);
+    init_tracing(opts.log_file.as_deref())?;
 
-            for sub in filtered_submissions {
-                tracing::info!(
-                    "{}-{} - {}, {}: {}",
-                    sub.site,
-                    sub.id,
-                    sub.posted_at.format("%Y-%m-%d"),
-                    sub.title,
-                    sub.tags.join(", ")
-                );
-            }
-        }
-        Command::ApplyTags {
-            dry_run,
-            search,
-            tags,
-        } => {
-            let submissions = get_submissions(&pool).await?;
-            let filtered_submissions = query_submissions(&submissions, &search);
+    let run_id = uuid::Uuid::new_v4();
+    run(opts, run_id)
+        .instrument(tracing::info_span!("run", id = %run_id))
+        .await
+}
 
-            if dry_run {
-                for sub in filtered_submissions {
-                    let _span =
-                        tracing::info_span!("Dry run", id = sub.id, site = %sub.site).entered();
+/// Register external sites from the config file before parsing arguments, so
+/// their names are accepted anywhere a site is. Errors are left to be reported
+/// once arguments are parsed and the config is loaded again.
+fn register_external_sites() {
+    let matches = Opts::command().ignore_errors(true).get_matches();
+    let config = match matches.value_of("config").map(config::Config::load) {
+        Some(Ok(config)) => config,
+        _ => return,
+    };
 
-                    let new_tags = update_tags(&sub.tags, &tags);
-                    tag_display(&sub.tags, &new_tags);
-                }
-            } else {
-                for sub in filtered_submissions {
-                    let _span = tracing::info_span!("Updating tags", id = sub.id, site = %sub.site)
-                        .entered();
+    for external in config.external_sites {
+        let _ = SubmissionSite::register_external(&external.name);
+    }
+}
 
-                    let new_tags = update_tags(&sub.tags, &tags);
-                    tracing::info!("Setting tags to: {}", new_tags.join(", "));
+/// Log to stderr, and also as JSON to a file if one was given.
+fn init_tracing(log_file: Option<&std::path::Path>) -> anyhow::Result<()> {
+    use tracing_subscriber::prelude::*;
 
-                    match sub.site {
-                        SubmissionSite::FurAffinity => {
-                            furaffinity.set_tags(sub.id, &new_tags).await?
-                        }
-                        SubmissionSite::Weasyl => weasyl.set_tags(sub.id, &new_tags).await?,
-                    }
+    let file_layer = match log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .context("Could not open log file")?;
 
-                    let tag_value = serde_json::to_value(&new_tags)?;
-                    let site = sub.site.as_str();
-                    sqlx::query!(
-                        "UPDATE submission SET tags = $1 WHERE site = $2 AND id = $3",
-                        tag_value,
-                        site,
-                        sub.id
-                    )
-                    .execute(&pool)
-                    .await?;
-                }
-            }
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_ansi(false)
+                    .with_writer(std::sync::Mutex::new(file)),
+            )
         }
-    }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::filter::LevelFilter::INFO)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(file_layer)
+        .init();
 
     Ok(())
 }
 
-async fn get_submissions(pool: &sqlx::Pool<sqlx::Sqlite>) -> anyhow::Result<Vec<Submission>> {
-    let submissions = sqlx::query!("SELECT site, id, title, posted_at, tags FROM submission")
-        .map(|row| -> anyhow::Result<Submission> {
-            let posted_at: chrono::DateTime<chrono::Local> =
-                chrono::DateTime::<chrono::Utc>::from_utc(row.posted_at, chrono::Utc).into();
-
-            let site = match row.site.as_ref() {
-                "FurAffinity" => SubmissionSite::FurAffinity,
-                "Weasyl" => SubmissionSite::Weasyl,
-                _ => anyhow::bail!("unknown site in database"),
-            };
+async fn run(opts: Opts, run_id: uuid::Uuid) -> anyhow::Result<()> {
+    match opts.command {
+        Command::Completions { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut Opts::command(),
+                env!("CARGO_PKG_NAME"),
+                &mut std::io::stdout(),
+            );
+            return Ok(());
+        }
+        Command::Man => {
+            clap_mangen::Man::new(Opts::command()).render(&mut std::io::stdout())?;
+            return Ok(());
+        }
+        _ => (),
+    }
 
-            let tags: Vec<String> = serde_json::from_str(&row.tags)?;
+    if opts.db_readonly && !opts.command.reads_only() {
+        anyhow::bail!("--db-readonly can only be used with commands that don't change stored data");
+    }
 
-            Ok(Submission {
-                id: row.id as i32,
-                site,
-                title: row.title,
-                posted_at,
-                tags,
-            })
-        })
-        .fetch_all(pool)
-        .await?
+    // The default database is always created.
+    let create_db = (opts.create_db || opts.db_path.is_none()) && !opts.db_readonly;
+    let db_path = match opts.db_path {
+        _ if opts.ephemeral => None,
+        Some(db_path) if db_path.as_os_str() == IN_MEMORY_DB => None,
+        Some(db_path) => Some(db_path),
+        None => Some(default_db_path()?),
+    };
+    let passphrase = match &db_path {
+        Some(_) => db_passphrase(opts.db_passphrase, opts.db_passphrase_command.as_deref())?,
+        None => None,
+    };
+    let pool = match (&db_path, &passphrase) {
+        (Some(db_path), Some(passphrase)) => {
+            open_encrypted_database(db_path, passphrase, create_db).await?
+        }
+        (Some(db_path), None) => open_database(db_path, create_db, opts.db_readonly).await?,
+        (None, _) => open_memory_database(true).await?,
+    };
+    // Encrypted databases are kept in memory like ephemeral ones, and only
+    // written back once the run is over, unless opened read-only.
+    let encrypted = match (&db_path, &passphrase) {
+        (Some(path), Some(passphrase)) if !opts.db_readonly => {
+            Some((path.clone(), passphrase.clone(), pool.clone()))
+        }
+        _ => None,
+    };
+
+    // A read-only database must already have been migrated by an earlier
+    // run.
+    if !opts.db_readonly {
+        sqlx::migrate!().run(&pool).await.unwrap();
+        renormalize_tags(&pool).await?;
+    }
+
+    let config = config::Config::load(&opts.config)?;
+    let translations = match &config.translations {
+        Some(path) => translate::Translations::load(path)?,
+        None => Default::default(),
+    };
+    let blocklist = blocklist::Blocklist::new(&config.blocklist)?;
+    let account = opts.profile;
+    let mut profile = config.profile(&account)?.merge(config::Profile {
+        weasyl_api_key: opts.weasyl_api_key,
+        weasyl_user: opts.weasyl_user,
+        weasyl_token: opts.weasyl_token,
+        weasyl_client_id: opts.weasyl_client_id,
+        weasyl_client_secret: opts.weasyl_client_secret,
+        furaffinity_cookie_a: opts.furaffinity_cookie_a,
+        furaffinity_cookie_b: opts.furaffinity_cookie_b,
+        furaffinity_user: opts.furaffinity_user,
+        derpibooru_url: opts.derpibooru_url,
+        derpibooru_api_key: opts.derpibooru_api_key,
+        derpibooru_session: opts.derpibooru_session,
+        mastodon_instance: opts.mastodon_instance,
+        mastodon_token: opts.mastodon_token,
+        twitter_bearer_token: opts.twitter_bearer_token,
+        twitter_user: opts.twitter_user,
+        local_folder: opts.local_folder,
+        fuzzysearch_api_key: opts.fuzzysearch_api_key,
+        szurubooru_url: opts.szurubooru_url,
+        szurubooru_user: opts.szurubooru_user,
+        szurubooru_token: opts.szurubooru_token,
+    });
+
+    // Refreshing a saved token stores the new one, which a read-only database
+    // can't do.
+    if profile.weasyl_api_key.is_none() && profile.weasyl_token.is_none() && !opts.db_readonly {
+        profile.weasyl_token = auth::weasyl_token(&pool, &account, &profile).await?;
+    }
+    if profile.furaffinity_cookie_a.is_none() && profile.furaffinity_cookie_b.is_none() {
+        if let Some((cookie_a, cookie_b, username)) =
+            auth::furaffinity_session(&pool, &account).await?
+        {
+            profile.furaffinity_cookie_a = Some(cookie_a);
+            profile.furaffinity_cookie_b = Some(cookie_b);
+            profile.furaffinity_user.get_or_insert(username);
+        }
+    }
+
+    let sites = configure_sites(&profile, &config)?;
+
+    // Recorded as it goes, so history shows where a killed or crashed run
+    // stopped.
+    if !opts.db_readonly {
+        let args = runs::redact_args(std::env::args().skip(1));
+        let cli = Opts::command();
+        let command = args
+            .iter()
+            .find(|arg| cli.find_subcommand(arg.as_str()).is_some())
+            .cloned()
+            .unwrap_or_default();
+        runs::start(&pool, &account, &command, &args, &run_id.to_string()).await?;
+    }
+    let runs_pool = pool.clone();
+
+    let ctx = RunContext {
+        db_path,
+        db_passphrase: passphrase,
+        pool,
+        config,
+        translations,
+        blocklist,
+        account,
+        profile,
+        sites,
+        cache_dir: opts.cache_dir,
+        diff_format: opts.diff_format,
+    };
+
+    // Restoring replaces the encrypted file, which must not be overwritten
+    // with the database from before.
+    let command = opts.command;
+    let restoring = matches!(command, Command::Restore { .. });
+
+    let command = async move {
+        match command {
+            Command::Serve {
+                address,
+                api_only,
+                password,
+            } => {
+                web::serve(
+                    address,
+                    web::AppState {
+                        pool: ctx.pool,
+                        sites: ctx.sites,
+                        account: ctx.account,
+                        blocklist: ctx.blocklist,
+                        translations: ctx.translations,
+                        bundles: ctx.config.bundles,
+                        sort: ctx.config.sort,
+                    },
+                    api_only,
+                    password,
+                )
+                .await
+            }
+            Command::RunScript { path } => run_script(&ctx, &path).await,
+            command => run_command(&ctx, command).await,
+        }
+    };
+    // Stopping would otherwise lose everything changed in memory, so stop the
+    // command instead and save what it did.
+    let result = if encrypted.is_some() {
+        tokio::select! {
+            result = command => result,
+            _ = shutdown_signal() => Err(anyhow::anyhow!("Interrupted")),
+        }
+    } else {
+        command.await
+    };
+    runs::finish(&runs_pool, &result).await;
+
+    // Save even if the command failed, as changes may have already been made
+    // to sites.
+    if let Some((path, passphrase, pool)) = encrypted.filter(|_| !restoring) {
+        let saved = encryption::save_file(&pool, &path, &passphrase).await;
+        if let (Err(err), Err(_)) = (&saved, &result) {
+            tracing::error!("Could not save encrypted database: {:?}", err);
+        }
+
+        return result.and(saved);
+    }
+
+    result
+}
+
+/// Wait until the process is asked to stop with Ctrl-C or SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .expect("could not listen for SIGTERM");
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = terminate.recv() => (),
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Database path that keeps the database in memory instead of on disk.
+const IN_MEMORY_DB: &str = ":memory:";
+
+async fn open_database(
+    db_path: &std::path::Path,
+    create_db: bool,
+    read_only: bool,
+) -> anyhow::Result<sqlx::Pool<sqlx::Sqlite>> {
+    if create_db {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent).context("Could not create database directory")?;
+        }
+    }
+    if encryption::is_encrypted(db_path) {
+        anyhow::bail!(
+            "{} is encrypted, give its passphrase with --db-passphrase",
+            db_path.display()
+        );
+    }
+    tracing::debug!(path = %db_path.display(), "Opening database");
+
+    // WAL lets commands read while another run is writing, and a generous
+    // busy timeout waits out other writers instead of failing with "database
+    // is locked".
+    let connect_options = sqlx::sqlite::SqliteConnectOptions::new()
+        .filename(db_path)
+        .create_if_missing(create_db)
+        .read_only(read_only)
+        .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
+        .synchronous(sqlx::sqlite::SqliteSynchronous::Normal)
+        .busy_timeout(std::time::Duration::from_secs(30))
+        .pragma("temp_store", "memory");
+    sqlx::sqlite::SqlitePoolOptions::new()
+        .connect_with(connect_options)
+        .await
+        .with_context(|| format!("Could not open database at {}", db_path.display()))
+}
+
+/// Open a database that only exists for this run. Every connection to an
+/// in-memory database gets its own copy, so the pool must keep exactly one
+/// connection open.
+async fn open_memory_database(log_statements: bool) -> anyhow::Result<sqlx::Pool<sqlx::Sqlite>> {
+    use sqlx::ConnectOptions;
+
+    tracing::debug!("Opening in-memory database");
+
+    let mut connect_options: sqlx::sqlite::SqliteConnectOptions = "sqlite::memory:".parse()?;
+    if !log_statements {
+        connect_options.disable_statement_logging();
+    }
+    sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(connect_options)
+        .await
+        .context("Could not open in-memory database")
+}
+
+/// Open an encrypted database by decrypting it into memory. An existing
+/// database that isn't encrypted yet is copied into memory instead, and
+/// replaced with an encrypted one when the run finishes.
+async fn open_encrypted_database(
+    db_path: &std::path::Path,
+    passphrase: &str,
+    create_db: bool,
+) -> anyhow::Result<sqlx::Pool<sqlx::Sqlite>> {
+    // Statements include entire rows while loading, which must not end up in
+    // logs.
+    let pool = open_memory_database(false).await?;
+
+    if encryption::is_encrypted(db_path) {
+        encryption::load_file(&pool, db_path, passphrase).await?;
+    } else if db_path.exists() {
+        let plain = open_database(db_path, false, false).await?;
+        let dump = encryption::dump(&plain).await?;
+        plain.close().await;
+
+        encryption::load(&pool, &dump).await?;
+        tracing::warn!(
+            path = %db_path.display(),
+            "Database will be encrypted, existing backups are left unencrypted"
+        );
+    } else if create_db {
+        if let Some(parent) = db_path.parent() {
+            std::fs::create_dir_all(parent).context("Could not create database directory")?;
+        }
+    } else {
+        anyhow::bail!("Database {} does not exist", db_path.display());
+    }
+
+    Ok(pool)
+}
+
+/// Get the database passphrase, running the passphrase command if one was
+/// given instead of the passphrase itself.
+fn db_passphrase(
+    passphrase: Option<String>,
+    command: Option<&str>,
+) -> anyhow::Result<Option<String>> {
+    let command = match (passphrase, command) {
+        (Some(passphrase), _) => return Ok(Some(passphrase)),
+        (None, Some(command)) => command,
+        (None, None) => return Ok(None),
+    };
+
+    let output = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .context("Could not start passphrase command")?;
+    if !output.status.success() {
+        anyhow::bail!("Passphrase command exited with {}", output.status);
+    }
+
+    let passphrase = String::from_utf8(output.stdout)
+        .context("Passphrase was not valid UTF-8")?
+        .trim_end_matches(&['\r', '\n'][..])
+        .to_string();
+    if passphrase.is_empty() {
+        anyhow::bail!("Passphrase command printed nothing");
+    }
+
+    Ok(Some(passphrase))
+}
+
+/// Everything set up once per run, shared by every command in a script.
+struct RunContext {
+    /// Path to the database, or `None` if it is only kept in memory.
+    db_path: Option<std::path::PathBuf>,
+    /// Passphrase the database at `db_path` is encrypted with, if any.
+    db_passphrase: Option<String>,
+    pool: sqlx::Pool<sqlx::Sqlite>,
+    config: config::Config,
+    /// Translations of tags searches also match.
+    translations: translate::Translations,
+    blocklist: blocklist::Blocklist,
+    account: String,
+    profile: config::Profile,
+    sites: sites::Sites,
+    cache_dir: String,
+    diff_format: diff::DiffFormat,
+}
+
+/// Create clients for every site configured in a profile.
+fn configure_sites(
+    profile: &config::Profile,
+    config: &config::Config,
+) -> anyhow::Result<sites::Sites> {
+    let mut sites = sites::Sites::default();
+
+    sites.weasyl = match (
+        &profile.weasyl_api_key,
+        &profile.weasyl_token,
+        &profile.weasyl_user,
+    ) {
+        (Some(api_key), _, Some(user)) => Some(sites::Weasyl::new(api_key, user.clone())),
+        (None, Some(token), Some(user)) => Some(sites::Weasyl::with_token(token, user.clone())),
+        _ => None,
+    };
+    sites.furaffinity = match (
+        &profile.furaffinity_cookie_a,
+        &profile.furaffinity_cookie_b,
+        &profile.furaffinity_user,
+    ) {
+        (Some(cookie_a), Some(cookie_b), Some(user)) => {
+            Some(sites::FurAffinity::new(cookie_a, cookie_b, user.clone()))
+        }
+        _ => None,
+    };
+    sites.derpibooru = profile.derpibooru_api_key.as_ref().map(|api_key| {
+        sites::Derpibooru::new(
+            profile.derpibooru_url.as_deref(),
+            api_key.clone(),
+            profile.derpibooru_session.clone(),
+        )
+    });
+    sites.mastodon = match (&profile.mastodon_instance, &profile.mastodon_token) {
+        (Some(instance), Some(token)) => Some(sites::Mastodon::new(instance, token.clone())),
+        _ => None,
+    };
+    sites.twitter = match (&profile.twitter_bearer_token, &profile.twitter_user) {
+        (Some(bearer_token), Some(user)) => {
+            Some(sites::Twitter::new(bearer_token.clone(), user.clone()))
+        }
+        _ => None,
+    };
+    sites.local_folder = profile.local_folder.as_ref().map(sites::LocalFolder::new);
+    sites.external = config
+        .external_sites
+        .iter()
+        .map(sites::External::new)
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(sites)
+}
+
+/// Run each command in a script file, stopping at the first failure. Every
+/// line is parsed before anything runs, so a typo can't leave a script half
+/// applied.
+async fn run_script(ctx: &RunContext, path: &std::path::Path) -> anyhow::Result<()> {
+    let script = std::fs::read_to_string(path).context("Could not read script")?;
+    let commands = parse_script(&script)?;
+
+    for (line, command) in commands {
+        tracing::info!(line, "Running script command");
+        run_command(ctx, command)
+            .instrument(tracing::info_span!("script", line))
+            .await
+            .with_context(|| format!("Script failed on line {}", line))?;
+    }
+
+    Ok(())
+}
+
+/// Parse script lines into commands, along with their line numbers.
+fn parse_script(script: &str) -> anyhow::Result<Vec<(usize, Command)>> {
+    let mut commands = Vec::new();
+
+    for (index, line) in script.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let args = split_args(line).with_context(|| format!("Invalid line {}", line_number))?;
+        let command = Command::try_parse_from(
+            std::iter::once(env!("CARGO_PKG_NAME").to_string()).chain(args),
+        )
+        .with_context(|| format!("Invalid command on line {}", line_number))?;
+
+        if matches!(
+            command,
+            Command::RunScript { .. }
+                | Command::Serve { .. }
+                | Command::Auth { .. }
+                | Command::Login { .. }
+                | Command::Restore { .. }
+                | Command::Completions { .. }
+                | Command::Man
+        ) {
+            anyhow::bail!("Command on line {} can't be used in scripts", line_number);
+        }
+
+        commands.push((line_number, command));
+    }
+
+    Ok(commands)
+}
+
+/// Split a line into arguments like a shell, where single or double quotes
+/// group words and a backslash escapes the next character.
+fn split_args(line: &str) -> anyhow::Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', Some('\'')) => current.get_or_insert_with(String::new).push(c),
+            ('\\', _) => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("Line ends with a backslash"))?;
+                current.get_or_insert_with(String::new).push(escaped);
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (c, Some(_)) => current.get_or_insert_with(String::new).push(c),
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (c, None) if c.is_whitespace() => args.extend(current.take()),
+            (c, None) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quote.is_some() {
+        anyhow::bail!("Line has an unclosed quote");
+    }
+    args.extend(current);
+
+    Ok(args)
+}
+
+/// Run a single command using shared setup.
+async fn run_command(ctx: &RunContext, command: Command) -> anyhow::Result<()> {
+    let RunContext {
+        db_path,
+        db_passphrase,
+        pool,
+        config,
+        translations,
+        blocklist,
+        account,
+        profile,
+        sites,
+        ..
+    } = ctx;
+
+    match command {
+        Command::LoadSubmissions {
+            download_files,
+            max_drop,
+            allow_drop,
+            site: only_sites,
+            no_backup,
+            dry_run,
+        } => {
+            if let Some(db_path) = db_path
+                .as_deref()
+                .filter(|_| only_sites.is_empty() && !no_backup && !dry_run)
+            {
+                backup::backup(
+                    pool,
+                    &backup::default_path(db_path, db_passphrase.is_some()),
+                    db_passphrase.as_deref(),
+                )
+                .await?;
+            }
+
+            let mut submissions = Vec::new();
+            let mut failed = 0;
+            let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+            let mut summary = summary::RunSummary::new("load");
+
+            // Sites are loaded concurrently, and each is stored as soon as it
+            // finishes instead of waiting for the slowest site.
+            let mut loads = futures::stream::FuturesUnordered::new();
+
+            for site in sites.all() {
+                if !only_sites.is_empty() && !only_sites.contains(&site) {
+                    continue;
+                }
+
+                let client = match sites.get(site) {
+                    Ok(client) => client,
+                    Err(_) => {
+                        tracing::warn!("{} is not configured for this profile, skipping", site);
+                        continue;
+                    }
+                };
+
+                let span = tracing::info_span!("Loading submissions", %site);
+                loads.push(async move {
+                    let started = std::time::Instant::now();
+                    let result = client.get_all_submissions().instrument(span.clone()).await;
+                    let elapsed = started.elapsed();
+
+                    let reported = match &result {
+                        Ok(_) => client.reported_count().instrument(span).await,
+                        Err(_) => Ok(None),
+                    };
+
+                    (site, result, reported, elapsed)
+                });
+            }
+
+            while let Some((site, result, reported, elapsed)) = loads.next().await {
+                let _span = tracing::info_span!("Storing submissions", %site).entered();
+                summary.record_time(site, elapsed);
+
+                let site_submissions = match result {
+                    Ok(site_submissions) => site_submissions,
+                    Err(err) => {
+                        tracing::error!(
+                            "Could not load submissions, keeping stored data: {:?}",
+                            err
+                        );
+                        summary.record_failure(site);
+                        failed += 1;
+                        continue;
+                    }
+                };
+                summary.record_loaded(site, site_submissions.len());
+
+                match reported {
+                    Ok(Some(reported)) => {
+                        if let Some(difference) = count_difference(reported, site_submissions.len())
+                        {
+                            tracing::warn!(
+                                reported,
+                                loaded = site_submissions.len(),
+                                "Loaded {:.0}% {} submissions than the site reports, some may have been missed",
+                                difference.abs(),
+                                if difference < 0.0 { "fewer" } else { "more" }
+                            );
+                        }
+                    }
+                    Ok(None) => (),
+                    Err(err) => tracing::debug!("Could not check reported count: {:?}", err),
+                }
+
+                let site_name = site.as_str();
+                let previous = sqlx::query_scalar!(
+                    "SELECT count(*) FROM submission WHERE account = $1 AND site = $2 AND deleted_at IS NULL",
+                    account,
+                    site_name
+                )
+                .fetch_one(pool)
+                .await?;
+
+                if let Some(dropped) = dropped_percent(previous.into(), site_submissions.len()) {
+                    if dropped > max_drop {
+                        tracing::warn!(
+                            previous,
+                            loaded = site_submissions.len(),
+                            "Site returned {:.0}% fewer submissions than previously loaded",
+                            dropped
+                        );
+
+                        if !allow_drop && !dry_run {
+                            tracing::warn!("Keeping stored data, use --allow-drop to replace it");
+                            summary.record_failure(site);
+                            failed += 1;
+                            continue;
+                        }
+                    }
+                }
+
+                if site == SubmissionSite::FurAffinity {
+                    let stored = filter_submissions(pool, account, |sub| sub.site == site).await?;
+                    let filtered = missing_restricted(&stored, &site_submissions);
+                    if filtered > 0 {
+                        tracing::error!(
+                            filtered,
+                            "FurAffinity returned no mature or adult submissions, the session appears to be filtering content; check the account's content maturity settings"
+                        );
+
+                        if !allow_drop && !dry_run {
+                            tracing::warn!("Keeping stored data, use --allow-drop to replace it");
+                            summary.record_failure(site);
+                            failed += 1;
+                            continue;
+                        }
+                    }
+                }
+
+                if dry_run {
+                    let stored = filter_submissions(pool, account, |sub| sub.site == site).await?;
+                    let changes = SyncChanges::new(&stored, &site_submissions);
+
+                    for sub in &changes.added {
+                        tracing::info!(id = sub.id, "Would add {}", sub.title);
+                    }
+                    for sub in &changes.removed {
+                        tracing::info!(id = sub.id, "Would mark {} as deleted", sub.title);
+                    }
+                    for (old, new) in &changes.retagged {
+                        diff.show(site.as_str(), new.id, &old.tags, &new.tags);
+                    }
+
+                    tracing::info!(
+                        added = changes.added.len(),
+                        removed = changes.removed.len(),
+                        retagged = changes.retagged.len(),
+                        "Loaded {} submissions",
+                        site_submissions.len()
+                    );
+                    continue;
+                }
+
+                let deleted =
+                    replace_site_submissions(pool, account, site, &site_submissions).await?;
+                tracing::info!(deleted, "Stored {} submissions", site_submissions.len());
+
+                submissions.extend(site_submissions);
+            }
+
+            if download_files {
+                let cache = files::FileCache::new(&ctx.cache_dir);
+
+                for submission in &submissions {
+                    let _span = tracing::info_span!("Caching file", id = submission.id, site = %submission.site).entered();
+
+                    match cache_file(pool, &cache, submission).await {
+                        Ok(Some(path)) => report_file_keywords(submission, &path),
+                        Ok(None) => (),
+                        Err(err) => tracing::warn!("Could not cache file: {:?}", err),
+                    }
+                }
+            }
+
+            if dry_run {
+                diff.summary();
+            }
+            summary.log();
+
+            if failed > 0 {
+                anyhow::bail!("Submissions for {} sites were not updated", failed);
+            }
+        }
+        Command::RefreshTags {
+            search,
+            site: only_sites,
+            dry_run,
+        } => {
+            let submissions = match search {
+                Some(search) => search_submissions(pool, account, translations, &search).await?,
+                None => get_submissions(pool, account).await?,
+            };
+
+            let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+            let mut changed = 0;
+
+            for sub in &submissions {
+                if !only_sites.is_empty() && !only_sites.contains(&sub.site) {
+                    continue;
+                }
+
+                let client = match sites.get(sub.site) {
+                    Ok(client) => client,
+                    Err(_) => {
+                        tracing::debug!(site = %sub.site, "Site is not configured, skipping");
+                        continue;
+                    }
+                };
+
+                let _span =
+                    tracing::info_span!("Refreshing tags", id = sub.id, site = %sub.site).entered();
+
+                let tags = match client.get_tags(sub.id).await {
+                    Ok(tags) => tags,
+                    Err(err) => {
+                        tracing::warn!("Could not load tags: {:?}", err);
+                        continue;
+                    }
+                };
+
+                if tags == sub.tags {
+                    continue;
+                }
+
+                tracing::warn!("Tags changed on site since last load");
+                diff.show(sub.site.as_str(), sub.id, &sub.tags, &tags);
+                changed += 1;
+
+                if !dry_run {
+                    let mut tx = pool.begin().await?;
+                    store_tags(&mut tx, account, sub.site, sub.id, &tags).await?;
+                    tx.commit().await?;
+                }
+            }
+
+            diff.summary();
+            tracing::info!(dry_run, "Tags changed on {} submissions", changed);
+        }
+        Command::FindDuplicates { distance, dry_run } => {
+            let files = sqlx::query!(
+                r#"SELECT submission.site, submission.id, submission.title, submission_file.hash AS "hash!"
+                    FROM submission_file
+                    JOIN submission ON submission.site = submission_file.site AND submission.id = submission_file.id
+                    WHERE submission.account = $1 AND submission_file.hash IS NOT NULL"#,
+                account
+            )
+            .fetch_all(pool)
+            .await?;
+
+            let hashes: Vec<_> = files.iter().map(|file| file.hash).collect();
+            let groups = files::group_hashes(&hashes, distance);
+
+            tracing::info!("Found {} groups of duplicate submissions", groups.len());
+
+            for group in groups {
+                let group: Vec<_> = group.into_iter().map(|index| &files[index]).collect();
+
+                tracing::info!(
+                    "Duplicates: {}",
+                    group
+                        .iter()
+                        .map(|file| format!("{}-{} ({})", file.site, file.id, file.title))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+
+                if dry_run {
+                    continue;
+                }
+
+                for file in &group {
+                    for other in group.iter().filter(|other| other.site != file.site) {
+                        let linked_id = other.id.to_string();
+
+                        sqlx::query!(
+                            "INSERT OR IGNORE INTO submission_link (site, id, linked_site, linked_id, source) VALUES ($1, $2, $3, $4, 'hash')",
+                            file.site,
+                            file.id,
+                            other.site,
+                            linked_id
+                        )
+                        .execute(pool)
+                        .await?;
+                    }
+                }
+            }
+        }
+        Command::FindSources { distance, dry_run } => {
+            let api_key = profile
+                .fuzzysearch_api_key
+                .clone()
+                .context("FuzzySearch API key is required to find sources")?;
+            let fuzzysearch = fuzzysearch::FuzzySearch::new(api_key);
+
+            let files = sqlx::query!(
+                r#"SELECT submission.site, submission.id, submission.title, submission_file.hash AS "hash!"
+                    FROM submission_file
+                    JOIN submission ON submission.site = submission_file.site AND submission.id = submission_file.id
+                    WHERE submission.account = $1 AND submission_file.hash IS NOT NULL"#,
+                account
+            )
+            .fetch_all(pool)
+            .await?;
+
+            let hashes: Vec<_> = files.iter().map(|file| file.hash).collect();
+            let sources = fuzzysearch.lookup_hashes(&hashes, distance).await?;
+
+            tracing::info!("Found {} possible sources", sources.len());
+
+            for source in sources {
+                for file in files
+                    .iter()
+                    .filter(|file| file.hash == source.searched_hash)
+                {
+                    if source.site == file.site && source.site_id_str == file.id.to_string() {
+                        continue;
+                    }
+
+                    tracing::info!(
+                        "{}-{} ({}) was posted to {}: {} (distance {})",
+                        file.site,
+                        file.id,
+                        file.title,
+                        source.site,
+                        source.url,
+                        source.distance.unwrap_or_default()
+                    );
+
+                    if dry_run {
+                        continue;
+                    }
+
+                    sqlx::query!(
+                        "INSERT OR IGNORE INTO submission_link (site, id, linked_site, linked_id, url, source) VALUES ($1, $2, $3, $4, $5, 'fuzzysearch')",
+                        file.site,
+                        file.id,
+                        source.site,
+                        source.site_id_str,
+                        source.url
+                    )
+                    .execute(pool)
+                    .await?;
+                }
+            }
+        }
+        Command::PushSzurubooru {
+            search,
+            safety,
+            dry_run,
+        } => {
+            let szurubooru = match (
+                &profile.szurubooru_url,
+                &profile.szurubooru_user,
+                &profile.szurubooru_token,
+            ) {
+                (Some(url), Some(user), Some(token)) => {
+                    szurubooru::Szurubooru::new(url, user, token)
+                }
+                _ => anyhow::bail!("szurubooru URL, user, and token are required to push"),
+            };
+
+            let filtered_submissions =
+                search_submissions(pool, account, translations, &search).await?;
+            let cache = files::FileCache::new(&ctx.cache_dir);
+            let mut pushed = 0;
+
+            for sub in &filtered_submissions {
+                let _span =
+                    tracing::info_span!("Pushing submission", id = sub.id, site = %sub.site)
+                        .entered();
+
+                let site = sub.site.as_str();
+                let existing = sqlx::query_scalar!(
+                    "SELECT linked_id FROM submission_link WHERE site = $1 AND id = $2 AND linked_site = 'szurubooru'",
+                    site,
+                    sub.id
+                )
+                .fetch_optional(pool)
+                .await?
+                .and_then(|id| id.parse::<i64>().ok());
+
+                // szurubooru tag names can't contain whitespace.
+                let tags: Vec<_> = sub.tags.iter().map(|tag| normalize_tag(tag)).collect();
+                let source = sub.url();
+
+                if dry_run {
+                    match existing {
+                        Some(post_id) => tracing::info!(post_id, "Would update post"),
+                        None => tracing::info!("Would create post"),
+                    }
+                    pushed += 1;
+                    continue;
+                }
+
+                let post = match existing {
+                    Some(post_id) => {
+                        szurubooru
+                            .update_post(post_id, &tags, source.as_deref())
+                            .await
+                    }
+                    None => {
+                        let path = match cache_file(pool, &cache, sub).await {
+                            Ok(Some(path)) => path,
+                            Ok(None) => {
+                                tracing::warn!("Submission had no file, skipping");
+                                continue;
+                            }
+                            Err(err) => {
+                                tracing::warn!("Could not cache file, skipping: {:?}", err);
+                                continue;
+                            }
+                        };
+
+                        szurubooru
+                            .create_post(&path, &tags, source.as_deref(), safety)
+                            .await
+                    }
+                };
+
+                let post = match post {
+                    Ok(post) => post,
+                    Err(err) => {
+                        tracing::warn!("Could not push submission: {:?}", err);
+                        continue;
+                    }
+                };
+
+                let post_id = post.id.to_string();
+                let post_url = szurubooru.post_url(post.id);
+                sqlx::query!(
+                    "INSERT OR IGNORE INTO submission_link (site, id, linked_site, linked_id, url, source) VALUES ($1, $2, 'szurubooru', $3, $4, 'szurubooru')",
+                    site,
+                    sub.id,
+                    post_id,
+                    post_url
+                )
+                .execute(pool)
+                .await?;
+
+                tracing::info!(post_id = post.id, "Pushed submission");
+                pushed += 1;
+            }
+
+            tracing::info!(dry_run, "Pushed {} submissions", pushed);
+        }
+        Command::SuggestTags {
+            search,
+            tagger_cmd,
+            threshold,
+            dry_run,
+            output,
+        } => {
+            let filtered_submissions =
+                search_submissions(pool, account, translations, &search).await?;
+
+            let cache = files::FileCache::new(&ctx.cache_dir);
+            let mut plan = plan::Plan::default();
+            let mut changes = Vec::new();
+            let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+
+            for sub in &filtered_submissions {
+                let _span =
+                    tracing::info_span!("Suggesting tags", id = sub.id, site = %sub.site).entered();
+
+                let path = match cache_file(pool, &cache, sub).await {
+                    Ok(Some(path)) => path,
+                    Ok(None) => {
+                        tracing::warn!("Submission had no file, skipping");
+                        continue;
+                    }
+                    Err(err) => {
+                        tracing::warn!("Could not cache file, skipping: {:?}", err);
+                        continue;
+                    }
+                };
+
+                let existing_tags: Vec<_> = sub.tags.iter().map(|tag| normalize_tag(tag)).collect();
+                let suggestions: Vec<_> = tagger::run_tagger(&tagger_cmd, &path)
+                    .await?
+                    .into_iter()
+                    .filter(|suggestion| suggestion.confidence >= threshold)
+                    .map(|suggestion| suggestion.tag.trim_start_matches('-').to_string())
+                    .filter(|tag| !tag.is_empty() && !existing_tags.contains(&normalize_tag(tag)))
+                    .filter(|tag| !blocklist.is_blocked(tag))
+                    .map(|tag| quote_tag(&tag))
+                    .collect();
+
+                if suggestions.is_empty() {
+                    tracing::debug!("No new tags were suggested");
+                    continue;
+                }
+
+                let tag_changes = suggestions.join(" ");
+                let new_tags =
+                    update_tags(sub, &sub.tags, &tag_changes, &config.bundles, &config.sort);
+
+                if output.is_some() {
+                    diff.show(sub.site.as_str(), sub.id, &sub.tags, &new_tags);
+                    plan.entries.push(plan::PlanEntry {
+                        search: None,
+                        ids: Some(format!("{}:{}", sub.site, sub.id)),
+                        tags: tag_changes,
+                    });
+                } else if dry_run {
+                    diff.show(sub.site.as_str(), sub.id, &sub.tags, &new_tags);
+                } else {
+                    changes.push((sub, new_tags));
+                }
+            }
+
+            if output.is_some() || dry_run {
+                diff.summary();
+            } else if !changes.is_empty() {
+                let batch_id =
+                    start_batch(pool, account, "suggest-tags", Some(&search), None).await?;
+
+                apply_changes(pool, sites, account, &config.sort, batch_id, changes).await?;
+            }
+
+            if let Some(output) = output {
+                plan.save(&output)?;
+                tracing::info!(
+                    "Saved {} suggested changes to {}",
+                    plan.entries.len(),
+                    output
+                );
+            }
+        }
+        Command::QueryTags {
+            search,
+            include_deleted,
+            output,
+            sort,
+            desc,
+        } => {
+            let query = query::Query::parse_with(&search, translations)?;
+            let mut filtered_submissions = filter_all_submissions(
+                pool,
+                account,
+                include_deleted,
+                &query.required_tags(),
+                |sub| query.matches(sub),
+            )
+            .await?;
+            if let Some(sort) = sort {
+                sort_query_results(&mut filtered_submissions, sort, desc);
+            }
+
+            if output == QueryOutput::Ids {
+                for sub in &filtered_submissions {
+                    println!("{}:{}", sub.site, sub.id);
+                }
+                return Ok(());
+            }
+
+            for sub in &filtered_submissions {
+                tracing::info!(
+                    "{}-{} - {}, {}: {}",
+                    sub.site,
+                    sub.id,
+                    sub.posted_at.format("%Y-%m-%d"),
+                    sub.title,
+                    sub.tags.join(", ")
+                );
+
+                if !sub.local_tags.is_empty() {
+                    tracing::info!("Local tags: {}", sub.local_tags.join(", "));
+                }
+
+                if let Some(notes) = &sub.notes {
+                    tracing::info!("Notes: {}", notes);
+                }
+
+                if let Some(deleted_at) = sub.deleted_at {
+                    tracing::info!("Deleted from site: {}", deleted_at.format("%Y-%m-%d"));
+                }
+
+                if let Some(url) = sub.url() {
+                    tracing::info!("URL: {}", url);
+                }
+            }
+        }
+        Command::Show { site, id, remote } => {
+            let sub = filter_all_submissions(pool, account, true, &[], |sub| {
+                sub.site == site && sub.id == id
+            })
+            .await?
+            .into_iter()
+            .next()
+            .with_context(|| format!("{}-{} is not stored, load submissions first", site, id))?;
+
+            tracing::info!("{}-{}: {}", sub.site, sub.id, sub.title);
+            tracing::info!("Posted: {}", sub.posted_at.format("%Y-%m-%d %H:%M:%S"));
+            if let Some(deleted_at) = sub.deleted_at {
+                tracing::info!("Deleted from site: {}", deleted_at.format("%Y-%m-%d"));
+            }
+            if let Some(url) = sub.url() {
+                tracing::info!("URL: {}", url);
+            }
+            if let Some(folder_name) = &sub.folder_name {
+                tracing::info!("Folder: {}", folder_name);
+            }
+            if sub.visibility != Visibility::Public {
+                tracing::info!("Visibility: {}", sub.visibility.as_str());
+            }
+            tracing::info!("Tags ({}): {}", sub.tags.len(), sub.tags.join(", "));
+            if !sub.local_tags.is_empty() {
+                tracing::info!("Local tags: {}", sub.local_tags.join(", "));
+            }
+            if let Some(notes) = &sub.notes {
+                tracing::info!("Notes: {}", notes);
+            }
+            for (key, value) in &sub.site_data {
+                tracing::info!("{}: {}", key, value);
+            }
+
+            let site_name = site.as_str();
+
+            let pin = sqlx::query_scalar!(
+                "SELECT reason FROM submission_pin WHERE account = $1 AND site = $2 AND id = $3",
+                account,
+                site_name,
+                id
+            )
+            .fetch_optional(pool)
+            .await?;
+            if let Some(reason) = pin {
+                tracing::info!("Pinned: {}", reason.as_deref().unwrap_or("no reason given"));
+            }
+
+            let file = sqlx::query!(
+                "SELECT url, path FROM submission_file WHERE site = $1 AND id = $2",
+                site_name,
+                id
+            )
+            .fetch_optional(pool)
+            .await?;
+            if let Some(file) = file {
+                tracing::info!("File: {} (from {})", file.path, file.url);
+            }
+
+            let links = sqlx::query!(
+                "SELECT linked_site, linked_id, url, source FROM submission_link WHERE site = $1 AND id = $2 ORDER BY linked_site, linked_id",
+                site_name,
+                id
+            )
+            .fetch_all(pool)
+            .await?;
+            for link in links {
+                tracing::info!(
+                    "Linked to {}-{} by {}{}",
+                    link.linked_site,
+                    link.linked_id,
+                    link.source,
+                    link.url.map(|url| format!(": {}", url)).unwrap_or_default()
+                );
+            }
+
+            let changes = sqlx::query!(
+                r#"SELECT batch.id AS "batch_id!: i64", batch.command, batch_change.old_tags, batch_change.new_tags, batch_change.changed_at
+                    FROM batch_change
+                    JOIN batch ON batch.id = batch_change.batch_id
+                    WHERE batch.account = $1 AND batch_change.site = $2 AND batch_change.id = $3
+                    ORDER BY batch_change.changed_at"#,
+                account,
+                site_name,
+                id
+            )
+            .fetch_all(pool)
+            .await?;
+
+            let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+            for change in changes {
+                tracing::info!(
+                    "Changed by batch {} ({}) on {}",
+                    change.batch_id,
+                    change.command,
+                    change.changed_at.format("%Y-%m-%d %H:%M:%S")
+                );
+
+                let old_tags: Vec<String> = serde_json::from_str(&change.old_tags)?;
+                let new_tags: Vec<String> = serde_json::from_str(&change.new_tags)?;
+                diff.show(site_name, id, &old_tags, &new_tags);
+            }
+
+            if remote {
+                let current = sites.get(site)?.get_submission(id).await?;
+
+                if current.title != sub.title {
+                    tracing::info!("Title on site: {}", current.title);
+                }
+                if tags_diverged(&sub.tags, &current.tags) {
+                    tracing::info!("Tags on site differ from stored tags:");
+                    let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+                    diff.show(site_name, id, &sub.tags, &current.tags);
+                } else {
+                    tracing::info!("Tags on site match stored tags");
+                }
+                for (key, value) in &current.site_data {
+                    if sub.site_data.get(key) != Some(value) {
+                        tracing::info!("{} on site: {}", key, value);
+                    }
+                }
+            }
+        }
+        Command::Open { search, limit } => {
+            let filtered_submissions =
+                search_submissions(pool, account, translations, &search).await?;
+            tracing::info!("found {} matching submissions", filtered_submissions.len());
+
+            if filtered_submissions.len() > limit {
+                anyhow::bail!(
+                    "{} submissions matched, more than the limit of {}",
+                    filtered_submissions.len(),
+                    limit
+                );
+            }
+
+            for sub in &filtered_submissions {
+                match sub.url() {
+                    Some(url) => open_url(&url)?,
+                    None => tracing::warn!("no known URL for {}-{}", sub.site, sub.id),
+                }
+            }
+        }
+        Command::Stats { html } => {
+            let submissions = get_submissions(pool, account).await?;
+            let stats = stats::Stats::new(&submissions);
+
+            print!("{}", stats.to_table());
+
+            if let Some(path) = html {
+                std::fs::write(&path, stats.to_html()).context("Could not write HTML report")?;
+                tracing::info!(path = %path.display(), "Wrote HTML report");
+            }
+        }
+        Command::Report { out, batches } => {
+            let mut submissions = get_submissions(pool, account).await?;
+            submissions.sort_by_key(|sub| std::cmp::Reverse(sub.posted_at));
+
+            let changes = sqlx::query!(
+                r#"SELECT batch.id AS "batch_id!: i64", batch.command, batch.started_at, batch_change.site, batch_change.id AS "submission_id!: i64", batch_change.old_tags, batch_change.new_tags
+                    FROM batch_change
+                    JOIN batch ON batch.id = batch_change.batch_id
+                    WHERE batch.account = $1
+                        AND batch.id IN (SELECT id FROM batch WHERE account = $1 ORDER BY id DESC LIMIT $2)
+                    ORDER BY batch.id DESC, batch_change.changed_at"#,
+                account,
+                batches
+            )
+            .fetch_all(pool)
+            .await?;
+
+            let history = changes
+                .into_iter()
+                .map(|change| {
+                    Ok(report::HistoryChange {
+                        batch_id: change.batch_id,
+                        command: change.command,
+                        started_at: change.started_at,
+                        site: change.site,
+                        id: change.submission_id,
+                        old_tags: serde_json::from_str(&change.old_tags)?,
+                        new_tags: serde_json::from_str(&change.new_tags)?,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            std::fs::write(&out, report::render(&submissions, &history))
+                .context("Could not write report")?;
+            tracing::info!(path = %out.display(), "Wrote report");
+        }
+        Command::Export {
+            search,
+            include_deleted,
+            fields,
+            format,
+            output,
+        } => {
+            let fields = export::parse_fields(&fields)?;
+            let query = search
+                .as_deref()
+                .map(|search| query::Query::parse_with(search, translations))
+                .transpose()?;
+            let required_tags = query
+                .as_ref()
+                .map(|query| query.required_tags())
+                .unwrap_or_default();
+
+            let out: Box<dyn std::io::Write + Send> = match &output {
+                Some(path) => {
+                    Box::new(std::fs::File::create(path).context("Could not create export file")?)
+                }
+                None => Box::new(std::io::stdout()),
+            };
+            let mut writer =
+                export::Writer::new(std::io::BufWriter::new(out), format == ExportFormat::Ndjson);
+
+            // Written as they are read so large databases are never held in
+            // memory at once.
+            for_each_submission(pool, account, include_deleted, &required_tags, |sub| {
+                if query.as_ref().is_none_or(|query| query.matches(&sub)) {
+                    writer.write(&export::record(&sub, &fields))?;
+                }
+
+                Ok(())
+            })
+            .await?;
+
+            let exported = writer.finish()?;
+            tracing::info!("Exported {} submissions", exported);
+        }
+        Command::ExportFuzzysearch {
+            search,
+            include_deleted,
+            output,
+        } => {
+            let query = search
+                .as_deref()
+                .map(|search| query::Query::parse_with(search, translations))
+                .transpose()?;
+            let required_tags = query
+                .as_ref()
+                .map(|query| query.required_tags())
+                .unwrap_or_default();
+
+            let files = sqlx::query!(
+                r#"SELECT submission.site, submission.id, submission.title, submission_file.hash AS "hash!"
+                    FROM submission_file
+                    JOIN submission ON submission.site = submission_file.site AND submission.id = submission_file.id
+                    WHERE submission.account = $1 AND submission_file.hash IS NOT NULL"#,
+                account
+            )
+            .fetch_all(pool)
+            .await?;
+            let hashes: HashMap<_, _> = files
+                .into_iter()
+                .map(|file| ((file.site, file.id), file.hash))
+                .collect();
+
+            // Submissions only store the account's own work, so the artist is
+            // whoever the account is on each site.
+            let artist = |site: SubmissionSite| match site {
+                SubmissionSite::FurAffinity => profile.furaffinity_user.as_deref(),
+                SubmissionSite::Weasyl => profile.weasyl_user.as_deref(),
+                SubmissionSite::Twitter => profile.twitter_user.as_deref(),
+                _ => None,
+            };
+
+            let out: Box<dyn std::io::Write + Send> = match &output {
+                Some(path) => {
+                    Box::new(std::fs::File::create(path).context("Could not create export file")?)
+                }
+                None => Box::new(std::io::stdout()),
+            };
+            let mut writer = export::Writer::new(std::io::BufWriter::new(out), true);
+
+            for_each_submission(pool, account, include_deleted, &required_tags, |sub| {
+                if query.as_ref().is_none_or(|query| query.matches(&sub)) {
+                    let hash = hashes
+                        .get(&(sub.site.as_str().to_string(), sub.id))
+                        .copied();
+                    let record = fuzzysearch::IngestRecord::new(&sub, artist(sub.site), hash);
+                    writer.write(&serde_json::to_value(record)?)?;
+                }
+
+                Ok(())
+            })
+            .await?;
+
+            let exported = writer.finish()?;
+            tracing::info!("Exported {} submissions for FuzzySearch", exported);
+        }
+        Command::TagGraph {
+            search,
+            format,
+            min_count,
+            output,
+        } => {
+            let filtered_submissions = match &search {
+                Some(search) => search_submissions(pool, account, translations, search).await?,
+                None => get_submissions(pool, account).await?,
+            };
+
+            let mut graph = taggraph::TagGraph::new(&filtered_submissions);
+            if min_count > 1 {
+                graph.prune(min_count);
+            }
+
+            let graph = match format {
+                GraphFormat::Dot => graph.to_dot(),
+                GraphFormat::Graphml => graph.to_graphml(),
+            };
+
+            match output {
+                Some(path) => std::fs::write(path, graph).context("Could not write graph")?,
+                None => print!("{}", graph),
+            }
+        }
+        Command::Lint { search } => {
+            let linter = lint::Linter::new(&config.lint, translations)?;
+            if linter.is_empty() {
+                anyhow::bail!("no lint policies are configured");
+            }
+
+            let filtered_submissions = match &search {
+                Some(search) => search_submissions(pool, account, translations, search).await?,
+                None => get_submissions(pool, account).await?,
+            };
+
+            let mut failed = 0;
+            for sub in &filtered_submissions {
+                let violations = linter.check(sub);
+                if violations.is_empty() {
+                    continue;
+                }
+
+                failed += 1;
+                for violation in violations {
+                    tracing::warn!("{}-{} ({}) {}", sub.site, sub.id, sub.title, violation);
+                }
+            }
+
+            tracing::info!(
+                "{} of {} submissions violated policies",
+                failed,
+                filtered_submissions.len()
+            );
+        }
+        Command::ApplyTags {
+            dry_run,
+            search,
+            ids,
+            ids_file,
+            plan,
+            tags,
+            add_only,
+            remove_only,
+            order,
+            offset,
+            limit,
+            strip_blocked,
+            site,
+            chunk_size,
+            chunk_pause,
+            conflict,
+            only_between,
+            force,
+            folder,
+            confirm_over,
+            yes,
+            queue,
+        } => {
+            if chunk_size == Some(0) {
+                anyhow::bail!("Chunk size must be at least 1");
+            }
+
+            let plan_path = plan;
+            let plan = plan_path.as_deref().map(plan::Plan::load).transpose()?;
+            let tags = match &plan {
+                Some(plan) => {
+                    for entry in &plan.entries {
+                        check_change_mode(&entry.tags, add_only, remove_only)?;
+                        check_placeholders(&entry.tags)?;
+                        check_bundles(&entry.tags, &config.bundles)?;
+                    }
+
+                    plan.describe()
+                }
+                None => {
+                    let tags = tags.unwrap_or_default();
+                    check_change_mode(&tags, add_only, remove_only)?;
+                    check_placeholders(&tags)?;
+                    check_bundles(&tags, &config.bundles)?;
+
+                    tags
+                }
+            };
+
+            // Kept as read so the batch records which submissions were given.
+            let ids = match &ids_file {
+                Some(path) => {
+                    Some(std::fs::read_to_string(path).context("Could not read IDs file")?)
+                }
+                None => ids.as_deref().map(read_ids).transpose()?,
+            };
+
+            let submissions;
+            let mut planned = HashMap::new();
+            let mut filtered_submissions = if let Some(plan) = &plan {
+                submissions = get_submissions(pool, account).await?;
+                planned = plan.run(&submissions, translations, &config.bundles, &config.sort)?;
+                submissions
+                    .iter()
+                    .filter(|sub| planned.contains_key(&(sub.site, sub.id)))
+                    .collect()
+            } else if let Some(search) = &search {
+                submissions = search_submissions(pool, account, translations, search).await?;
+                submissions.iter().collect()
+            } else {
+                let ids = parse_submission_ids(ids.as_deref().unwrap_or_default())?;
+
+                submissions =
+                    filter_submissions(pool, account, |sub| ids.contains(&(sub.site, sub.id)))
+                        .await?;
+                find_submissions(&submissions, &ids)
+            };
+            if !site.is_empty() {
+                filtered_submissions.retain(|sub| site.contains(&sub.site));
+            }
+            let mut filtered_submissions =
+                skip_pinned(pool, account, filtered_submissions, force).await?;
+            sort_submissions(&mut filtered_submissions, order);
+
+            // Queued changes are checked against sites when they are flushed.
+            let filtered_submissions = if queue {
+                filtered_submissions
+            } else {
+                supported_submissions(
+                    sites,
+                    filtered_submissions,
+                    |sub| sub.site,
+                    "tag changes",
+                    |capabilities| capabilities.tags,
+                )
+            };
+
+            let total = filtered_submissions.len();
+            let filtered_submissions: Vec<_> = filtered_submissions
+                .into_iter()
+                .skip(offset)
+                .take(limit.unwrap_or(usize::MAX))
+                .collect();
+            tracing::info!(
+                "Selected {} of {} matched submissions",
+                filtered_submissions.len(),
+                total
+            );
+
+            // Changes are queued on top of anything already queued.
+            let queued: HashMap<_, _> = if queue {
+                pending::load(pool, account)
+                    .await?
+                    .into_iter()
+                    .map(|change| ((change.site, change.id), change.new_tags))
+                    .collect()
+            } else {
+                HashMap::new()
+            };
+
+            let mut changes = Vec::with_capacity(filtered_submissions.len());
+            for sub in filtered_submissions {
+                let current_tags = queued.get(&(sub.site, sub.id)).unwrap_or(&sub.tags);
+                let new_tags = match planned.remove(&(sub.site, sub.id)) {
+                    Some(new_tags) => new_tags,
+                    None => update_tags(sub, current_tags, &tags, &config.bundles, &config.sort),
+                };
+
+                let new_tags = if strip_blocked {
+                    blocklist.strip(&sub.tags, new_tags)
+                } else {
+                    blocklist
+                        .check(&sub.tags, &new_tags)
+                        .with_context(|| format!("Refusing to update {}-{}", sub.site, sub.id))?;
+                    new_tags
+                };
+
+                changes.push((sub, new_tags));
+            }
+
+            if dry_run {
+                let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+                for (sub, new_tags) in changes {
+                    diff.show(sub.site.as_str(), sub.id, &sub.tags, &new_tags);
+                }
+                diff.summary();
+            } else if queue {
+                for (sub, new_tags) in &changes {
+                    pending::queue(pool, account, sub, new_tags, &tags).await?;
+                }
+                tracing::info!(
+                    "Queued changes to {} submissions, push them to sites with flush",
+                    changes.len()
+                );
+            } else {
+                // Moving submissions into a folder still needs a request even
+                // when their tags stay the same.
+                if folder.is_none() {
+                    changes.retain(|(sub, new_tags)| *new_tags != sub.tags);
+                }
+                if changes.is_empty() {
+                    tracing::info!("No submissions need to be updated");
+                    return Ok(());
+                }
+
+                println!("{} submissions will be updated", changes.len());
+
+                if changes.len() > confirm_over
+                    && !yes
+                    && !confirm(&format!("Update {} submissions?", changes.len()))?
+                {
+                    anyhow::bail!("Aborted, no submissions were updated");
+                }
+
+                let batch_id = start_batch(
+                    pool,
+                    account,
+                    "apply-tags",
+                    search
+                        .as_deref()
+                        .or(ids.as_deref())
+                        .or(plan_path.as_deref()),
+                    Some(&tags),
+                )
+                .await?;
+
+                let options = ApplyOptions {
+                    chunking: chunk_size.map(|size| (size, chunk_pause)),
+                    conflict,
+                    window: only_between,
+                    folder,
+                    force,
+                };
+                apply_changes_with(
+                    pool,
+                    sites,
+                    account,
+                    &config.sort,
+                    batch_id,
+                    changes,
+                    options,
+                )
+                .await?;
+            }
+        }
+        Command::Flush {
+            dry_run,
+            conflict,
+            discard,
+            yes,
+        } => {
+            let queued = pending::load(pool, account).await?;
+            if queued.is_empty() {
+                tracing::info!("No changes are queued");
+                return Ok(());
+            }
+
+            if discard {
+                for change in &queued {
+                    pending::remove(pool, account, change.site, change.id).await?;
+                }
+                tracing::info!("Discarded {} queued changes", queued.len());
+                return Ok(());
+            }
+
+            let keys: HashSet<_> = queued
+                .iter()
+                .map(|change| (change.site, change.id))
+                .collect();
+            let submissions =
+                filter_submissions(pool, account, |sub| keys.contains(&(sub.site, sub.id))).await?;
+
+            let mut changes = Vec::with_capacity(queued.len());
+            for change in queued {
+                let sub = match submissions
+                    .iter()
+                    .find(|sub| sub.site == change.site && sub.id == change.id)
+                {
+                    Some(sub) => sub,
+                    None => {
+                        tracing::warn!(
+                            "{}-{} is no longer stored, dropping its queued change",
+                            change.site,
+                            change.id
+                        );
+                        if !dry_run {
+                            pending::remove(pool, account, change.site, change.id).await?;
+                        }
+                        continue;
+                    }
+                };
+
+                if sub.tags == change.new_tags {
+                    if !dry_run {
+                        pending::remove(pool, account, sub.site, sub.id).await?;
+                    }
+                    continue;
+                }
+
+                if tags_diverged(&change.old_tags, &sub.tags) {
+                    tracing::warn!(
+                        "{}-{} was loaded with different tags since it was queued ({})",
+                        sub.site,
+                        sub.id,
+                        change.changes
+                    );
+                }
+
+                blocklist
+                    .check(&sub.tags, &change.new_tags)
+                    .with_context(|| format!("Refusing to update {}-{}", sub.site, sub.id))?;
+
+                changes.push((sub, change.new_tags));
+            }
+
+            let changes = supported_submissions(
+                sites,
+                changes,
+                |(sub, _new_tags)| sub.site,
+                "tag changes",
+                |capabilities| capabilities.tags,
+            );
+
+            if dry_run {
+                let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+                for (sub, new_tags) in changes {
+                    diff.show(sub.site.as_str(), sub.id, &sub.tags, &new_tags);
+                }
+                diff.summary();
+                return Ok(());
+            }
+
+            if changes.is_empty() {
+                tracing::info!("Queued changes are already on every site");
+                return Ok(());
+            }
+
+            if !yes && !confirm(&format!("Push {} queued changes?", changes.len()))? {
+                anyhow::bail!("Aborted, no submissions were updated");
+            }
+
+            let batch_id = start_batch(pool, account, "flush", None, None).await?;
+            let options = ApplyOptions {
+                conflict,
+                ..Default::default()
+            };
+            let result = apply_changes_with(
+                pool,
+                sites,
+                account,
+                &config.sort,
+                batch_id,
+                changes,
+                options,
+            )
+            .await;
+
+            // Changes that reached their site are no longer pending, even if
+            // others failed.
+            let cleared = pending::clear_applied(pool, account, batch_id).await?;
+            tracing::info!("Pushed {} queued changes", cleared);
+            result?;
+        }
+        Command::SyncState { action } => match action {
+            SyncStateAction::Export { output } => {
+                let state = syncstate::export(pool, account).await?;
+                std::fs::write(&output, serde_json::to_string_pretty(&state)?)
+                    .context("Could not write sync state")?;
+                tracing::info!(
+                    path = %output.display(),
+                    "Exported {} queued changes and {} batches",
+                    state.pending.len(),
+                    state.batches.len()
+                );
+            }
+            SyncStateAction::Import { input } => {
+                let data = std::fs::read(&input).context("Could not read sync state")?;
+                let state: syncstate::SyncState =
+                    serde_json::from_slice(&data).context("Sync state was not valid")?;
+
+                let imported = syncstate::import(pool, account, &state).await?;
+                tracing::info!(
+                    "Imported {} batches and {} queued changes, {} queued changes were already applied",
+                    imported.batches,
+                    imported.pending,
+                    imported.cleared
+                );
+            }
+        },
+        Command::CopyTags {
+            dry_run,
+            from_search,
+            to_search,
+            force,
+        } => {
+            let sources = search_submissions(pool, account, translations, &from_search).await?;
+            let tags = union_tags(&sources);
+            tracing::info!(
+                "Found {} tags on {} source submissions",
+                tags.len(),
+                sources.len()
+            );
+
+            let targets = search_submissions(pool, account, translations, &to_search).await?;
+            let targets = supported_submissions(
+                sites,
+                targets.iter().collect(),
+                |sub| sub.site,
+                "tag changes",
+                |capabilities| capabilities.tags,
+            );
+
+            let mut changes = Vec::new();
+            for sub in targets {
+                let existing: Vec<_> = sub.tags.iter().map(|tag| normalize_tag(tag)).collect();
+
+                let mut new_tags = sub.tags.clone();
+                new_tags.extend(
+                    tags.iter()
+                        .filter(|tag| !existing.contains(&normalize_tag(tag)))
+                        .cloned(),
+                );
+
+                if new_tags.len() == sub.tags.len() {
+                    continue;
+                }
+
+                blocklist
+                    .check(&sub.tags, &new_tags)
+                    .with_context(|| format!("Refusing to update {}-{}", sub.site, sub.id))?;
+
+                changes.push((sub, new_tags));
+            }
+
+            if dry_run {
+                let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+                for (sub, new_tags) in changes {
+                    diff.show(sub.site.as_str(), sub.id, &sub.tags, &new_tags);
+                }
+                diff.summary();
+            } else if !changes.is_empty() {
+                let quoted: Vec<_> = tags.iter().map(|tag| quote_tag(tag)).collect();
+                let batch_id = start_batch(
+                    pool,
+                    account,
+                    "copy-tags",
+                    Some(&to_search),
+                    Some(&quoted.join(" ")),
+                )
+                .await?;
+
+                let options = ApplyOptions {
+                    force,
+                    ..Default::default()
+                };
+                apply_changes_with(
+                    pool,
+                    sites,
+                    account,
+                    &config.sort,
+                    batch_id,
+                    changes,
+                    options,
+                )
+                .await?;
+            }
+        }
+        Command::TranslateTags {
+            dry_run,
+            search,
+            force,
+        } => {
+            if translations.is_empty() {
+                anyhow::bail!(
+                    "No translations are configured, set translations in the config file"
+                );
+            }
+
+            let submissions = search_submissions(pool, account, translations, &search).await?;
+            let submissions =
+                skip_pinned(pool, account, submissions.iter().collect(), force).await?;
+            let submissions = supported_submissions(
+                sites,
+                submissions,
+                |sub| sub.site,
+                "tag changes",
+                |capabilities| capabilities.tags,
+            );
+
+            let mut changes = Vec::new();
+            for sub in submissions {
+                let existing: Vec<_> = sub.tags.iter().map(|tag| normalize_tag(tag)).collect();
+
+                // Compared normalized, but added as written in the
+                // translations file.
+                let mut normalized = existing.clone();
+                let mut new_tags = sub.tags.clone();
+                for tag in &existing {
+                    for translation in translations.counterparts(tag) {
+                        if !normalized.contains(translation) {
+                            normalized.push(translation.clone());
+                            new_tags.push(translations.spelling(translation).to_string());
+                        }
+                    }
+                }
+
+                if new_tags.len() == sub.tags.len() {
+                    continue;
+                }
+
+                blocklist
+                    .check(&sub.tags, &new_tags)
+                    .with_context(|| format!("Refusing to update {}-{}", sub.site, sub.id))?;
+
+                changes.push((sub, new_tags));
+            }
+
+            if dry_run {
+                let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+                for (sub, new_tags) in changes {
+                    diff.show(sub.site.as_str(), sub.id, &sub.tags, &new_tags);
+                }
+                diff.summary();
+            } else if !changes.is_empty() {
+                let batch_id =
+                    start_batch(pool, account, "translate-tags", Some(&search), None).await?;
+
+                let options = ApplyOptions {
+                    force,
+                    ..Default::default()
+                };
+                apply_changes_with(
+                    pool,
+                    sites,
+                    account,
+                    &config.sort,
+                    batch_id,
+                    changes,
+                    options,
+                )
+                .await?;
+            }
+        }
+        Command::RunRule {
+            dry_run,
+            older_than,
+            search,
+            tags,
+            force,
+        } => {
+            check_placeholders(&tags)?;
+            check_bundles(&tags, &config.bundles)?;
+
+            let cutoff = older_than.before(chrono::Local::now());
+            let query = search
+                .as_deref()
+                .map(|search| query::Query::parse_with(search, translations))
+                .transpose()?;
+            let submissions = filter_submissions(pool, account, |sub| {
+                sub.posted_at < cutoff && query.as_ref().is_none_or(|query| query.matches(sub))
+            })
+            .await?;
+            tracing::info!(
+                "found {} submissions posted before {}",
+                submissions.len(),
+                cutoff.format("%Y-%m-%d")
+            );
+
+            let submissions =
+                skip_pinned(pool, account, submissions.iter().collect(), force).await?;
+            let submissions = supported_submissions(
+                sites,
+                submissions,
+                |sub| sub.site,
+                "tag changes",
+                |capabilities| capabilities.tags,
+            );
+
+            let mut changes = Vec::new();
+            for sub in submissions {
+                let new_tags = update_tags(sub, &sub.tags, &tags, &config.bundles, &config.sort);
+                if new_tags == sub.tags {
+                    continue;
+                }
+
+                blocklist
+                    .check(&sub.tags, &new_tags)
+                    .with_context(|| format!("Refusing to update {}-{}", sub.site, sub.id))?;
+
+                changes.push((sub, new_tags));
+            }
+
+            if dry_run {
+                let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+                for (sub, new_tags) in changes {
+                    diff.show(sub.site.as_str(), sub.id, &sub.tags, &new_tags);
+                }
+                diff.summary();
+            } else if !changes.is_empty() {
+                let rule = match &search {
+                    Some(search) => format!("older-than:{} {}", older_than, search),
+                    None => format!("older-than:{}", older_than),
+                };
+                let batch_id =
+                    start_batch(pool, account, "run-rule", Some(&rule), Some(&tags)).await?;
+
+                let options = ApplyOptions {
+                    force,
+                    ..Default::default()
+                };
+                apply_changes_with(
+                    pool,
+                    sites,
+                    account,
+                    &config.sort,
+                    batch_id,
+                    changes,
+                    options,
+                )
+                .await?;
+            }
+        }
+        Command::BackfillDates {
+            dry_run,
+            format,
+            site,
+            force,
+        } => {
+            check_placeholders(&format)?;
+
+            let submissions = filter_submissions(pool, account, |sub| {
+                site.is_empty() || site.contains(&sub.site)
+            })
+            .await?;
+
+            let submissions = supported_submissions(
+                sites,
+                submissions.iter().collect(),
+                |sub| sub.site,
+                "tag changes",
+                |capabilities| capabilities.tags,
+            );
+
+            let mut changes = Vec::new();
+            for sub in submissions {
+                let date_tag = expand_placeholders(&format, sub);
+                if sub
+                    .tags
+                    .iter()
+                    .any(|tag| normalize_tag(tag) == normalize_tag(&date_tag))
+                {
+                    continue;
+                }
+
+                let mut new_tags = sub.tags.clone();
+                new_tags.push(date_tag);
+                blocklist
+                    .check(&sub.tags, &new_tags)
+                    .with_context(|| format!("Refusing to update {}-{}", sub.site, sub.id))?;
+
+                changes.push((sub, new_tags));
+            }
+
+            tracing::info!("{} submissions are missing a date tag", changes.len());
+
+            if dry_run {
+                let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+                for (sub, new_tags) in changes {
+                    diff.show(sub.site.as_str(), sub.id, &sub.tags, &new_tags);
+                }
+                diff.summary();
+            } else if !changes.is_empty() {
+                let batch_id =
+                    start_batch(pool, account, "backfill-dates", None, Some(&format)).await?;
+
+                let options = ApplyOptions {
+                    force,
+                    ..Default::default()
+                };
+                apply_changes_with(
+                    pool,
+                    sites,
+                    account,
+                    &config.sort,
+                    batch_id,
+                    changes,
+                    options,
+                )
+                .await?;
+            }
+        }
+        Command::MoveFolder {
+            dry_run,
+            search,
+            folder,
+            site,
+        } => {
+            let client = sites.get(site)?;
+            if !client.capabilities().folders {
+                anyhow::bail!("{} does not support folders", site);
+            }
+
+            let mut filtered_submissions =
+                search_submissions(pool, account, translations, &search).await?;
+            filtered_submissions.retain(|sub| sub.site == site);
+
+            let site_folders = client.list_folders().await.unwrap_or_else(|err| {
+                tracing::warn!("Could not load folders from site: {:?}", err);
+                Vec::new()
+            });
+            let stored_folders = filter_submissions(pool, account, |sub| sub.site == site)
+                .await?
+                .into_iter()
+                .filter_map(|sub| Some((sub.folder_id?, sub.folder_name?)));
+
+            let (folder_id, folder_name) =
+                match find_folder(site_folders.into_iter().chain(stored_folders), &folder) {
+                    Some((folder_id, folder_name)) => (folder_id, Some(folder_name)),
+                    None => match folder.parse::<i32>() {
+                        Ok(folder_id) => (folder_id, None),
+                        Err(_) => anyhow::bail!("Unknown folder: {}", folder),
+                    },
+                };
+
+            if !dry_run && !filtered_submissions.is_empty() {
+                client
+                    .verify_ownership()
+                    .await
+                    .context("Refusing to move submissions")?;
+            }
+
+            for sub in filtered_submissions {
+                let _span =
+                    tracing::info_span!("Moving folder", id = sub.id, site = %sub.site).entered();
+
+                if sub.folder_id == Some(folder_id) {
+                    tracing::debug!("Submission was already in folder");
+                    continue;
+                }
+
+                tracing::info!(
+                    "Moving from {} to {}",
+                    sub.folder_name.as_deref().unwrap_or("no folder"),
+                    folder_name.as_deref().unwrap_or(&folder)
+                );
+
+                if dry_run {
+                    continue;
+                }
+
+                client.move_folder(sub.id, folder_id).await?;
+
+                let site = sub.site.as_str();
+                sqlx::query!(
+                    "UPDATE submission SET folder_id = $1, folder_name = $2 WHERE account = $3 AND site = $4 AND id = $5",
+                    folder_id,
+                    folder_name,
+                    account,
+                    site,
+                    sub.id
+                )
+                .execute(pool)
+                .await?;
+            }
+        }
+        Command::SetMetadata {
+            dry_run,
+            search,
+            title,
+            description,
+            rating,
+            fields,
+            site,
+            force,
+        } => {
+            let changes = sites::MetadataChanges {
+                title,
+                description,
+                rating,
+                extras: fields.into_iter().collect(),
+            };
+            if changes.is_empty() {
+                anyhow::bail!("No metadata changes were given");
+            }
+
+            let submissions = search_submissions(pool, account, translations, &search).await?;
+            let mut filtered_submissions: Vec<_> = submissions.iter().collect();
+            if !site.is_empty() {
+                filtered_submissions.retain(|sub| site.contains(&sub.site));
+            }
+            let filtered_submissions =
+                skip_pinned(pool, account, filtered_submissions, force).await?;
+            let filtered_submissions = supported_submissions(
+                sites,
+                filtered_submissions,
+                |sub| sub.site,
+                "metadata changes",
+                |capabilities| capabilities.descriptions || capabilities.ratings,
+            );
+
+            tracing::info!(
+                "Changing {} on {} submissions",
+                changes.fields().join(", "),
+                filtered_submissions.len()
+            );
+
+            let mut verified = HashSet::new();
+            for sub in filtered_submissions {
+                let _span = tracing::info_span!("Updating metadata", id = sub.id, site = %sub.site)
+                    .entered();
+
+                if dry_run {
+                    tracing::info!("Would update {}", sub.title);
+                    continue;
+                }
+
+                let client = sites.get(sub.site)?;
+                if verified.insert(sub.site) {
+                    client
+                        .verify_ownership()
+                        .await
+                        .with_context(|| format!("Refusing to update {} submissions", sub.site))?;
+                } else {
+                    tokio::time::sleep(client.update_delay()).await;
+                }
+
+                let unsupported = client.update_metadata(sub.id, changes.clone()).await?;
+                if !unsupported.is_empty() {
+                    tracing::warn!(
+                        "{} does not support changing {}",
+                        sub.site,
+                        unsupported.join(", ")
+                    );
+                }
+
+                let title_changed = !unsupported.iter().any(|field| field == "title");
+                if let Some(title) = changes.title.as_ref().filter(|_| title_changed) {
+                    let site = sub.site.as_str();
+                    sqlx::query!(
+                        "UPDATE submission SET title = $1 WHERE account = $2 AND site = $3 AND id = $4",
+                        title,
+                        account,
+                        site,
+                        sub.id
+                    )
+                    .execute(pool)
+                    .await?;
+                }
+            }
+        }
+        Command::Edit { site, id } => {
+            let client = sites.get(site)?;
+            if !client.capabilities().tags {
+                anyhow::bail!("{} does not support tag changes", site);
+            }
+
+            let sub = client.get_submission(id).await?;
+            let new_tags = edit_tags(&sub)?;
+
+            if new_tags == sub.tags {
+                tracing::info!("Tags were not changed");
+                return Ok(());
+            }
+
+            blocklist
+                .check(&sub.tags, &new_tags)
+                .with_context(|| format!("Refusing to update {}-{}", sub.site, sub.id))?;
+
+            let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+            diff.show(sub.site.as_str(), sub.id, &sub.tags, &new_tags);
+
+            // Pins only keep submissions out of bulk changes, not one picked
+            // to edit by hand.
+            let batch_id = start_batch(pool, account, "edit", None, None).await?;
+            let options = ApplyOptions {
+                force: true,
+                ..Default::default()
+            };
+            apply_changes_with(
+                pool,
+                sites,
+                account,
+                &config.sort,
+                batch_id,
+                vec![(&sub, new_tags)],
+                options,
+            )
+            .await?;
+        }
+        Command::WriteMetadata { search, dry_run } => {
+            let submissions = match search {
+                Some(search) => search_submissions(pool, account, translations, &search).await?,
+                None => get_submissions(pool, account).await?,
+            };
+
+            let mut updated = 0;
+            for sub in &submissions {
+                let _span = tracing::info_span!("Writing metadata", id = sub.id, site = %sub.site)
+                    .entered();
+
+                let site = sub.site.as_str();
+                let path = match sqlx::query_scalar!(
+                    "SELECT path FROM submission_file WHERE site = $1 AND id = $2",
+                    site,
+                    sub.id
+                )
+                .fetch_optional(pool)
+                .await?
+                {
+                    Some(path) => std::path::PathBuf::from(path),
+                    None => {
+                        tracing::debug!("Submission had no cached file, skipping");
+                        continue;
+                    }
+                };
+
+                match write_file_keywords(&path, &sub.tags, dry_run) {
+                    Ok(true) => updated += 1,
+                    Ok(false) => tracing::debug!("File keywords were already up to date"),
+                    Err(err) => tracing::warn!("Could not write metadata: {:?}", err),
+                }
+            }
+
+            tracing::info!(dry_run, "Updated keywords in {} files", updated);
+        }
+        Command::TagCasing { tags: None, .. } => {
+            let casings = sqlx::query!(
+                "SELECT normalized, name FROM tag_casing WHERE account = $1 ORDER BY normalized",
+                account
+            )
+            .fetch_all(pool)
+            .await?;
+
+            for casing in casings {
+                tracing::info!("{}: {}", casing.normalized, casing.name);
+            }
+        }
+        Command::TagCasing {
+            tags: Some(tags),
+            clear,
+        } => {
+            for tag in split_tags(&tags) {
+                let normalized = normalize_tag(&tag);
+
+                if clear {
+                    sqlx::query!(
+                        "DELETE FROM tag_casing WHERE account = $1 AND normalized = $2",
+                        account,
+                        normalized
+                    )
+                    .execute(pool)
+                    .await?;
+                } else {
+                    sqlx::query!(
+                        "INSERT INTO tag_casing (account, normalized, name) VALUES ($1, $2, $3)
+                            ON CONFLICT (account, normalized) DO UPDATE SET name = excluded.name",
+                        account,
+                        normalized,
+                        tag
+                    )
+                    .execute(pool)
+                    .await?;
+                }
+            }
+        }
+        Command::Pin { ids: None, .. } => {
+            let pins = sqlx::query!(
+                r#"SELECT site, id, reason, pinned_at AS "pinned_at: chrono::DateTime<chrono::Utc>"
+                    FROM submission_pin WHERE account = $1 ORDER BY site, id"#,
+                account
+            )
+            .fetch_all(pool)
+            .await?;
+
+            for pin in pins {
+                tracing::info!(
+                    "{}-{} pinned {}: {}",
+                    pin.site,
+                    pin.id,
+                    pin.pinned_at
+                        .with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d"),
+                    pin.reason.as_deref().unwrap_or("no reason given")
+                );
+            }
+        }
+        Command::Pin {
+            ids: Some(ids),
+            reason,
+            remove,
+        } => {
+            let ids = parse_submission_ids(&read_ids(&ids)?)?;
+            let now = chrono::Utc::now();
+
+            for (site, id) in ids {
+                let site = site.as_str();
+
+                if remove {
+                    sqlx::query!(
+                        "DELETE FROM submission_pin WHERE account = $1 AND site = $2 AND id = $3",
+                        account,
+                        site,
+                        id
+                    )
+                    .execute(pool)
+                    .await?;
+                } else {
+                    sqlx::query!(
+                        "INSERT INTO submission_pin (account, site, id, reason, pinned_at) VALUES ($1, $2, $3, $4, $5)
+                            ON CONFLICT (account, site, id) DO UPDATE SET reason = excluded.reason",
+                        account,
+                        site,
+                        id,
+                        reason,
+                        now
+                    )
+                    .execute(pool)
+                    .await?;
+                }
+            }
+        }
+        Command::Note {
+            search,
+            ids,
+            tags,
+            note,
+            clear_note,
+        } => {
+            if let Some(tags) = &tags {
+                check_placeholders(tags)?;
+                check_bundles(tags, &config.bundles)?;
+            }
+
+            let submissions;
+            let filtered_submissions = match (search, ids) {
+                (Some(search), _) => {
+                    submissions = search_submissions(pool, account, translations, &search).await?;
+                    submissions.iter().collect()
+                }
+                (None, ids) => {
+                    let ids = parse_submission_ids(&read_ids(&ids.unwrap_or_default())?)?;
+
+                    submissions =
+                        filter_submissions(pool, account, |sub| ids.contains(&(sub.site, sub.id)))
+                            .await?;
+                    find_submissions(&submissions, &ids)
+                }
+            };
+
+            for sub in filtered_submissions {
+                let local_tags = match &tags {
+                    Some(tags) => {
+                        update_tags(sub, &sub.local_tags, tags, &config.bundles, &config.sort)
+                    }
+                    None => sub.local_tags.clone(),
+                };
+                let notes = match (&note, clear_note) {
+                    (_, true) => None,
+                    (Some(note), _) => Some(note.clone()),
+                    (None, false) => sub.notes.clone(),
+                };
+
+                tracing::info!(
+                    id = sub.id,
+                    site = %sub.site,
+                    "Setting local tags to: {}",
+                    local_tags.join(", ")
+                );
+
+                let site = sub.site.as_str();
+                let local_tags = serde_json::to_value(&local_tags)?;
+                sqlx::query!(
+                    "INSERT INTO submission_note (site, id, local_tags, notes) VALUES ($1, $2, $3, $4)
+                        ON CONFLICT (site, id) DO UPDATE SET local_tags = excluded.local_tags, notes = excluded.notes",
+                    site,
+                    sub.id,
+                    local_tags,
+                    notes
+                )
+                .execute(pool)
+                .await?;
+            }
+        }
+        Command::Tui { dry_run } => {
+            let mut submissions = get_submissions(pool, account).await?;
+            submissions.sort_by_key(|sub| std::cmp::Reverse(sub.posted_at));
+
+            let changes = tui::run(&submissions, translations, &config.bundles, &config.sort)?;
+            for (sub, new_tags) in &changes {
+                blocklist
+                    .check(&sub.tags, new_tags)
+                    .with_context(|| format!("Refusing to update {}-{}", sub.site, sub.id))?;
+            }
+            tracing::info!("Applying {} staged changes", changes.len());
+
+            if dry_run {
+                let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+                for (sub, new_tags) in changes {
+                    diff.show(sub.site.as_str(), sub.id, &sub.tags, &new_tags);
+                }
+                diff.summary();
+            } else if !changes.is_empty() {
+                let batch_id = start_batch(pool, account, "tui", None, None).await?;
+
+                apply_changes(pool, sites, account, &config.sort, batch_id, changes).await?;
+            }
+        }
+        Command::Backup { output } => {
+            let path = output
+                .or_else(|| {
+                    db_path
+                        .as_deref()
+                        .map(|db_path| backup::default_path(db_path, db_passphrase.is_some()))
+                })
+                .context("In-memory databases can only be backed up with --output")?;
+            backup::backup(pool, &path, db_passphrase.as_deref()).await?;
+        }
+        Command::Restore { input } => {
+            let db_path = db_path
+                .as_deref()
+                .context("Backups can't be restored into an in-memory database")?;
+            backup::backup(
+                pool,
+                &backup::default_path(db_path, db_passphrase.is_some()),
+                db_passphrase.as_deref(),
+            )
+            .await?;
+            pool.close().await;
+
+            backup::restore(&input, db_path, db_passphrase.as_deref())?;
+        }
+        Command::Doctor { repair, delete } => {
+            let mut corrupt = Vec::new();
+
+            let mut rows = sqlx::query!(
+                "SELECT site, id FROM submission WHERE account = $1",
+                account
+            )
+            .fetch(pool);
+
+            while let Some(row) = rows.try_next().await? {
+                let site = row.site.parse::<SubmissionSite>().ok();
+
+                let problem = match site {
+                    Some(site) if site.as_str() == row.site => continue,
+                    Some(_) => "site name has the wrong case",
+                    None => "unknown site",
+                };
+
+                tracing::warn!("{}-{}: {}", row.site, row.id, problem);
+                corrupt.push((row.site, row.id, site));
+            }
+            drop(rows);
+
+            let mut corrupt_notes = Vec::new();
+
+            let mut rows =
+                sqlx::query!("SELECT site, id, local_tags FROM submission_note").fetch(pool);
+
+            while let Some(row) = rows.try_next().await? {
+                if serde_json::from_str::<Vec<String>>(&row.local_tags).is_err() {
+                    tracing::warn!("{}-{}: local tags are malformed", row.site, row.id);
+                    corrupt_notes.push((row.site, row.id));
+                }
+            }
+            drop(rows);
+
+            tracing::info!(
+                "Found {} corrupt submissions and {} corrupt notes",
+                corrupt.len(),
+                corrupt_notes.len()
+            );
+
+            for (site, id, parsed_site) in corrupt {
+                let mut site = site;
+
+                if repair {
+                    if let Some(parsed_site) = parsed_site.filter(|parsed| parsed.as_str() != site)
+                    {
+                        let new_site = parsed_site.as_str();
+                        let updated = sqlx::query!(
+                            "UPDATE OR IGNORE submission SET site = $1 WHERE account = $2 AND site = $3 AND id = $4",
+                            new_site,
+                            account,
+                            site,
+                            id
+                        )
+                        .execute(pool)
+                        .await?
+                        .rows_affected();
+
+                        if updated > 0 {
+                            sqlx::query!(
+                                "UPDATE OR IGNORE submission_tag SET site = $1 WHERE account = $2 AND site = $3 AND id = $4",
+                                new_site,
+                                account,
+                                site,
+                                id
+                            )
+                            .execute(pool)
+                            .await?;
+
+                            tracing::info!("Repaired site name for {}-{}", site, id);
+                            site = new_site.to_string();
+                        }
+                    }
+                }
+
+                let repaired = parsed_site
+                    .map(|parsed| parsed.as_str() == site)
+                    .unwrap_or(false);
+
+                if repaired {
+                    continue;
+                }
+
+                if delete {
+                    tracing::info!("Deleting {}-{}", site, id);
+                    sqlx::query!(
+                        "DELETE FROM submission WHERE account = $1 AND site = $2 AND id = $3",
+                        account,
+                        site,
+                        id
+                    )
+                    .execute(pool)
+                    .await?;
+                    sqlx::query!(
+                        "DELETE FROM submission_tag WHERE account = $1 AND site = $2 AND id = $3",
+                        account,
+                        site,
+                        id
+                    )
+                    .execute(pool)
+                    .await?;
+                } else {
+                    tracing::warn!(
+                        "{}-{} could not be repaired, reload submissions or use --delete",
+                        site,
+                        id
+                    );
+                }
+            }
+
+            if delete {
+                for (site, id) in corrupt_notes {
+                    tracing::info!("Deleting note for {}-{}", site, id);
+                    sqlx::query!(
+                        "DELETE FROM submission_note WHERE site = $1 AND id = $2",
+                        site,
+                        id
+                    )
+                    .execute(pool)
+                    .await?;
+                }
+            }
+        }
+        Command::History {
+            batch: None,
+            runs: true,
+            limit,
+        } => {
+            for run in runs::recent(pool, account, limit).await? {
+                tracing::info!("{}", run.describe());
+                tracing::info!("  Arguments: {}", run.args);
+                if let Some(error) = &run.error {
+                    tracing::info!("  Error: {}", error);
+                }
+            }
+        }
+        Command::History { batch, limit, .. } => match batch {
+            Some(batch_id) => {
+                let changes = sqlx::query!(
+                    r#"SELECT batch_change.site, batch_change.id, batch_change.old_tags, batch_change.new_tags, batch_change.changed_at
+                        FROM batch_change
+                        JOIN batch ON batch.id = batch_change.batch_id
+                        WHERE batch.account = $1 AND batch.id = $2
+                        ORDER BY batch_change.changed_at"#,
+                    account,
+                    batch_id
+                )
+                .fetch_all(pool)
+                .await?;
+
+                tracing::info!("Batch {} changed {} submissions", batch_id, changes.len());
+
+                let mut diff = diff::DiffRenderer::new(ctx.diff_format);
+                for change in changes {
+                    let old_tags: Vec<String> = serde_json::from_str(&change.old_tags)?;
+                    let new_tags: Vec<String> = serde_json::from_str(&change.new_tags)?;
+
+                    diff.show(&change.site, change.id, &old_tags, &new_tags);
+                }
+                diff.summary();
+            }
+            None => {
+                for run in runs::recent(pool, account, limit).await? {
+                    if run.unfinished() {
+                        tracing::warn!("{}, see history --runs", run.describe());
+                    }
+                }
+
+                let batches = sqlx::query!(
+                    r#"SELECT id, command, search, changes, started_at,
+                            (SELECT COUNT(*) FROM batch_change WHERE batch_change.batch_id = batch.id) AS "count!: i64"
+                        FROM batch
+                        WHERE account = $1
+                        ORDER BY id DESC
+                        LIMIT $2"#,
+                    account,
+                    limit
+                )
+                .fetch_all(pool)
+                .await?;
+
+                for batch in batches {
+                    tracing::info!(
+                        "Batch {} - {} {}, search: {}, changes: {}, {} submissions",
+                        batch.id,
+                        batch.started_at.format("%Y-%m-%d %H:%M:%S"),
+                        batch.command,
+                        batch.search.as_deref().unwrap_or("none"),
+                        batch.changes.as_deref().unwrap_or("none"),
+                        batch.count
+                    );
+                }
+            }
+        },
+        Command::Auth { site, port, scope } => match site {
+            SubmissionSite::Weasyl => {
+                auth::authorize_weasyl(pool, account, profile, port, &scope).await?;
+                tracing::info!("Saved Weasyl tokens for profile {}", account);
+            }
+            site => anyhow::bail!("{} does not support authorizing in the browser", site),
+        },
+        Command::Login {
+            site,
+            username,
+            password,
+            totp_code,
+        } => match site {
+            SubmissionSite::FurAffinity => {
+                let username = match username.or_else(|| profile.furaffinity_user.clone()) {
+                    Some(username) => username,
+                    None => prompt("Username", false)?,
+                };
+                let password = match password {
+                    Some(password) => password,
+                    None => prompt("Password", true)?,
+                };
+
+                auth::login_furaffinity(pool, account, &username, &password, totp_code).await?;
+                tracing::info!("Saved FurAffinity session for profile {}", account);
+            }
+            site => anyhow::bail!("{} does not support logging in", site),
+        },
+        Command::Completions { .. }
+        | Command::Man
+        | Command::Serve { .. }
+        | Command::RunScript { .. } => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// A length of time written as a number and unit, such as `90d` or `3y`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Age {
+    amount: u32,
+    unit: char,
+}
+
+impl Age {
+    /// The time this long before `now`. Months and years follow the calendar,
+    /// using the last day of the month when the day does not exist.
+    fn before(&self, now: chrono::DateTime<chrono::Local>) -> chrono::DateTime<chrono::Local> {
+        use chrono::Datelike;
+
+        let months = match self.unit {
+            'd' => return now - chrono::Duration::days(self.amount.into()),
+            'w' => return now - chrono::Duration::weeks(self.amount.into()),
+            'm' => self.amount as i32,
+            _ => self.amount as i32 * 12,
+        };
+
+        let total = now.year() * 12 + now.month0() as i32 - months;
+        let (year, month) = (total.div_euclid(12), total.rem_euclid(12) as u32 + 1);
+
+        (1..=now.day())
+            .rev()
+            .find_map(|day| {
+                now.with_day(1)?
+                    .with_year(year)?
+                    .with_month(month)?
+                    .with_day(day)
+            })
+            .unwrap_or(now)
+    }
+}
+
+impl std::str::FromStr for Age {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unit = s
+            .chars()
+            .last()
+            .filter(|unit| matches!(unit, 'd' | 'w' | 'm' | 'y'))
+            .ok_or_else(|| anyhow::anyhow!("Age must end with d, w, m, or y"))?;
+        let amount = s[..s.len() - 1]
+            .parse()
+            .with_context(|| format!("Invalid age: {}", s))?;
+
+        Ok(Self { amount, unit })
+    }
+}
+
+impl std::fmt::Display for Age {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.amount, self.unit)
+    }
+}
+
+/// A daily time window, such as `02:00-06:00`. Windows ending before they
+/// start continue past midnight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct TimeWindow {
+    start: chrono::NaiveTime,
+    end: chrono::NaiveTime,
+}
+
+impl TimeWindow {
+    fn contains(&self, time: chrono::NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// How long to wait from `now` until the window opens, if it is closed.
+    fn wait(&self, now: chrono::NaiveTime) -> Option<std::time::Duration> {
+        if self.contains(now) {
+            return None;
+        }
+
+        let mut wait = self.start - now;
+        if wait < chrono::Duration::zero() {
+            wait = wait + chrono::Duration::days(1);
+        }
+
+        wait.to_std().ok()
+    }
+}
+
+impl std::str::FromStr for TimeWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| anyhow::anyhow!("Time window must look like 02:00-06:00"))?;
+        let parse = |time: &str| {
+            chrono::NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .with_context(|| format!("Invalid time: {}", time))
+        };
+
+        let window = Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        if window.start == window.end {
+            anyhow::bail!("Time window must not be empty");
+        }
+
+        Ok(window)
+    }
+}
+
+impl std::fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+/// Parse a pause written as a number and unit, such as `30s`, `10m`, or `1h`.
+fn parse_pause(s: &str) -> anyhow::Result<std::time::Duration> {
+    let (amount, seconds) = match s.chars().last() {
+        Some('s') => (&s[..s.len() - 1], 1),
+        Some('m') => (&s[..s.len() - 1], 60),
+        Some('h') => (&s[..s.len() - 1], 60 * 60),
+        _ => anyhow::bail!("Pause must end with s, m, or h"),
+    };
+    let amount: u64 = amount
+        .parse()
+        .with_context(|| format!("Invalid pause: {}", s))?;
+
+    Ok(std::time::Duration::from_secs(amount * seconds))
+}
+
+/// Parse a site-specific field change written as `name=value`.
+fn parse_field(s: &str) -> anyhow::Result<(String, String)> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Field must look like name=value"))?;
+    if name.trim().is_empty() {
+        anyhow::bail!("Field name must not be empty");
+    }
+
+    Ok((normalize_tag(name), value.to_string()))
+}
+
+/// Percentage of previously stored submissions missing from a fresh load, if
+/// any were lost.
+fn dropped_percent(previous: i64, loaded: usize) -> Option<f64> {
+    let loaded = loaded as i64;
+    if previous == 0 || loaded >= previous {
+        return None;
+    }
+
+    Some((previous - loaded) as f64 / previous as f64 * 100.0)
+}
+
+/// Number of stored submissions rated mature or adult that are missing from a
+/// fresh load which has none with those ratings, suggesting the session is
+/// filtering them out rather than that they were deleted.
+fn missing_restricted(stored: &[Submission], loaded: &[Submission]) -> usize {
+    let restricted = |sub: &&Submission| {
+        matches!(
+            sub.site_data.get("rating").map(String::as_str),
+            Some("mature" | "adult")
+        )
+    };
+
+    if loaded.iter().any(|sub| restricted(&sub)) {
+        return 0;
+    }
+
+    let loaded: HashSet<i64> = loaded.iter().map(|sub| sub.id).collect();
+    stored
+        .iter()
+        .filter(restricted)
+        .filter(|sub| !loaded.contains(&sub.id))
+        .count()
+}
+
+/// Percentage a loaded count may differ from the count a site reports before
+/// warning, allowing for submissions posted or removed while loading.
+const COUNT_TOLERANCE: f64 = 5.0;
+
+/// Percentage a loaded count differs from the count a site reports, negative
+/// when fewer were loaded, if it differs by more than [`COUNT_TOLERANCE`].
+fn count_difference(reported: usize, loaded: usize) -> Option<f64> {
+    if reported == 0 {
+        return None;
+    }
+
+    let difference = (loaded as f64 - reported as f64) / reported as f64 * 100.0;
+    (difference.abs() > COUNT_TOLERANCE).then_some(difference)
+}
+
+/// Differences between stored submissions and a fresh load from a site.
+#[derive(Debug, Default)]
+struct SyncChanges<'a> {
+    /// Loaded submissions that were not stored.
+    added: Vec<&'a Submission>,
+    /// Stored submissions that were not loaded.
+    removed: Vec<&'a Submission>,
+    /// Stored and loaded versions of submissions whose tags differ.
+    retagged: Vec<(&'a Submission, &'a Submission)>,
+}
+
+impl<'a> SyncChanges<'a> {
+    fn new(stored: &'a [Submission], loaded: &'a [Submission]) -> Self {
+        let stored_by_id: HashMap<_, _> = stored.iter().map(|sub| (sub.id, sub)).collect();
+        let loaded_ids: HashSet<_> = loaded.iter().map(|sub| sub.id).collect();
+
+        let mut changes = Self {
+            removed: stored
+                .iter()
+                .filter(|sub| !loaded_ids.contains(&sub.id))
+                .collect(),
+            ..Default::default()
+        };
+
+        for sub in loaded {
+            match stored_by_id.get(&sub.id) {
+                None => changes.added.push(sub),
+                Some(old) if old.tags != sub.tags => changes.retagged.push((old, sub)),
+                Some(_) => (),
+            }
+        }
+
+        changes
+    }
+}
+
+/// Replace all stored submissions for a single site, leaving other sites
+/// untouched. Stored submissions that were not loaded are marked as deleted,
+/// returning how many were newly marked.
+async fn replace_site_submissions(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    site: SubmissionSite,
+    submissions: &[Submission],
+) -> error::Result<u64> {
+    let site = site.as_str();
+    let now = chrono::Utc::now();
+
+    let mut tx = pool.begin().await?;
+    sqlx::query!(
+        "UPDATE submission SET deleted_at = $3 WHERE account = $1 AND site = $2 AND deleted_at IS NULL",
+        account,
+        site,
+        now
+    )
+    .execute(&mut tx)
+    .await?;
+
+    for submission in submissions {
+        let posted_at = chrono::DateTime::<chrono::Utc>::from(submission.posted_at);
+        // Sites that did not provide any data keep what was stored before.
+        let site_data = if submission.site_data.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(&submission.site_data)?)
+        };
+        let visibility = submission.visibility.as_str();
+
+        sqlx::query!(
+            "INSERT INTO submission (account, site, id, title, posted_at, file_url, folder_id, folder_name, site_data, visibility) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                ON CONFLICT (account, site, id) DO UPDATE SET title = excluded.title, posted_at = excluded.posted_at, file_url = excluded.file_url, folder_id = excluded.folder_id, folder_name = excluded.folder_name, site_data = coalesce(excluded.site_data, submission.site_data), visibility = excluded.visibility, deleted_at = NULL",
+            account, site, submission.id, submission.title, posted_at, submission.file_url, submission.folder_id, submission.folder_name, site_data, visibility
+        ).execute(&mut tx).await?;
+
+        store_tags(
+            &mut tx,
+            account,
+            submission.site,
+            submission.id,
+            &submission.tags,
+        )
+        .await?;
+    }
+
+    let deleted = sqlx::query!(
+        "SELECT count(*) AS count FROM submission WHERE account = $1 AND site = $2 AND deleted_at = $3",
+        account,
+        site,
+        now
+    )
+    .fetch_one(&mut tx)
+    .await?
+    .count;
+
+    tx.commit().await?;
+
+    Ok(deleted as u64)
+}
+
+/// Open a submission's tags in the user's editor, one per line, and return the
+/// saved tags.
+fn edit_tags(sub: &Submission) -> anyhow::Result<Vec<String>> {
+    use std::io::Write;
+
+    // A random name that must not exist yet, so another user can't have
+    // put a file or link in its place.
+    let path = std::env::temp_dir().join(format!(
+        "batch-tagger-{}-{}-{}.txt",
+        sub.site.as_str().to_lowercase(),
+        sub.id,
+        uuid::Uuid::new_v4().simple()
+    ));
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&path).context("Could not create tags file")?;
+
+    let mut contents = format!(
+        "# Tags for {}-{} \"{}\", one per line. Lines starting with # are ignored.\n",
+        sub.site, sub.id, sub.title
+    );
+    for tag in &sub.tags {
+        contents.push_str(tag);
+        contents.push('\n');
+    }
+    let written = file.write_all(contents.as_bytes());
+    drop(file);
+    if let Err(err) = written {
+        let _ = std::fs::remove_file(&path);
+        return Err(err).context("Could not write tags file");
+    }
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+
+    // Run through the shell so editors configured with arguments work.
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status()
+        .context("Could not start editor")?;
+
+    let contents = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+
+    if !status.success() {
+        anyhow::bail!("Editor exited with {}, no changes were made", status);
+    }
+
+    Ok(parse_edited_tags(
+        &contents.context("Could not read tags file")?,
+    ))
+}
+
+/// Ask a yes or no question on the terminal, defaulting to no. Fails instead
+/// of reading an answer from stdin that isn't a terminal or was already read
+/// to the end, such as when it was used for `--ids -`.
+fn confirm(question: &str) -> anyhow::Result<bool> {
+    use std::io::{IsTerminal, Write};
+
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("Can't ask for confirmation without a terminal, use --yes to skip it");
+    }
+
+    // Kept off stdout, which may be piped somewhere else.
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush()?;
+
+    let mut answer = String::new();
+    let read = std::io::stdin()
+        .read_line(&mut answer)
+        .context("Could not read confirmation, use --yes to skip it")?;
+    if read == 0 {
+        anyhow::bail!("Stdin was already closed, use --yes to skip confirmation");
+    }
+
+    Ok(is_confirmed(&answer))
+}
+
+fn is_confirmed(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Ask for a line of input on the terminal, without showing what is typed if
+/// it is secret.
+fn prompt(question: &str, secret: bool) -> anyhow::Result<String> {
+    use std::io::Write;
+
+    // Kept off stdout like confirm, so piped output stays clean.
+    eprint!("{}: ", question);
+    std::io::stderr().flush()?;
+
+    if !secret {
+        let mut answer = String::new();
+        std::io::stdin()
+            .read_line(&mut answer)
+            .context("Could not read answer")?;
+
+        return Ok(answer.trim().to_string());
+    }
+
+    crossterm::terminal::enable_raw_mode()?;
+    let answer = read_secret();
+    crossterm::terminal::disable_raw_mode()?;
+    eprintln!();
+
+    answer
+}
+
+fn read_secret() -> anyhow::Result<String> {
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+    let mut answer = String::new();
+
+    loop {
+        match crossterm::event::read()? {
+            Event::Key(KeyEvent {
+                kind: KeyEventKind::Release,
+                ..
+            }) => (),
+            Event::Key(KeyEvent {
+                code: KeyCode::Enter,
+                ..
+            }) => return Ok(answer),
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('c'),
+                modifiers,
+                ..
+            }) if modifiers.contains(KeyModifiers::CONTROL) => anyhow::bail!("Cancelled"),
+            Event::Key(KeyEvent {
+                code: KeyCode::Backspace,
+                ..
+            }) => {
+                answer.pop();
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                ..
+            }) => answer.push(c),
+            _ => (),
+        }
+    }
+}
+
+/// Open a URL with the platform's default handler, usually the browser.
+fn open_url(url: &str) -> anyhow::Result<()> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+
+    let status = command
+        .arg(url)
+        .status()
+        .context("Could not start browser")?;
+    if !status.success() {
+        anyhow::bail!("Opening {} exited with {}", url, status);
+    }
+
+    Ok(())
+}
+
+fn parse_edited_tags(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Location of the database in the platform data directory.
+fn default_db_path() -> anyhow::Result<std::path::PathBuf> {
+    let dirs = directories::ProjectDirs::from("net", "Syfaro", env!("CARGO_PKG_NAME"))
+        .context("Could not find a data directory, use --db-path instead")?;
+
+    Ok(dirs.data_dir().join("submissions.db"))
+}
+
+/// Version of [`normalize_tag`] that stored normalized tags were computed
+/// with, kept as the database's `user_version`. Raise it whenever
+/// normalization changes so stored values are recomputed once.
+const TAG_NORMALIZATION_VERSION: i64 = 1;
+
+/// Recompute normalized tags and tag casing keys written by an older
+/// [`normalize_tag`], as searches filter on them in the database and new
+/// writes never replace existing rows.
+async fn renormalize_tags(pool: &sqlx::Pool<sqlx::Sqlite>) -> error::Result<()> {
+    let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+        .fetch_one(pool)
+        .await?;
+    if version >= TAG_NORMALIZATION_VERSION {
+        return Ok(());
+    }
+
+    let mut tx = pool.begin().await?;
+    let mut updated = 0;
+
+    let tags = sqlx::query!("SELECT id, name, normalized FROM tag")
+        .fetch_all(&mut tx)
+        .await?;
+    for tag in tags {
+        let normalized = normalize_tag(&tag.name);
+        if normalized != tag.normalized {
+            sqlx::query!(
+                "UPDATE tag SET normalized = $1 WHERE id = $2",
+                normalized,
+                tag.id
+            )
+            .execute(&mut tx)
+            .await?;
+            updated += 1;
+        }
+    }
+
+    // Casings that now share a key collapse into one, as only one can apply.
+    let casings = sqlx::query!("SELECT account, normalized, name FROM tag_casing")
+        .fetch_all(&mut tx)
+        .await?;
+    for casing in casings {
+        let normalized = normalize_tag(&casing.name);
+        if normalized != casing.normalized {
+            sqlx::query!(
+                "UPDATE OR REPLACE tag_casing SET normalized = $1 WHERE account = $2 AND normalized = $3",
+                normalized,
+                casing.account,
+                casing.normalized
+            )
+            .execute(&mut tx)
+            .await?;
+            updated += 1;
+        }
+    }
+
+    sqlx::query(&format!(
+        "PRAGMA user_version = {}",
+        TAG_NORMALIZATION_VERSION
+    ))
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+
+    if updated > 0 {
+        tracing::info!("Updated {} stored tags for new tag matching", updated);
+    }
+
+    Ok(())
+}
+
+/// Replace a submission's tags, keeping their order.
+async fn store_tags(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    account: &str,
+    site: SubmissionSite,
+    id: i64,
+    tags: &[String],
+) -> error::Result<()> {
+    let site = site.as_str();
+
+    sqlx::query!(
+        "DELETE FROM submission_tag WHERE account = $1 AND site = $2 AND id = $3",
+        account,
+        site,
+        id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    for (position, tag) in tags.iter().enumerate() {
+        let position = position as i64;
+        let normalized = normalize_tag(tag);
+
+        sqlx::query!(
+            "INSERT OR IGNORE INTO tag (name, normalized) VALUES ($1, $2)",
+            tag,
+            normalized
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query!(
+            "INSERT INTO submission_tag (account, site, id, position, tag_id) SELECT $1, $2, $3, $4, id FROM tag WHERE name = $5",
+            account,
+            site,
+            id,
+            position,
+            tag
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Update a submission's tags on its site and in the database.
+///
+/// The change is recorded as part of the given batch.
+async fn set_tags(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    sites: &sites::Sites,
+    account: &str,
+    batch_id: i64,
+    sub: &Submission,
+    new_tags: &[String],
+    folder: Option<&str>,
+) -> error::Result<()> {
+    tracing::info!("Setting tags to: {}", new_tags.join(", "));
+
+    let client = sites.get(sub.site)?;
+    match folder {
+        Some(folder) => client.set_tags_in_folder(sub.id, new_tags, folder).await?,
+        None => client.set_tags(sub.id, new_tags).await?,
+    }
+
+    let tag_value = serde_json::to_value(new_tags)?;
+    let old_tag_value = serde_json::to_value(&sub.tags)?;
+    let site = sub.site.as_str();
+    let now = chrono::Utc::now();
+
+    let mut tx = pool.begin().await?;
+
+    store_tags(&mut tx, account, sub.site, sub.id, new_tags).await?;
+
+    sqlx::query!(
+        "INSERT OR REPLACE INTO batch_change (batch_id, site, id, old_tags, new_tags, changed_at) VALUES ($1, $2, $3, $4, $5, $6)",
+        batch_id,
+        site,
+        sub.id,
+        old_tag_value,
+        tag_value,
+        now
+    )
+    .execute(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Apply changes to submissions, with a separate queue for each site so that
+/// sites are updated in parallel while respecting their own update delays.
+async fn apply_changes(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    sites: &sites::Sites,
+    account: &str,
+    sort: &config::TagSort,
+    batch_id: i64,
+    changes: Vec<(&Submission, Vec<String>)>,
+) -> anyhow::Result<()> {
+    let options = ApplyOptions::default();
+    apply_changes_with(pool, sites, account, sort, batch_id, changes, options).await
+}
+
+/// Optional behavior when applying tag changes.
+#[derive(Clone, Debug, Default)]
+struct ApplyOptions {
+    /// Number of submissions to update on a site before pausing, and how long
+    /// to pause for.
+    chunking: Option<(usize, std::time::Duration)>,
+    /// When set, each submission's tags are re-fetched before updating it to
+    /// detect changes made on the site.
+    conflict: Option<ConflictPolicy>,
+    /// Time of day updates are allowed in.
+    window: Option<TimeWindow>,
+    /// Folder to add submissions to along with their tags, on sites that
+    /// support it.
+    folder: Option<String>,
+    /// Also update pinned submissions.
+    force: bool,
+}
+
+/// Apply tag changes like [`apply_changes`], with chunking, conflict
+/// detection, and time windows.
+async fn apply_changes_with(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    sites: &sites::Sites,
+    account: &str,
+    sort: &config::TagSort,
+    batch_id: i64,
+    changes: Vec<(&Submission, Vec<String>)>,
+    options: ApplyOptions,
+) -> anyhow::Result<()> {
+    // Every command writes through here, so pins hold even for commands that
+    // don't check them first.
+    let changes = if options.force {
+        changes
+    } else {
+        let subs = changes.iter().map(|(sub, _new_tags)| *sub).collect();
+        let unpinned: HashSet<_> = skip_pinned(pool, account, subs, false)
+            .await?
+            .into_iter()
+            .map(|sub| (sub.site, sub.id))
+            .collect();
+
+        changes
+            .into_iter()
+            .filter(|(sub, _new_tags)| unpinned.contains(&(sub.site, sub.id)))
+            .collect()
+    };
+
+    let changes = supported_submissions(
+        sites,
+        changes,
+        |(sub, _new_tags)| sub.site,
+        "tag changes",
+        |capabilities| capabilities.tags,
+    );
+
+    let casing = casing::TagCasing::load(pool, account, sort.added_casing).await?;
+
+    let mut queues: HashMap<SubmissionSite, Vec<_>> = HashMap::new();
+    for (sub, new_tags) in changes {
+        let mut new_tags = casing.apply(&sub.tags, new_tags);
+        tagsort::sort_tags(&mut new_tags, sort);
+        queues.entry(sub.site).or_default().push((sub, new_tags));
+    }
+
+    for site in queues.keys() {
+        loop {
+            match sites.get(*site)?.verify_ownership().await {
+                Err(err) if relogin(pool, sites, account, &err).await? => continue,
+                result => {
+                    result.with_context(|| format!("Refusing to update {} submissions", site))?
+                }
+            }
+            break;
+        }
+    }
+
+    let summary = std::sync::Mutex::new(summary::RunSummary::new("update"));
+    runs::add_total(pool, queues.values().map(Vec::len).sum()).await;
+
+    let options = &options;
+    let summary = &summary;
+    let workers = queues.into_iter().map(|(site, changes)| async move {
+        let delay = sites.get(site)?.update_delay();
+
+        // FurAffinity assigns folders in the same form as tags.
+        let folder = match options.folder.as_deref() {
+            Some(_) if site != SubmissionSite::FurAffinity => {
+                tracing::warn!("{} does not support --folder, only updating tags", site);
+                None
+            }
+            folder => folder,
+        };
+
+        for (index, (sub, new_tags)) in changes.into_iter().enumerate() {
+            match options.chunking {
+                Some((size, pause)) if index > 0 && index % size == 0 => {
+                    tracing::info!(
+                        "updated {} {} submissions, pausing for {}s",
+                        index,
+                        site,
+                        pause.as_secs()
+                    );
+                    tokio::time::sleep(pause).await;
+                }
+                _ if index > 0 => tokio::time::sleep(delay).await,
+                _ => (),
+            }
+
+            if let Some(window) = options.window {
+                if let Some(wait) = window.wait(chrono::Local::now().time()) {
+                    tracing::info!(
+                        "outside of {}, pausing {} updates for {}m",
+                        window,
+                        site,
+                        wait.as_secs() / 60
+                    );
+                    tokio::time::sleep(wait).await;
+                }
+            }
+
+            let span = tracing::info_span!("Updating tags", id = sub.id, site = %sub.site);
+
+            let new_tags = match options.conflict {
+                Some(policy) => {
+                    match resolve_conflict(sites, sub, new_tags, policy)
+                        .instrument(span.clone())
+                        .await
+                    {
+                        Some(new_tags) => new_tags,
+                        None => continue,
+                    }
+                }
+                None => new_tags,
+            };
+
+            loop {
+                let started = std::time::Instant::now();
+                let result = set_tags(pool, sites, account, batch_id, sub, &new_tags, folder)
+                    .instrument(span.clone())
+                    .await;
+                summary.lock().unwrap().record_time(site, started.elapsed());
+
+                match result {
+                    Err(err) if relogin(pool, sites, account, &err).await? => continue,
+                    Err(err) => {
+                        summary.lock().unwrap().record_failure(site);
+                        runs::record_progress(pool, sub, false).await;
+                        return Err(err.into());
+                    }
+                    Ok(()) => {
+                        summary
+                            .lock()
+                            .unwrap()
+                            .record_change(site, &sub.tags, &new_tags);
+                        runs::record_progress(pool, sub, true).await;
+                    }
+                }
+                break;
+            }
+        }
+
+        Ok::<_, anyhow::Error>(())
+    });
+
+    // Every site runs to the end even if another failed, as dropping one
+    // partway through could leave a change on the site but not recorded.
+    let results = futures::future::join_all(workers).await;
+    summary.lock().unwrap().log();
+
+    let mut errors: Vec<_> = results.into_iter().filter_map(Result::err).collect();
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.remove(0)),
+        count => Err(anyhow::anyhow!(
+            "{} sites failed: {}",
+            count,
+            errors
+                .iter()
+                .map(|err| format!("{:#}", err))
+                .collect::<Vec<_>>()
+                .join("; ")
+        )),
+    }
+}
+
+/// Offer to log in again when a FurAffinity session expired partway through
+/// a run, so it can continue instead of aborting. Returns if the session was
+/// replaced, which is never the case without a terminal to ask on.
+async fn relogin(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    sites: &sites::Sites,
+    account: &str,
+    err: &error::Error,
+) -> anyhow::Result<bool> {
+    use std::io::IsTerminal;
+
+    let client = match (err, &sites.furaffinity) {
+        (
+            error::Error::Auth {
+                site: SubmissionSite::FurAffinity,
+                ..
+            },
+            Some(client),
+        ) => client,
+        _ => return Ok(false),
+    };
+
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+
+    tracing::warn!("{}", err);
+    if !confirm("Log in to FurAffinity again and continue?")? {
+        return Ok(false);
+    }
+
+    let password = prompt(&format!("Password for {}", client.user()), true)?;
+    let (cookie_a, cookie_b) =
+        auth::login_furaffinity(pool, account, client.user(), &password, None).await?;
+    client.set_cookies(&cookie_a, &cookie_b);
+
+    Ok(true)
+}
+
+/// Compare a submission's tags on its site with the stored tags, returning
+/// the tags to set according to the policy, or `None` to skip it.
+async fn resolve_conflict(
+    sites: &sites::Sites,
+    sub: &Submission,
+    new_tags: Vec<String>,
+    policy: ConflictPolicy,
+) -> Option<Vec<String>> {
+    let remote = match sites.get(sub.site) {
+        Ok(client) => client.get_tags(sub.id).await,
+        Err(err) => Err(err.into()),
+    };
+    let remote = match remote {
+        Ok(remote) => remote,
+        Err(err) => {
+            tracing::warn!("Could not check for conflicts, skipping: {:?}", err);
+            return None;
+        }
+    };
+
+    if !tags_diverged(&sub.tags, &remote) {
+        return Some(new_tags);
+    }
+
+    match policy {
+        ConflictPolicy::Skip => {
+            tracing::warn!(
+                "Tags were changed on site since last load, skipping: {}",
+                remote.join(", ")
+            );
+            None
+        }
+        ConflictPolicy::Merge => {
+            tracing::warn!("Tags were changed on site since last load, merging");
+            Some(merge_tags(&sub.tags, &new_tags, &remote))
+        }
+        ConflictPolicy::Overwrite => {
+            tracing::warn!("Tags were changed on site since last load, overwriting");
+            Some(new_tags)
+        }
+    }
+}
+
+/// If two lists contain different tags after normalizing, ignoring order.
+fn tags_diverged(stored: &[String], remote: &[String]) -> bool {
+    let normalized = |tags: &[String]| -> HashSet<String> {
+        tags.iter().map(|tag| normalize_tag(tag)).collect()
+    };
+
+    normalized(stored) != normalized(remote)
+}
+
+/// Keep only items on sites that support a kind of change, warning once about
+/// each site that does not instead of failing partway through a run.
+fn supported_submissions<T>(
+    sites: &sites::Sites,
+    items: Vec<T>,
+    site: impl Fn(&T) -> SubmissionSite,
+    action: &str,
+    supports: impl Fn(&sites::SiteCapabilities) -> bool,
+) -> Vec<T> {
+    let mut skipped: HashMap<SubmissionSite, usize> = HashMap::new();
+
+    let items: Vec<_> = items
+        .into_iter()
+        .filter(|item| {
+            let site = site(item);
+            let supported = match sites.get(site) {
+                Ok(client) => supports(&client.capabilities()),
+                Err(_) => false,
+            };
+
+            if !supported {
+                *skipped.entry(site).or_default() += 1;
+            }
+
+            supported
+        })
+        .collect();
+
+    for (site, count) in skipped {
+        match sites.get(site) {
+            Ok(client) => tracing::warn!(
+                "{} does not support {} (supports {}), skipping {} submissions",
+                site,
+                action,
+                match client.capabilities().names() {
+                    names if names.is_empty() => "nothing".to_string(),
+                    names => names.join(", "),
+                },
+                count
+            ),
+            Err(err) => tracing::warn!("{}, skipping {} submissions", err, count),
+        }
+    }
+
+    items
+}
+
+/// Record the start of a batch of changes, returning its ID.
+async fn start_batch(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    command: &str,
+    search: Option<&str>,
+    changes: Option<&str>,
+) -> error::Result<i64> {
+    let now = chrono::Utc::now();
+
+    let batch_id = sqlx::query!(
+        "INSERT INTO batch (account, command, search, changes, started_at) VALUES ($1, $2, $3, $4, $5)",
+        account,
+        command,
+        search,
+        changes,
+        now
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    tracing::debug!(batch_id, "Started batch");
+    runs::record_batch(pool, batch_id).await;
+
+    Ok(batch_id)
+}
+
+/// Ensure a submission's file is downloaded into the cache and hashed,
+/// returning the path if the submission has a file.
+async fn cache_file(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    cache: &files::FileCache,
+    sub: &Submission,
+) -> error::Result<Option<std::path::PathBuf>> {
+    let url = match &sub.file_url {
+        Some(url) => url,
+        None => return Ok(None),
+    };
+
+    let site = sub.site.as_str();
+    let existing = sqlx::query!(
+        "SELECT url, path FROM submission_file WHERE site = $1 AND id = $2",
+        site,
+        sub.id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(existing) = existing {
+        if &existing.url == url && std::path::Path::new(&existing.path).exists() {
+            tracing::debug!("File was already cached");
+            return Ok(Some(existing.path.into()));
+        }
+    }
+
+    let path = cache.download(sub.site, sub.id, url).await?;
+    let hash = files::hash_file(&path);
+    let path_str = path.to_string_lossy().to_string();
+
+    sqlx::query!(
+        "INSERT INTO submission_file (site, id, url, path, hash) VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (site, id) DO UPDATE SET url = excluded.url, path = excluded.path, hash = excluded.hash",
+        site,
+        sub.id,
+        url,
+        path_str,
+        hash
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(Some(path))
+}
+
+/// Log keywords embedded in a submission's file that are missing from the
+/// site, such as ones added in a photo manager.
+fn report_file_keywords(sub: &Submission, path: &std::path::Path) {
+    let xmp = match std::fs::read(path)
+        .ok()
+        .and_then(|data| xmp::read_embedded(&data))
+    {
+        Some(xmp) => xmp,
+        None => return,
+    };
+
+    let tags: HashSet<_> = sub.tags.iter().map(|tag| normalize_tag(tag)).collect();
+    let missing: Vec<_> = xmp::read_keywords(&xmp)
+        .into_iter()
+        .filter(|keyword| !tags.contains(&normalize_tag(keyword)))
+        .collect();
+
+    if !missing.is_empty() {
+        tracing::info!("File has keywords not on site: {}", missing.join(", "));
+    }
+}
+
+/// Embed tags as the XMP keywords of an image file, returning if the file
+/// needed to change.
+fn write_file_keywords(
+    path: &std::path::Path,
+    tags: &[String],
+    dry_run: bool,
+) -> anyhow::Result<bool> {
+    let data = std::fs::read(path).context("Could not read file")?;
+    let existing = xmp::read_embedded(&data);
+
+    if existing
+        .as_deref()
+        .is_some_and(|xmp| xmp::read_keywords(xmp) == tags)
+    {
+        return Ok(false);
+    }
+
+    let updated = xmp::embed(&data, &xmp::set_keywords(existing.as_deref(), tags))?;
+    if !dry_run {
+        std::fs::write(path, updated).context("Could not write file")?;
+    }
+
+    Ok(true)
+}
+
+async fn get_submissions(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+) -> error::Result<Vec<Submission>> {
+    filter_submissions(pool, account, |_sub| true).await
+}
+
+/// Load only the submissions matching a predicate. Rows are streamed from the
+/// database and discarded as they are checked, so memory use depends only on
+/// the number of matches.
+async fn filter_submissions<F>(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    predicate: F,
+) -> error::Result<Vec<Submission>>
+where
+    F: FnMut(&Submission) -> bool,
+{
+    filter_all_submissions(pool, account, false, &[], predicate).await
+}
+
+/// Load submissions matching a predicate, optionally including submissions
+/// that were deleted from their site.
+///
+/// Submissions without all of the required normalized tags are excluded by
+/// the database before being parsed.
+async fn filter_all_submissions<F>(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    include_deleted: bool,
+    required_tags: &[String],
+    mut predicate: F,
+) -> error::Result<Vec<Submission>>
+where
+    F: FnMut(&Submission) -> bool,
+{
+    let mut submissions = Vec::new();
+
+    for_each_submission(pool, account, include_deleted, required_tags, |sub| {
+        if predicate(&sub) {
+            submissions.push(sub);
+        }
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(submissions)
+}
+
+/// Call a function with each stored submission as it is read from the
+/// database, without loading them all at once.
+async fn for_each_submission<F>(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    include_deleted: bool,
+    required_tags: &[String],
+    mut f: F,
+) -> error::Result<()>
+where
+    F: FnMut(Submission) -> anyhow::Result<()>,
+{
+    let mut corrupt = 0;
+
+    let required_count = required_tags.len() as i64;
+    let required_tags = serde_json::to_string(required_tags)?;
+
+    let mut rows = sqlx::query!(
+        r#"SELECT site, id, title, posted_at, file_url, folder_id, folder_name, deleted_at, site_data, visibility,
+                (SELECT json_group_array(name) FROM (
+                    SELECT tag.name FROM submission_tag JOIN tag ON tag.id = submission_tag.tag_id
+                    WHERE submission_tag.account = submission.account AND submission_tag.site = submission.site AND submission_tag.id = submission.id
+                    ORDER BY submission_tag.position)) AS "tags!: String",
+                (SELECT local_tags FROM submission_note WHERE submission_note.site = submission.site AND submission_note.id = submission.id) AS "local_tags?: String",
+                (SELECT notes FROM submission_note WHERE submission_note.site = submission.site AND submission_note.id = submission.id) AS "notes?: String"
+            FROM submission
+            WHERE account = $1 AND (deleted_at IS NULL OR $2)
+                AND (SELECT count(DISTINCT tag.normalized) FROM submission_tag JOIN tag ON tag.id = submission_tag.tag_id
+                    WHERE submission_tag.account = submission.account AND submission_tag.site = submission.site AND submission_tag.id = submission.id
+                        AND tag.normalized IN (SELECT value FROM json_each($3))) = $4"#,
+        account,
+        include_deleted,
+        required_tags,
+        required_count
+    )
+    .map(|row| -> anyhow::Result<Submission> {
+        let posted_at: chrono::DateTime<chrono::Local> =
+            chrono::DateTime::<chrono::Utc>::from_utc(row.posted_at, chrono::Utc).into();
+
+        let site = match row.site.as_ref() {
+            "FurAffinity" => SubmissionSite::FurAffinity,
+            "Weasyl" => SubmissionSite::Weasyl,
+            "Derpibooru" => SubmissionSite::Derpibooru,
+            "Mastodon" => SubmissionSite::Mastodon,
+            "Twitter" => SubmissionSite::Twitter,
+            "LocalFolder" => SubmissionSite::LocalFolder,
+            name => match name.parse() {
+                Ok(site @ SubmissionSite::External(_)) => site,
+                _ => anyhow::bail!("unknown site in database"),
+            },
+        };
+
+        let tags: Vec<String> = serde_json::from_str(&row.tags)?;
+        let local_tags: Vec<String> = match row.local_tags {
+            Some(local_tags) => serde_json::from_str(&local_tags)?,
+            None => Vec::new(),
+        };
+        let site_data = match row.site_data {
+            Some(site_data) => serde_json::from_str(&site_data)?,
+            None => Default::default(),
+        };
+        let visibility = row.visibility.parse()?;
+
+        Ok(Submission {
+            id: row.id,
+            site,
+            title: row.title,
+            posted_at,
+            tags,
+            file_url: row.file_url,
+            folder_id: row.folder_id.map(|id| id as i32),
+            folder_name: row.folder_name,
+            local_tags,
+            notes: row.notes,
+            deleted_at: row.deleted_at.map(|deleted_at| {
+                chrono::DateTime::<chrono::Utc>::from_utc(deleted_at, chrono::Utc).into()
+            }),
+            site_data,
+            visibility,
+        })
+    })
+    .fetch(pool);
+
+    while let Some(row) = rows.try_next().await? {
+        match row {
+            Ok(sub) => f(sub)?,
+            Err(err) => {
+                tracing::debug!("Skipping corrupt submission: {:?}", err);
+                corrupt += 1;
+            }
+        }
+    }
+
+    if corrupt > 0 {
+        tracing::warn!(
+            "Skipped {} corrupt submissions, run doctor for details",
+            corrupt
+        );
+    }
+
+    Ok(())
+}
+
+/// Load only the submissions matching a search.
+async fn search_submissions(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    translations: &translate::Translations,
+    search: &str,
+) -> error::Result<Vec<Submission>> {
+    let query = query::Query::parse_with(search, translations)?;
+
+    filter_all_submissions(pool, account, false, &query.required_tags(), |sub| {
+        query.matches(sub)
+    })
+    .await
+}
+
+/// Placeholders that may be used in a change string, expanded separately for
+/// each submission.
+const PLACEHOLDERS: &[&str] = &[
+    "site",
+    "id",
+    "posted_year",
+    "posted_month",
+    "posted_day",
+    "folder",
+];
+
+/// Replace `{placeholder}` names in a change string with values from the
+/// submission. Unknown placeholders are left as-is.
+fn expand_placeholders(changes: &str, sub: &Submission) -> String {
+    if !changes.contains('{') {
+        return changes.to_string();
+    }
+
+    let re = regex::Regex::new(r"\{([a-z_]+)\}").unwrap();
+    re.replace_all(changes, |caps: &regex::Captures| match &caps[1] {
+        "site" => sub.site.as_str().to_lowercase(),
+        "id" => sub.id.to_string(),
+        "posted_year" => sub.posted_at.format("%Y").to_string(),
+        "posted_month" => sub.posted_at.format("%m").to_string(),
+        "posted_day" => sub.posted_at.format("%d").to_string(),
+        "folder" => sub
+            .folder_name
+            .as_deref()
+            .map(normalize_tag)
+            .unwrap_or_default(),
+        _ => caps[0].to_string(),
+    })
+    .into_owned()
+}
+
+/// Ensure every placeholder in a change string is known.
+fn check_placeholders(changes: &str) -> anyhow::Result<()> {
+    let re = regex::Regex::new(r"\{([^}]*)\}").unwrap();
+    for caps in re.captures_iter(changes) {
+        if !PLACEHOLDERS.contains(&&caps[1]) {
+            anyhow::bail!(
+                "Unknown placeholder {}, expected one of: {}",
+                &caps[0],
+                PLACEHOLDERS.join(", ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace `@name` bundle references in split changes with each tag in the
+/// bundle, keeping a leading `-` for removals. Unknown bundles are left as-is.
+fn expand_bundles(change_tags: Vec<String>, bundles: &config::Bundles) -> Vec<String> {
+    change_tags
         .into_iter()
-        .filter_map(|row| row.ok())
+        .flat_map(|tag| {
+            let (prefix, name) = match tag.strip_prefix('-') {
+                Some(name) => ("-", name),
+                None => ("", tag.as_str()),
+            };
+
+            match name.strip_prefix('@').and_then(|name| bundles.get(name)) {
+                Some(bundle) => bundle
+                    .iter()
+                    .map(|tag| format!("{}{}", prefix, tag))
+                    .collect(),
+                None => vec![tag],
+            }
+        })
+        .collect()
+}
+
+/// Ensure every bundle referenced in a change string exists.
+fn check_bundles(changes: &str, bundles: &config::Bundles) -> anyhow::Result<()> {
+    for tag in split_tags(changes) {
+        let name = tag.strip_prefix('-').unwrap_or(&tag);
+        if let Some(name) = name.strip_prefix('@') {
+            if !bundles.contains_key(name) {
+                anyhow::bail!("Unknown bundle @{}", name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Three-way merge of tag changes. `base` is the stored tags both sides
+/// started from, `ours` has the changes being applied, and `theirs` has
+/// changes made on the site. Tags added or removed by either side stay added
+/// or removed, with tags added on the site placed after ours.
+fn merge_tags(base: &[String], ours: &[String], theirs: &[String]) -> Vec<String> {
+    let normalized = |tags: &[String]| -> HashSet<String> {
+        tags.iter().map(|tag| normalize_tag(tag)).collect()
+    };
+    let base_set = normalized(base);
+    let ours_set = normalized(ours);
+    let theirs_set = normalized(theirs);
+
+    let mut seen = HashSet::new();
+
+    ours.iter()
+        .filter(|tag| {
+            let tag = normalize_tag(tag);
+            !base_set.contains(&tag) || theirs_set.contains(&tag)
+        })
+        .chain(theirs.iter().filter(|tag| {
+            let tag = normalize_tag(tag);
+            !base_set.contains(&tag) && !ours_set.contains(&tag)
+        }))
+        .filter(|tag| seen.insert(normalize_tag(tag)))
+        .cloned()
+        .collect()
+}
+
+/// Apply a change string to tags belonging to a submission, expanding any
+/// placeholders from the submission's metadata and any tag bundles first.
+fn update_tags(
+    sub: &Submission,
+    tags: &[String],
+    changes: &str,
+    bundles: &config::Bundles,
+    sort: &config::TagSort,
+) -> Vec<String> {
+    let changes = expand_placeholders(changes, sub);
+    let change_tags = expand_bundles(split_tags(&changes), bundles);
+    let add_tags = change_tags.iter().filter(|tag| !tag.starts_with('-'));
+    let remove_tags: Vec<_> = change_tags
+        .iter()
+        .filter_map(|tag| tag.strip_prefix('-'))
+        .map(normalize_tag)
         .collect();
 
+    // Removing first keeps the order of remaining tags, and lets a change
+    // such as `-Wolf wolf` fix a tag's casing instead of dropping it.
+    let mut tags = tags.to_vec();
+    let mut kept = 0;
+    let mut removed_at = None;
+    tags.retain(|tag| {
+        let tag = normalize_tag(tag);
+        let remove = remove_tags
+            .iter()
+            .any(|remove| wildcard_matches(remove, &tag));
+
+        if remove {
+            removed_at.get_or_insert(kept);
+        } else {
+            kept += 1;
+        }
+
+        !remove
+    });
+
+    let mut added: Vec<String> = Vec::new();
+    for tag in add_tags {
+        // Tags already present are never added again with different casing,
+        // only recased if the requested casing should win.
+        let normalized = normalize_tag(tag);
+        if let Some(existing) = tags
+            .iter_mut()
+            .chain(added.iter_mut())
+            .find(|existing| normalize_tag(existing) == normalized)
+        {
+            if sort.added_casing == config::AddedCasing::Requested {
+                *existing = tag.to_string();
+            }
+            continue;
+        }
+
+        added.push(tag.to_string());
+    }
+
+    let position = match sort.additions {
+        config::AddPosition::End => tags.len(),
+        config::AddPosition::Start => 0,
+        config::AddPosition::Replace => removed_at.unwrap_or(tags.len()),
+    };
+    tags.splice(position..position, added);
+
+    tags
+}
+
+/// Every distinct tag used on any of the submissions, in the order they were
+/// first seen.
+fn union_tags(submissions: &[Submission]) -> Vec<String> {
+    let mut seen = Vec::new();
+    let mut tags = Vec::new();
+
+    for tag in submissions.iter().flat_map(|sub| &sub.tags) {
+        let normalized = normalize_tag(tag);
+        if !seen.contains(&normalized) {
+            seen.push(normalized);
+            tags.push(tag.clone());
+        }
+    }
+
+    tags
+}
+
+/// Remove pinned submissions from a bulk change unless forced, warning about
+/// how many were left out.
+async fn skip_pinned<'a>(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    mut submissions: Vec<&'a Submission>,
+    force: bool,
+) -> anyhow::Result<Vec<&'a Submission>> {
+    let pinned: HashSet<(String, i64)> = sqlx::query!(
+        "SELECT site, id FROM submission_pin WHERE account = $1",
+        account
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|pin| (pin.site, pin.id))
+    .collect();
+
+    let before = submissions.len();
+    let is_pinned = |sub: &Submission| pinned.contains(&(sub.site.to_string(), sub.id));
+
+    if force {
+        let count = submissions.iter().filter(|sub| is_pinned(sub)).count();
+        if count > 0 {
+            tracing::warn!("Updating {} pinned submissions", count);
+        }
+    } else {
+        submissions.retain(|sub| !is_pinned(sub));
+        if submissions.len() < before {
+            tracing::warn!(
+                "Skipping {} pinned submissions, use --force to update them",
+                before - submissions.len()
+            );
+        }
+    }
+
     Ok(submissions)
 }
 
-fn query_submissions<'a>(submissions: &'a [Submission], query: &str) -> Vec<&'a Submission> {
-    let query_tags: Vec<_> = query
-        .split(' ')
-        .map(|tag| tag.to_ascii_lowercase())
-        .collect();
-    let required_tags: Vec<_> = query_tags
-        .iter()
-        .filter(|tag| !tag.starts_with('-'))
-        .collect();
-    let skipped_tags: Vec<_> = query_tags
-        .iter()
-        .filter(|tag| tag.starts_with('-'))
-        .map(|tag| tag.chars().skip(1).collect())
-        .collect();
+/// Find a folder by its ID or name, ignoring case and spacing.
+fn find_folder<I>(folders: I, folder: &str) -> Option<(i32, String)>
+where
+    I: IntoIterator<Item = (i32, String)>,
+{
+    let normalized = normalize_tag(folder);
+
+    folders
+        .into_iter()
+        .find(|(id, name)| id.to_string() == folder || normalize_tag(name) == normalized)
+}
+
+fn find_submissions<'a, I>(submissions: I, ids: &[(SubmissionSite, i64)]) -> Vec<&'a Submission>
+where
+    I: IntoIterator<Item = &'a Submission>,
+{
+    let found: Vec<_> = submissions
+        .into_iter()
+        .filter(|sub| ids.contains(&(sub.site, sub.id)))
+        .collect();
+
+    if found.len() != ids.len() {
+        for (site, id) in ids {
+            if !found.iter().any(|sub| sub.site == *site && sub.id == *id) {
+                tracing::warn!(%site, id, "Submission was not found in database");
+            }
+        }
+    }
+
+    found
+}
+
+/// Use a list of submission IDs as given, or read it from stdin if it is `-`.
+fn read_ids(ids: &str) -> anyhow::Result<String> {
+    if ids != "-" {
+        return Ok(ids.to_string());
+    }
+
+    std::io::read_to_string(std::io::stdin()).context("Could not read IDs from stdin")
+}
+
+/// Parse a list of submissions in the form of `Site:id` separated by commas
+/// or whitespace.
+fn parse_submission_ids(input: &str) -> anyhow::Result<Vec<(SubmissionSite, i64)>> {
+    input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (site, id) = item
+                .split_once(':')
+                .with_context(|| format!("Submission {} was missing site", item))?;
+
+            let site = site.parse()?;
+            let id = id
+                .parse()
+                .with_context(|| format!("Submission {} had invalid ID", item))?;
+
+            Ok((site, id))
+        })
+        .collect()
+}
+
+fn sort_submissions(submissions: &mut [&Submission], order: SubmissionOrder) {
+    match order {
+        SubmissionOrder::PostedAt => {
+            submissions.sort_by_key(|sub| (sub.posted_at, sub.site.as_str(), sub.id))
+        }
+        SubmissionOrder::Id => submissions.sort_by_key(|sub| (sub.site.as_str(), sub.id)),
+    }
+}
+
+/// Order query results by a field, smallest or oldest first unless
+/// descending. Submissions missing a count come last either way.
+fn sort_query_results(submissions: &mut [Submission], sort: QuerySort, desc: bool) {
+    let count = |sub: &Submission, key: &str| -> Option<u64> {
+        sub.site_data.get(key).and_then(|count| count.parse().ok())
+    };
+
+    submissions.sort_by(|a, b| {
+        let ordering = match sort {
+            QuerySort::PostedAt => a.posted_at.cmp(&b.posted_at),
+            QuerySort::Tagcount => a.tags.len().cmp(&b.tags.len()),
+            QuerySort::Faves | QuerySort::Views => {
+                let key = match sort {
+                    QuerySort::Faves => "favorites",
+                    _ => "views",
+                };
+                match (count(a, key), count(b, key)) {
+                    (Some(a_count), Some(b_count)) => a_count.cmp(&b_count),
+                    (Some(_), None) => return std::cmp::Ordering::Less,
+                    (None, Some(_)) => return std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                }
+            }
+        };
+        let ordering = if desc { ordering.reverse() } else { ordering };
+
+        ordering.then_with(|| (a.site.as_str(), a.id).cmp(&(b.site.as_str(), b.id)))
+    });
+}
+
+/// Split a search or change string into tags.
+///
+/// Tags are separated by whitespace, which may be included in a tag by
+/// quoting it (`"digital painting"`) or escaping it (`digital\ painting`).
+fn split_tags(input: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => current.extend(chars.next()),
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    tags.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        tags.push(current);
+    }
+
+    tags
+}
+
+/// Quote a tag so it survives being split again as part of a change string.
+fn quote_tag(tag: &str) -> String {
+    if tag.chars().any(char::is_whitespace) {
+        format!("\"{}\"", tag)
+    } else {
+        tag.to_string()
+    }
+}
+
+/// Normalize a tag for comparison. Sites differ in if they display spaces or
+/// underscores in multi-word tags, so they are treated as equivalent.
+///
+/// Compatibility forms are composed first, so accented letters typed either
+/// way and fullwidth letters match their usual form, and case is ignored for
+/// every script rather than only ASCII.
+fn normalize_tag(tag: &str) -> String {
+    tag.nfkc()
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("_")
+        .to_lowercase()
+}
+
+/// Check if a normalized tag matches a pattern, where each `*` matches any
+/// text. Patterns without a `*` must match exactly.
+fn wildcard_matches(pattern: &str, tag: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = tag.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// Split a namespaced tag such as `species:wolf` into its namespace and value.
+/// Tags without both parts have no namespace.
+fn split_namespace(tag: &str) -> Option<(&str, &str)> {
+    tag.split_once(':')
+        .filter(|(namespace, value)| !namespace.is_empty() && !value.is_empty())
+}
+
+fn check_change_mode(changes: &str, add_only: bool, remove_only: bool) -> anyhow::Result<()> {
+    let (remove_tags, add_tags): (Vec<_>, Vec<_>) = split_tags(changes)
+        .into_iter()
+        .partition(|tag| tag.starts_with('-'));
+
+    if add_only && !remove_tags.is_empty() {
+        anyhow::bail!(
+            "changes remove tags in add-only mode: {}",
+            remove_tags.join(" ")
+        );
+    }
+
+    if remove_only && !add_tags.is_empty() {
+        anyhow::bail!(
+            "changes add tags in remove-only mode: {}",
+            add_tags.join(" ")
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use crate::{
+        apply_changes, check_bundles, check_change_mode, check_placeholders, count_difference,
+        dropped_percent, filter_all_submissions, find_folder, is_confirmed, merge_tags,
+        missing_restricted, normalize_tag, open_memory_database, parse_edited_tags, parse_field,
+        parse_pause, parse_script, parse_submission_ids, query, renormalize_tags,
+        replace_site_submissions,
+        sites::{self, Site, Submission, SubmissionSite, Visibility},
+        sort_query_results, split_args, split_namespace, split_tags, start_batch, tags_diverged,
+        union_tags, update_tags, wildcard_matches, Age, Command, Opts, QuerySort, SyncChanges,
+        TimeWindow,
+    };
+
+    #[test]
+    fn test_opts() {
+        Opts::command().debug_assert();
+    }
+
+    fn query_submissions<'a, I>(submissions: I, query: &str) -> Vec<&'a Submission>
+    where
+        I: IntoIterator<Item = &'a Submission>,
+    {
+        let query = query::Query::parse_with(query, &Default::default()).unwrap();
+
+        submissions
+            .into_iter()
+            .filter(|sub| query.matches(sub))
+            .collect()
+    }
+
+    pub(crate) fn submission(id: i64, tags: &[&str]) -> Submission {
+        Submission {
+            id,
+            site: SubmissionSite::FurAffinity,
+            title: "test".to_string(),
+            posted_at: chrono::Local::now(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            file_url: None,
+            folder_id: None,
+            folder_name: None,
+            local_tags: Vec::new(),
+            notes: None,
+            deleted_at: None,
+            site_data: Default::default(),
+            visibility: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_age() {
+        use chrono::TimeZone;
+
+        let now = chrono::Local.ymd(2024, 3, 31).and_hms(12, 0, 0);
+
+        let age: Age = "3y".parse().unwrap();
+        assert_eq!(
+            age.before(now),
+            chrono::Local.ymd(2021, 3, 31).and_hms(12, 0, 0)
+        );
+
+        let age: Age = "1m".parse().unwrap();
+        assert_eq!(
+            age.before(now),
+            chrono::Local.ymd(2024, 2, 29).and_hms(12, 0, 0)
+        );
+
+        let age: Age = "2w".parse().unwrap();
+        assert_eq!(
+            age.before(now),
+            chrono::Local.ymd(2024, 3, 17).and_hms(12, 0, 0)
+        );
+
+        assert!("3".parse::<Age>().is_err());
+        assert!("xy".parse::<Age>().is_err());
+    }
+
+    #[test]
+    fn test_parse_pause() {
+        use std::time::Duration;
+
+        assert_eq!(parse_pause("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_pause("10m").unwrap(), Duration::from_secs(600));
+        assert_eq!(parse_pause("1h").unwrap(), Duration::from_secs(3600));
+        assert!(parse_pause("10").is_err());
+        assert!(parse_pause("m").is_err());
+    }
+
+    #[test]
+    fn test_parse_field() {
+        assert_eq!(
+            parse_field("Species=Red Fox").unwrap(),
+            ("species".to_string(), "Red Fox".to_string())
+        );
+        assert!(parse_field("species").is_err());
+        assert!(parse_field("=wolf").is_err());
+    }
+
+    #[test]
+    fn test_external_site() {
+        let site = SubmissionSite::register_external("TestBooru").unwrap();
+        assert_eq!(site, SubmissionSite::External("TestBooru"));
+        assert_eq!("testbooru".parse::<SubmissionSite>().unwrap(), site);
+        assert_eq!(
+            SubmissionSite::register_external("TestBooru").unwrap(),
+            site
+        );
+
+        assert!(SubmissionSite::register_external("weasyl").is_err());
+        assert!(SubmissionSite::register_external("test booru").is_err());
+
+        assert_eq!(serde_json::to_string(&site).unwrap(), r#""TestBooru""#);
+        assert_eq!(
+            serde_json::from_str::<SubmissionSite>(r#""Weasyl""#).unwrap(),
+            SubmissionSite::Weasyl
+        );
+    }
+
+    #[test]
+    fn test_submission_url() {
+        assert_eq!(
+            submission(123, &[]).url().as_deref(),
+            Some("https://www.furaffinity.net/view/123/")
+        );
+        assert_eq!(SubmissionSite::Mastodon.url(123), None);
+    }
+
+    #[test]
+    fn test_query_submissions() {
+        let submissions = vec![
+            submission(1, &["tag1", "tag2"]),
+            submission(2, &["tag3"]),
+            submission(3, &["tag1", "tag4"]),
+        ];
+
+        let items = query_submissions(&submissions, "tag1 -tag4");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![1]);
+
+        let items = query_submissions(&submissions, "tag1 tag2");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![1]);
+
+        let items = query_submissions(&submissions, "tag1");
+        assert_eq!(
+            items.iter().map(|sub| sub.id).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+    }
+
+    #[test]
+    fn test_query_submissions_folder() {
+        let mut submissions = vec![submission(1, &["tag1"]), submission(2, &["tag1"])];
+        submissions[0].folder_id = Some(10);
+        submissions[0].folder_name = Some("Old Sketches".to_string());
+
+        let items = query_submissions(&submissions, r#"folder:"old sketches""#);
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![1]);
+
+        let items = query_submissions(&submissions, "tag1 -folder:10");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn test_query_submissions_local() {
+        let mut submissions = vec![submission(1, &["tag1"]), submission(2, &["tag1"])];
+        submissions[1].local_tags = vec!["needs_redraw".to_string()];
+
+        let items = query_submissions(&submissions, "local:needs_redraw");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![2]);
+
+        let items = query_submissions(&submissions, "needs_redraw");
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn test_query_submissions_fuzzy() {
+        let submissions = vec![
+            submission(1, &["ref_sheet"]),
+            submission(2, &["refsheeet"]),
+            submission(3, &["reference"]),
+            submission(4, &["box"]),
+        ];
+
+        let items = query_submissions(&submissions, "fuzzy:refsheet");
+        assert_eq!(
+            items.iter().map(|sub| sub.id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        let items = query_submissions(&submissions, "fuzzy:fox");
+        assert!(items.is_empty());
+
+        let items = query_submissions(&submissions, "-fuzzy:Ref-Sheet");
+        assert_eq!(
+            items.iter().map(|sub| sub.id).collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+    }
+
+    #[test]
+    fn test_query_submissions_title() {
+        let mut submissions = vec![
+            submission(1, &["tag1"]),
+            submission(2, &["tag1"]),
+            submission(3, &["tag2"]),
+        ];
+        submissions[0].title = "Commission for Bob".to_string();
+        submissions[1].title = "YCH Commission 2".to_string();
+        submissions[2].title = "Sketch".to_string();
+
+        let items = query_submissions(&submissions, "title:~commission");
+        assert_eq!(
+            items.iter().map(|sub| sub.id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+
+        let items = query_submissions(&submissions, r#"tag1 title:~"for bob""#);
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![1]);
+
+        let items = query_submissions(&submissions, "title:sketch");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![3]);
+
+        let items = query_submissions(&submissions, r"title:/^ych\\s+commission\\s+\\d+$/");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![2]);
+
+        let items = query_submissions(&submissions, "-title:~commission");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![3]);
+
+        // Never searched as a tag named after the term instead.
+        let parse = |search| query::Query::parse_with(search, &Default::default());
+        assert!(parse("title:/[unclosed/").is_err());
+        assert!(parse("-title:/(/").is_err());
+        assert!(parse("title:~").is_err());
+        assert!(parse("title:").is_err());
+    }
+
+    #[test]
+    fn test_query_submissions_site_data() {
+        let mut submissions = vec![submission(1, &["tag1"]), submission(2, &["tag1"])];
+        submissions[0]
+            .site_data
+            .insert("species".to_string(), "Wolf".to_string());
+        submissions[1].site = SubmissionSite::Weasyl;
+        submissions[1]
+            .site_data
+            .insert("species".to_string(), "Wolf".to_string());
+
+        let items = query_submissions(&submissions, "fa.species:wolf");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![1]);
+
+        let items = query_submissions(&submissions, "weasyl.species:w*");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![2]);
+
+        let items = query_submissions(&submissions, "fa.species:fox");
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn test_query_submissions_popularity() {
+        let mut submissions = vec![
+            submission(1, &["tag1"]),
+            submission(2, &["tag1"]),
+            submission(3, &["tag1"]),
+        ];
+        submissions[0]
+            .site_data
+            .insert("favorites".to_string(), "150".to_string());
+        submissions[0]
+            .site_data
+            .insert("comments".to_string(), "4".to_string());
+        submissions[1]
+            .site_data
+            .insert("favorites".to_string(), "20".to_string());
+
+        let items = query_submissions(&submissions, "faves:>100");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![1]);
+
+        let items = query_submissions(&submissions, "favorites:<=20");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![2]);
+
+        let items = query_submissions(&submissions, "tag1 -comments:>0");
+        assert_eq!(
+            items.iter().map(|sub| sub.id).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+    }
+
+    #[test]
+    fn test_sort_query_results() {
+        let mut submissions = vec![
+            submission(1, &["tag1"]),
+            submission(2, &["tag1", "tag2", "tag3"]),
+            submission(3, &["tag1", "tag2"]),
+        ];
+        submissions[0]
+            .site_data
+            .insert("favorites".to_string(), "5".to_string());
+        submissions[1]
+            .site_data
+            .insert("favorites".to_string(), "40".to_string());
+        let ids =
+            |submissions: &[Submission]| submissions.iter().map(|sub| sub.id).collect::<Vec<_>>();
+
+        sort_query_results(&mut submissions, QuerySort::Faves, true);
+        assert_eq!(ids(&submissions), vec![2, 1, 3]);
 
-    submissions
-        .iter()
-        .filter(|sub| {
-            let tags: Vec<_> = sub
-                .tags
-                .iter()
-                .map(|tag| tag.to_ascii_lowercase())
-                .collect();
+        sort_query_results(&mut submissions, QuerySort::Faves, false);
+        assert_eq!(ids(&submissions), vec![1, 2, 3]);
 
-            required_tags.iter().all(|tag| tags.contains(tag))
-                && !skipped_tags.iter().any(|tag| tags.contains(tag))
-        })
-        .collect()
-}
+        sort_query_results(&mut submissions, QuerySort::Tagcount, true);
+        assert_eq!(ids(&submissions), vec![2, 3, 1]);
+    }
 
-fn update_tags(tags: &[String], changes: &str) -> Vec<String> {
-    let change_tags: Vec<_> = changes.split(' ').collect();
-    let add_tags = change_tags.iter().filter(|tag| !tag.starts_with('-'));
-    let remove_tags: Vec<_> = change_tags
-        .iter()
-        .filter(|tag| tag.starts_with('-'))
-        .map(|tag| tag.chars().skip(1).collect::<String>())
-        .map(|tag| tag.to_ascii_lowercase())
-        .collect();
+    #[test]
+    fn test_query_submissions_visibility() {
+        let mut submissions = vec![
+            submission(1, &["tag1"]),
+            submission(2, &["tag1"]),
+            submission(3, &["tag1"]),
+        ];
+        submissions[1].visibility = Visibility::Friends;
+        submissions[2].visibility = Visibility::Hidden;
 
-    let mut tags = tags.to_vec();
-    tags.extend(add_tags.into_iter().map(|tag| tag.to_string()));
-    tags.retain(|tag| !remove_tags.contains(&tag.to_ascii_lowercase()));
+        let items = query_submissions(&submissions, "visibility:friends");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![2]);
 
-    tags
-}
+        let items = query_submissions(&submissions, "tag1 -visibility:public");
+        assert_eq!(
+            items.iter().map(|sub| sub.id).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+    }
 
-fn tag_display(old: &[String], new: &[String]) {
-    let old: HashSet<&String> = HashSet::from_iter(old.iter());
-    let new: HashSet<&String> = HashSet::from_iter(new.iter());
+    #[test]
+    fn test_query_submissions_missing() {
+        let submissions = vec![
+            submission(1, &[]),
+            submission(2, &["species:wolf", "tag1"]),
+            submission(3, &["tag1"]),
+        ];
 
-    let added = new.difference(&old);
-    let removed = old.difference(&new);
+        let items = query_submissions(&submissions, "has:none");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![1]);
 
-    tracing::info!(
-        "Adding tags: {}",
-        added
-            .into_iter()
-            .map(|tag| tag.to_string())
-            .collect::<Vec<_>>()
-            .join(", ")
-    );
-    tracing::info!(
-        "Removing tags: {}",
-        removed
-            .into_iter()
-            .map(|tag| tag.to_string())
-            .collect::<Vec<_>>()
-            .join(", ")
-    );
-}
+        let items = query_submissions(&submissions, "missing:species");
+        assert_eq!(
+            items.iter().map(|sub| sub.id).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
 
-#[cfg(test)]
-mod tests {
-    use crate::{
-        query_submissions,
-        sites::{Submission, SubmissionSite},
-        update_tags,
-    };
+        let items = query_submissions(&submissions, "-has:none missing:Species");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![3]);
+    }
 
     #[test]
-    fn test_query_submissions() {
+    fn test_query_submissions_tag_count() {
         let submissions = vec![
-            Submission {
-                id: 1,
-                site: SubmissionSite::FurAffinity,
-                title: "test".to_string(),
-                posted_at: chrono::Local::now(),
-                tags: vec!["tag1".to_string(), "tag2".to_string()],
-            },
-            Submission {
-                id: 2,
-                site: SubmissionSite::FurAffinity,
-                title: "test".to_string(),
-                posted_at: chrono::Local::now(),
-                tags: vec!["tag3".to_string()],
-            },
-            Submission {
-                id: 3,
-                site: SubmissionSite::FurAffinity,
-                title: "test".to_string(),
-                posted_at: chrono::Local::now(),
-                tags: vec!["tag1".to_string(), "tag4".to_string()],
-            },
+            submission(1, &[]),
+            submission(2, &["tag1", "tag2"]),
+            submission(3, &["tag1", "tag2", "tag3"]),
         ];
 
-        let items = query_submissions(&submissions, "tag1 -tag4");
-        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![1]);
+        let items = query_submissions(&submissions, "tagcount:<3");
+        assert_eq!(
+            items.iter().map(|sub| sub.id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
 
-        let items = query_submissions(&submissions, "tag1 tag2");
+        let items = query_submissions(&submissions, "tagcount:>=2 -tagcount:3");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![2]);
+
+        let items = query_submissions(&submissions, "tagcount:0");
         assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![1]);
+    }
 
-        let items = query_submissions(&submissions, "tag1");
+    #[test]
+    fn test_query_submissions_namespace() {
+        let submissions = vec![
+            submission(1, &["species:wolf", "oc:rex"]),
+            submission(2, &["Species:Fox"]),
+            submission(3, &["wolf", "species:"]),
+        ];
+
+        let items = query_submissions(&submissions, "species:*");
         assert_eq!(
             items.iter().map(|sub| sub.id).collect::<Vec<_>>(),
-            vec![1, 3]
+            vec![1, 2]
+        );
+
+        let items = query_submissions(&submissions, "species:* -oc:*");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![2]);
+
+        let items = query_submissions(&submissions, "species:wolf");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![1]);
+
+        assert_eq!(split_namespace("oc:rex"), Some(("oc", "rex")));
+        assert_eq!(split_namespace(":rex"), None);
+    }
+
+    #[test]
+    fn test_wildcards() {
+        assert!(wildcard_matches("oc:*", "oc:rex"));
+        assert!(wildcard_matches("*_sketch", "pencil_sketch"));
+        assert!(wildcard_matches("wip_*_v*", "wip_rex_v2"));
+        assert!(wildcard_matches("fox", "fox"));
+        assert!(!wildcard_matches("fox", "foxes"));
+        assert!(!wildcard_matches("*_sketch", "sketch"));
+        assert!(!wildcard_matches("a*a", "a"));
+
+        let submissions = vec![
+            submission(1, &["pencil_sketch", "wolf"]),
+            submission(2, &["wolf"]),
+        ];
+        let items = query_submissions(&submissions, "*_sketch");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![1]);
+        let items = query_submissions(&submissions, "wolf -*sketch*");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![2]);
+
+        let sub = submission(1, &["wip_1", "WIP 2", "wolf", "wip"]);
+        let new_tags = update_tags(
+            &sub,
+            &sub.tags,
+            "-wip_*",
+            &Default::default(),
+            &Default::default(),
         );
+        assert_eq!(new_tags, vec!["wolf", "wip"]);
+    }
+
+    #[test]
+    fn test_query_required_tags() {
+        let query = crate::query::Query::parse_with(
+            r#"Tag2 -tag3 "big tag" folder:sketches tag2"#,
+            &Default::default(),
+        )
+        .unwrap();
+        assert_eq!(query.required_tags(), vec!["big_tag", "tag2"]);
     }
 
     #[test]
     fn test_update_tags() {
+        let bundles = Default::default();
         let tags = vec!["tag1".to_string(), "tag2".to_string()];
-        let new_tags = update_tags(&tags, "tag3 -tag2");
+        let new_tags = update_tags(
+            &submission(1, &[]),
+            &tags,
+            "tag3 -tag2",
+            &bundles,
+            &Default::default(),
+        );
         assert_eq!(new_tags, vec!["tag1".to_string(), "tag3".to_string()]);
+
+        let tags = vec!["tag1".to_string(), "digital_painting".to_string()];
+        let new_tags = update_tags(
+            &submission(1, &[]),
+            &tags,
+            r#"-"digital painting" "traditional art""#,
+            &bundles,
+            &Default::default(),
+        );
+        assert_eq!(
+            new_tags,
+            vec!["tag1".to_string(), "traditional art".to_string()]
+        );
+
+        let tags = vec!["Wolf".to_string()];
+        let new_tags = update_tags(
+            &submission(1, &[]),
+            &tags,
+            "wolf fox Fox",
+            &bundles,
+            &Default::default(),
+        );
+        assert_eq!(new_tags, vec!["Wolf".to_string(), "fox".to_string()]);
+    }
+
+    #[test]
+    fn test_normalize_tag_unicode() {
+        assert_eq!(normalize_tag("Cafe\u{301} Art"), "café_art");
+        assert_eq!(normalize_tag("ＷＯＬＦ"), "wolf");
+        assert_eq!(normalize_tag("ÉLAN"), "élan");
+        assert_eq!(normalize_tag("オオカミ"), "オオカミ");
+
+        let submissions = vec![submission(1, &["Café"]), submission(2, &["Ｆｏｘ"])];
+        let items = query_submissions(&submissions, "cafe\u{301} fox");
+        assert!(items.is_empty());
+        let items = query_submissions(&submissions, "CAFÉ");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![1]);
+        let items = query_submissions(&submissions, "fox");
+        assert_eq!(items.iter().map(|sub| sub.id).collect::<Vec<_>>(), vec![2]);
+
+        let tags = vec!["Ｆｏｘ".to_string(), "Éclair".to_string()];
+        let new_tags = update_tags(
+            &submission(1, &[]),
+            &tags,
+            "-fox -éclair",
+            &Default::default(),
+            &Default::default(),
+        );
+        assert!(new_tags.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_renormalize_tags() {
+        let pool = open_memory_database(false).await.unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+
+        replace_site_submissions(
+            &pool,
+            "default",
+            SubmissionSite::FurAffinity,
+            &[submission(1, &["Ｆｏｘ", "CAFÉ"])],
+        )
+        .await
+        .unwrap();
+
+        // Values as the earlier ASCII-only normalization stored them.
+        sqlx::query("UPDATE tag SET normalized = lower(replace(trim(name), ' ', '_'))")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO tag_casing (account, normalized, name) VALUES ('default', 'Ｆｏｘ', 'Ｆｏｘ')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let search = |pool: sqlx::Pool<sqlx::Sqlite>| async move {
+            let query = query::Query::parse_with("fox café", &Default::default()).unwrap();
+            filter_all_submissions(&pool, "default", false, &query.required_tags(), |sub| {
+                query.matches(sub)
+            })
+            .await
+            .unwrap()
+        };
+        assert!(search(pool.clone()).await.is_empty());
+
+        renormalize_tags(&pool).await.unwrap();
+        assert_eq!(search(pool.clone()).await.len(), 1);
+
+        let casing: String =
+            sqlx::query_scalar("SELECT normalized FROM tag_casing WHERE name = 'Ｆｏｘ'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(casing, "fox");
+    }
+
+    #[tokio::test]
+    async fn test_normalized_tags_migration_keeps_unparsed() {
+        const NORMALIZED_TAGS: i64 = 20211227150322;
+
+        let pool = open_memory_database(false).await.unwrap();
+        let migrator = sqlx::migrate!();
+        let run = |version: i64, down: bool| {
+            let migration = migrator
+                .iter()
+                .find(|migration| {
+                    migration.version == version
+                        && migration.migration_type.is_down_migration() == down
+                })
+                .unwrap();
+            sqlx::query(&migration.sql).execute(&pool)
+        };
+
+        for migration in migrator.iter() {
+            if migration.version < NORMALIZED_TAGS && !migration.migration_type.is_down_migration()
+            {
+                run(migration.version, false).await.unwrap();
+            }
+        }
+
+        sqlx::query(
+            "INSERT INTO submission (account, site, id, title, posted_at, tags) VALUES
+            ('default', 'FurAffinity', 1, '', '2021-01-01 00:00:00', '[\"fox\"]'),
+            ('default', 'FurAffinity', 2, '', '2021-01-01 00:00:00', 'fox, wolf')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        run(NORMALIZED_TAGS, false).await.unwrap();
+
+        let unparsed: Vec<(i64, String)> =
+            sqlx::query_as("SELECT id, tags FROM submission_tags_unparsed")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(unparsed, vec![(2, "fox, wolf".to_string())]);
+
+        run(NORMALIZED_TAGS, true).await.unwrap();
+
+        let tags: Vec<(i64, String)> =
+            sqlx::query_as("SELECT id, tags FROM submission ORDER BY id")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            tags,
+            vec![(1, r#"["fox"]"#.to_string()), (2, "fox, wolf".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply_changes_skips_pinned() {
+        let dir = std::env::temp_dir().join(format!("batch-tagger-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.png"), b"").unwrap();
+        std::fs::write(dir.join("b.png"), b"").unwrap();
+
+        let pool = open_memory_database(false).await.unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+
+        let sites = sites::Sites {
+            local_folder: Some(sites::LocalFolder::new(&dir)),
+            ..Default::default()
+        };
+        let folder = sites.local_folder.as_ref().unwrap();
+        let submissions = folder.get_all_submissions().await.unwrap();
+        replace_site_submissions(&pool, "default", SubmissionSite::LocalFolder, &submissions)
+            .await
+            .unwrap();
+
+        sqlx::query(
+            "INSERT INTO submission_pin (account, site, id, pinned_at) VALUES ('default', 'LocalFolder', $1, CURRENT_TIMESTAMP)",
+        )
+        .bind(submissions[0].id)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        // As used by commands that don't check pins themselves, such as
+        // copy-tags and the web interface.
+        let batch_id = start_batch(&pool, "default", "copy-tags", None, None)
+            .await
+            .unwrap();
+        let changes = submissions
+            .iter()
+            .map(|sub| (sub, vec!["wolf".to_string()]))
+            .collect();
+        apply_changes(
+            &pool,
+            &sites,
+            "default",
+            &Default::default(),
+            batch_id,
+            changes,
+        )
+        .await
+        .unwrap();
+
+        let tags = |id| async move { folder.get_submission(id).await.unwrap().tags };
+        assert!(tags(submissions[0].id).await.is_empty());
+        assert_eq!(tags(submissions[1].id).await, vec!["wolf"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_update_tags_order() {
+        use crate::config::{AddPosition, AddedCasing, TagSort};
+
+        let bundles = Default::default();
+        let sub = submission(1, &["a", "b", "c", "d"]);
+        let update = |changes, additions| {
+            let sort = TagSort {
+                additions,
+                ..Default::default()
+            };
+            update_tags(&sub, &sub.tags, changes, &bundles, &sort)
+        };
+
+        assert_eq!(
+            update("-b x y", AddPosition::End),
+            vec!["a", "c", "d", "x", "y"]
+        );
+        assert_eq!(
+            update("-b x y", AddPosition::Start),
+            vec!["x", "y", "a", "c", "d"]
+        );
+        assert_eq!(
+            update("-b x y", AddPosition::Replace),
+            vec!["a", "x", "y", "c", "d"]
+        );
+        assert_eq!(update("x -c -a", AddPosition::Replace), vec!["x", "b", "d"]);
+        assert_eq!(
+            update("x X", AddPosition::Replace),
+            vec!["a", "b", "c", "d", "x"]
+        );
+        assert_eq!(update("-b b", AddPosition::End), vec!["a", "c", "d", "b"]);
+
+        let sub = submission(1, &["Wolf", "fox"]);
+        let mut sort = TagSort {
+            additions: AddPosition::Replace,
+            ..Default::default()
+        };
+        let new_tags = update_tags(&sub, &sub.tags, "-Wolf wolf", &bundles, &sort);
+        assert_eq!(new_tags, vec!["wolf", "fox"]);
+
+        let new_tags = update_tags(&sub, &sub.tags, "wolf Fox", &bundles, &sort);
+        assert_eq!(new_tags, vec!["Wolf", "fox"]);
+
+        sort.added_casing = AddedCasing::Requested;
+        let new_tags = update_tags(&sub, &sub.tags, "wolf Fox bat BAT", &bundles, &sort);
+        assert_eq!(new_tags, vec!["wolf", "Fox", "BAT"]);
+    }
+
+    #[test]
+    fn test_update_tags_placeholders() {
+        let mut sub = submission(42, &["tag1"]);
+        sub.posted_at = chrono::TimeZone::ymd(&chrono::Local, 2019, 3, 7).and_hms(12, 0, 0);
+        sub.folder_name = Some("Sketch Dump".to_string());
+
+        let new_tags = update_tags(
+            &sub,
+            &sub.tags,
+            "year_{posted_year} {site}_upload",
+            &Default::default(),
+            &Default::default(),
+        );
+        assert_eq!(new_tags, vec!["tag1", "year_2019", "furaffinity_upload"]);
+
+        let new_tags = update_tags(
+            &sub,
+            &sub.tags,
+            "{posted_year}-{posted_month} {folder}",
+            &Default::default(),
+            &Default::default(),
+        );
+        assert_eq!(new_tags, vec!["tag1", "2019-03", "sketch_dump"]);
+
+        assert!(check_placeholders("year_{posted_year} id_{id}").is_ok());
+        assert!(check_placeholders("{unknown}").is_err());
+    }
+
+    #[test]
+    fn test_missing_restricted() {
+        let rated = |id, rating: &str| {
+            let mut sub = submission(id, &[]);
+            sub.site_data
+                .insert("rating".to_string(), rating.to_string());
+            sub
+        };
+
+        let stored = vec![rated(1, "general"), rated(2, "adult"), rated(3, "mature")];
+        assert_eq!(missing_restricted(&stored, &[rated(1, "general")]), 2);
+        assert_eq!(
+            missing_restricted(&stored, &[rated(1, "general"), rated(3, "mature")]),
+            0
+        );
+        assert_eq!(missing_restricted(&stored, &stored), 0);
+    }
+
+    #[test]
+    fn test_count_difference() {
+        assert_eq!(count_difference(0, 10), None);
+        assert_eq!(count_difference(100, 100), None);
+        assert_eq!(count_difference(100, 99), None);
+        assert_eq!(count_difference(100, 80), Some(-20.0));
+        assert_eq!(count_difference(100, 150), Some(50.0));
+    }
+
+    #[test]
+    fn test_dropped_percent() {
+        assert_eq!(dropped_percent(0, 10), None);
+        assert_eq!(dropped_percent(10, 10), None);
+        assert_eq!(dropped_percent(10, 12), None);
+        assert_eq!(dropped_percent(10, 4), Some(60.0));
+        assert_eq!(dropped_percent(10, 0), Some(100.0));
+    }
+
+    #[test]
+    fn test_find_folder() {
+        let folders = || vec![(3, "Sketches".to_string()), (7, "Comic Pages".to_string())];
+
+        assert_eq!(
+            find_folder(folders(), "7"),
+            Some((7, "Comic Pages".to_string()))
+        );
+        assert_eq!(
+            find_folder(folders(), "comic pages"),
+            Some((7, "Comic Pages".to_string()))
+        );
+        assert_eq!(find_folder(folders(), "12"), None);
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(
+            split_args(r#"apply-tags --search 'oc:rex' --tags "blue fur" -sketch"#).unwrap(),
+            vec![
+                "apply-tags",
+                "--search",
+                "oc:rex",
+                "--tags",
+                "blue fur",
+                "-sketch"
+            ]
+        );
+        assert_eq!(
+            split_args(r#"note --note it\'s "" x"#).unwrap(),
+            vec!["note", "--note", "it's", "", "x"]
+        );
+        assert!(split_args("note --note 'unclosed").is_err());
+    }
+
+    #[test]
+    fn test_is_confirmed() {
+        assert!(is_confirmed("y\n"));
+        assert!(is_confirmed(" YES "));
+        assert!(!is_confirmed("\n"));
+        assert!(!is_confirmed("no"));
+    }
+
+    #[test]
+    fn test_parse_script() {
+        let script = "# Retag sketches\n\napply-tags --dry-run --search sketch --tags wip\nstats\n";
+        let commands = parse_script(script).unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].0, 3);
+        assert!(matches!(
+            commands[0].1,
+            Command::ApplyTags { dry_run: true, .. }
+        ));
+        assert!(matches!(commands[1].1, Command::Stats { .. }));
+
+        assert!(parse_script("stats\napply-tags --bogus\n").is_err());
+        assert!(parse_script("run-script other.bt\n").is_err());
+    }
+
+    #[test]
+    fn test_time_window() {
+        let time = |s: &str| chrono::NaiveTime::parse_from_str(s, "%H:%M").unwrap();
+
+        let window: TimeWindow = "02:00-06:00".parse().unwrap();
+        assert_eq!(window.to_string(), "02:00-06:00");
+        assert_eq!(window.wait(time("03:30")), None);
+        assert_eq!(
+            window.wait(time("01:30")),
+            Some(std::time::Duration::from_secs(30 * 60))
+        );
+        assert_eq!(
+            window.wait(time("06:00")),
+            Some(std::time::Duration::from_secs(20 * 60 * 60))
+        );
+
+        let overnight: TimeWindow = "22:00-04:00".parse().unwrap();
+        assert_eq!(overnight.wait(time("23:00")), None);
+        assert_eq!(overnight.wait(time("01:00")), None);
+        assert_eq!(
+            overnight.wait(time("12:00")),
+            Some(std::time::Duration::from_secs(10 * 60 * 60))
+        );
+
+        assert!("02:00".parse::<TimeWindow>().is_err());
+        assert!("02:00-25:00".parse::<TimeWindow>().is_err());
+        assert!("02:00-02:00".parse::<TimeWindow>().is_err());
+    }
+
+    #[test]
+    fn test_merge_tags() {
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+
+        let base = tags(&["wolf", "sketch", "wip"]);
+        assert!(!tags_diverged(&base, &tags(&["Sketch", "wolf", "wip"])));
+        assert!(tags_diverged(&base, &tags(&["wolf", "sketch"])));
+
+        // We add blue_fur and remove wip, the site added forest and removed
+        // sketch.
+        let ours = tags(&["wolf", "sketch", "blue_fur"]);
+        let theirs = tags(&["wolf", "wip", "forest"]);
+        assert_eq!(
+            merge_tags(&base, &ours, &theirs),
+            vec!["wolf", "blue_fur", "forest"]
+        );
+
+        // Both sides adding the same tag keeps only one.
+        let theirs = tags(&["wolf", "sketch", "wip", "Blue_Fur"]);
+        assert_eq!(
+            merge_tags(&base, &ours, &theirs),
+            vec!["wolf", "sketch", "blue_fur"]
+        );
+    }
+
+    #[test]
+    fn test_sync_changes() {
+        let stored = vec![
+            submission(1, &["wolf"]),
+            submission(2, &["fox"]),
+            submission(3, &["cat"]),
+        ];
+        let loaded = vec![
+            submission(1, &["wolf"]),
+            submission(3, &["cat", "sketch"]),
+            submission(4, &[]),
+        ];
+
+        let changes = SyncChanges::new(&stored, &loaded);
+        let ids = |subs: &[&Submission]| subs.iter().map(|sub| sub.id).collect::<Vec<_>>();
+        assert_eq!(ids(&changes.added), vec![4]);
+        assert_eq!(ids(&changes.removed), vec![2]);
+        assert_eq!(changes.retagged.len(), 1);
+        assert_eq!(changes.retagged[0].1.tags, vec!["cat", "sketch"]);
+    }
+
+    #[test]
+    fn test_update_tags_bundles() {
+        let mut bundles = std::collections::HashMap::new();
+        bundles.insert(
+            "rex".to_string(),
+            vec!["oc:rex".to_string(), "blue fur".to_string()],
+        );
+
+        let sub = submission(1, &["wolf", "oc:rex", "blue_fur"]);
+        let new_tags = update_tags(
+            &sub,
+            &sub.tags,
+            "-@rex sketch",
+            &bundles,
+            &Default::default(),
+        );
+        assert_eq!(new_tags, vec!["wolf", "sketch"]);
+
+        let new_tags = update_tags(&sub, &[], "@rex", &bundles, &Default::default());
+        assert_eq!(new_tags, vec!["oc:rex", "blue fur"]);
+
+        assert!(check_bundles("@rex -@rex tag", &bundles).is_ok());
+        assert!(check_bundles("-@unknown", &bundles).is_err());
+    }
+
+    #[test]
+    fn test_union_tags() {
+        let submissions = vec![
+            submission(1, &["oc:rex", "wolf"]),
+            submission(2, &["Wolf", "blue fur"]),
+        ];
+
+        assert_eq!(union_tags(&submissions), vec!["oc:rex", "wolf", "blue fur"]);
+    }
+
+    #[test]
+    fn test_parse_edited_tags() {
+        let tags = parse_edited_tags("# Tags for Weasyl-1\nfox\n\n  big wolf \n#comment\n");
+        assert_eq!(tags, vec!["fox", "big wolf"]);
+    }
+
+    #[test]
+    fn test_parse_submission_ids() {
+        let ids = parse_submission_ids("FurAffinity:123,weasyl:456\nWeasyl:7").unwrap();
+        assert_eq!(
+            ids,
+            vec![
+                (SubmissionSite::FurAffinity, 123),
+                (SubmissionSite::Weasyl, 456),
+                (SubmissionSite::Weasyl, 7)
+            ]
+        );
+
+        assert!(parse_submission_ids("123").is_err());
+        assert!(parse_submission_ids("Unknown:123").is_err());
+    }
+
+    #[test]
+    fn test_split_tags() {
+        assert_eq!(split_tags("tag1  tag2"), vec!["tag1", "tag2"]);
+        assert_eq!(
+            split_tags(r#"tag1 "digital painting" -"a b""#),
+            vec!["tag1", "digital painting", "-a b"]
+        );
+        assert_eq!(split_tags(r"digital\ painting"), vec!["digital painting"]);
+    }
+
+    #[test]
+    fn test_check_change_mode() {
+        assert!(check_change_mode("tag1 -tag2", false, false).is_ok());
+        assert!(check_change_mode("tag1 tag2", true, false).is_ok());
+        assert!(check_change_mode("tag1 -tag2", true, false).is_err());
+        assert!(check_change_mode("-tag1 -tag2", false, true).is_ok());
+        assert!(check_change_mode("tag1 -tag2", false, true).is_err());
     }
 }
@@ -0,0 +1,116 @@
+use crate::{
+    error,
+    sites::{Submission, SubmissionSite},
+};
+
+/// Tags queued to be set on a submission by a later `flush`.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct PendingChange {
+    pub site: SubmissionSite,
+    pub id: i64,
+    /// Tags the submission had when the change was first queued.
+    pub old_tags: Vec<String>,
+    pub new_tags: Vec<String>,
+    /// Every change that was queued for the submission.
+    pub changes: String,
+    pub queued_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Record the tags a submission should have, replacing anything already
+/// queued for it while keeping the tags it had before the first change.
+pub async fn queue(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    sub: &Submission,
+    new_tags: &[String],
+    changes: &str,
+) -> error::Result<()> {
+    let site = sub.site.as_str();
+    let old_tags = serde_json::to_string(&sub.tags)?;
+    let new_tags = serde_json::to_string(new_tags)?;
+    let now = chrono::Utc::now();
+
+    sqlx::query!(
+        "INSERT INTO pending_change (account, site, id, old_tags, new_tags, changes, queued_at) VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (account, site, id) DO UPDATE SET new_tags = excluded.new_tags, changes = pending_change.changes || '; ' || excluded.changes, queued_at = excluded.queued_at",
+        account,
+        site,
+        sub.id,
+        old_tags,
+        new_tags,
+        changes,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Every queued change for an account, oldest first.
+pub async fn load(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+) -> error::Result<Vec<PendingChange>> {
+    let rows = sqlx::query!(
+        "SELECT site, id, old_tags, new_tags, changes, queued_at FROM pending_change WHERE account = $1 ORDER BY queued_at",
+        account
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut pending = Vec::with_capacity(rows.len());
+    for row in rows {
+        pending.push(PendingChange {
+            site: row.site.parse()?,
+            id: row.id,
+            old_tags: serde_json::from_str(&row.old_tags)?,
+            new_tags: serde_json::from_str(&row.new_tags)?,
+            changes: row.changes,
+            queued_at: chrono::DateTime::<chrono::Utc>::from_utc(row.queued_at, chrono::Utc),
+        });
+    }
+
+    Ok(pending)
+}
+
+/// Remove queued changes that were applied by a batch.
+pub async fn clear_applied(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    batch_id: i64,
+) -> error::Result<u64> {
+    let cleared = sqlx::query!(
+        "DELETE FROM pending_change WHERE account = $1 AND EXISTS (
+            SELECT 1 FROM batch_change
+            WHERE batch_change.batch_id = $2 AND batch_change.site = pending_change.site AND batch_change.id = pending_change.id)",
+        account,
+        batch_id
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(cleared)
+}
+
+/// Remove a single queued change without applying it.
+pub async fn remove(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    site: SubmissionSite,
+    id: i64,
+) -> error::Result<()> {
+    let site = site.as_str();
+
+    sqlx::query!(
+        "DELETE FROM pending_change WHERE account = $1 AND site = $2 AND id = $3",
+        account,
+        site,
+        id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+
+use crate::{
+    config, parse_submission_ids,
+    query::Query,
+    sites::{Submission, SubmissionSite},
+    translate::Translations,
+    update_tags,
+};
+
+/// A set of changes to apply, saved for later review.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Plan {
+    #[serde(default, rename = "entry")]
+    pub entries: Vec<PlanEntry>,
+}
+
+/// Changes to apply to submissions matching a search or list of IDs.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct PlanEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ids: Option<String>,
+    pub tags: String,
+}
+
+impl Plan {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(path).context("Could not read plan file")?;
+        let plan: Self = toml::from_str(&data).context("Could not parse plan file")?;
+
+        for entry in &plan.entries {
+            match (&entry.search, &entry.ids) {
+                (Some(_), None) => (),
+                (None, Some(ids)) => {
+                    parse_submission_ids(ids)?;
+                }
+                _ => anyhow::bail!("Plan entry for {} needs either a search or IDs", entry.tags),
+            }
+        }
+
+        Ok(plan)
+    }
+
+    pub fn save(&self, path: &str) -> anyhow::Result<()> {
+        let data = toml::to_string_pretty(self).context("Could not serialize plan")?;
+        std::fs::write(path, data).context("Could not write plan file")
+    }
+
+    /// Apply every entry in order, with each one matching the tags left by
+    /// earlier entries. Returns the final tags of each submission that
+    /// changed.
+    pub fn run(
+        &self,
+        submissions: &[Submission],
+        translations: &Translations,
+        bundles: &config::Bundles,
+        sort: &config::TagSort,
+    ) -> anyhow::Result<HashMap<(SubmissionSite, i64), Vec<String>>> {
+        let mut working: Vec<Submission> = submissions.to_vec();
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            let query = entry
+                .search
+                .as_deref()
+                .map(|search| Query::parse_with(search, translations))
+                .transpose()?;
+            let ids = entry
+                .ids
+                .as_deref()
+                .map(parse_submission_ids)
+                .transpose()?
+                .unwrap_or_default();
+
+            let mut matched = 0;
+            for sub in working.iter_mut() {
+                let matches = match &query {
+                    Some(query) => query.matches(sub),
+                    None => ids.contains(&(sub.site, sub.id)),
+                };
+                if !matches {
+                    continue;
+                }
+
+                sub.tags = update_tags(sub, &sub.tags, &entry.tags, bundles, sort);
+                matched += 1;
+            }
+
+            tracing::info!(
+                "Entry {} matched {} submissions: {} => {}",
+                index + 1,
+                matched,
+                entry.describe_target(),
+                entry.tags
+            );
+        }
+
+        Ok(submissions
+            .iter()
+            .zip(working)
+            .filter(|(sub, planned)| sub.tags != planned.tags)
+            .map(|(sub, planned)| ((sub.site, sub.id), planned.tags))
+            .collect())
+    }
+
+    /// Every entry's changes, for the batch history.
+    pub fn describe(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| format!("{} => {}", entry.describe_target(), entry.tags))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+impl PlanEntry {
+    fn describe_target(&self) -> &str {
+        self.search
+            .as_deref()
+            .or(self.ids.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Plan, PlanEntry};
+    use crate::{sites::SubmissionSite, tests::submission};
+
+    #[test]
+    fn test_plan_run() {
+        let plan = Plan {
+            entries: vec![
+                PlanEntry {
+                    search: Some("wolf".to_string()),
+                    ids: None,
+                    tags: "canine".to_string(),
+                },
+                PlanEntry {
+                    search: Some("canine -fox".to_string()),
+                    ids: None,
+                    tags: "-wolf mammal".to_string(),
+                },
+                PlanEntry {
+                    search: None,
+                    ids: Some("FurAffinity:3".to_string()),
+                    tags: "feathers".to_string(),
+                },
+            ],
+        };
+
+        let submissions = vec![
+            submission(1, &["wolf"]),
+            submission(2, &["wolf", "fox"]),
+            submission(3, &["bird"]),
+            submission(4, &["bird"]),
+        ];
+
+        let changes = plan
+            .run(
+                &submissions,
+                &Default::default(),
+                &Default::default(),
+                &Default::default(),
+            )
+            .unwrap();
+        assert_eq!(changes.len(), 3);
+        assert_eq!(
+            changes[&(SubmissionSite::FurAffinity, 1)],
+            vec!["canine", "mammal"]
+        );
+        assert_eq!(
+            changes[&(SubmissionSite::FurAffinity, 2)],
+            vec!["wolf", "fox", "canine"]
+        );
+        assert_eq!(
+            changes[&(SubmissionSite::FurAffinity, 3)],
+            vec!["bird", "feathers"]
+        );
+    }
+}
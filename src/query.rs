@@ -0,0 +1,338 @@
+use crate::{
+    normalize_tag,
+    sites::{Submission, SubmissionSite, Visibility},
+    split_namespace, split_tags,
+    translate::Translations,
+    wildcard_matches,
+};
+
+/// A single condition from a search string.
+#[derive(Debug)]
+enum Term {
+    /// Submission must have a tag.
+    Tag(String),
+    /// Submission must have a tag or any of its translations.
+    Translated(Vec<String>),
+    /// Submission must have any tag in a namespace, written as `species:*`.
+    Namespace(String),
+    /// Submission must have a tag matching a pattern, where `*` matches any
+    /// text, such as `*_sketch`.
+    Wildcard(String),
+    /// Submission must have a tag within a few typos of a tag, ignoring
+    /// separators, written as `fuzzy:refsheet`.
+    Fuzzy(Vec<char>),
+    /// Submission must be in a folder, by name or ID.
+    Folder(String),
+    /// Submission's title must match, written as `title:text` for the whole
+    /// title, `title:~text` for part of it, or `title:/pattern/` for a
+    /// regular expression.
+    Title(TitleMatch),
+    /// Submission must have a local tag.
+    Local(String),
+    /// Submission must have no tags at all, written as `has:none`.
+    Untagged,
+    /// Submission must have no tag in a namespace, written as
+    /// `missing:species`.
+    Missing(String),
+    /// Submission must be on a site and have a site-specific field matching a
+    /// value, written as `fa.species:wolf`.
+    SiteData {
+        site: SubmissionSite,
+        key: String,
+        value: String,
+    },
+    /// Submission's number of tags must compare to a count, written as
+    /// `tagcount:<3` or `tagcount:>=10`.
+    TagCount(Comparison, usize),
+    /// Submission must be visible to a group, written as
+    /// `visibility:friends`.
+    Visibility(Visibility),
+    /// Submission's views, favorites, or comments on its site must compare
+    /// to a count, written as `faves:>100`. Submissions from sites that do
+    /// not report the count never match.
+    Popularity(&'static str, Comparison, usize),
+}
+
+/// How a title is matched in a term. Text is normalized like tags, so
+/// `title:~"for bob"` matches `Commission for Bob`.
+#[derive(Debug)]
+enum TitleMatch {
+    Exact(String),
+    Contains(String),
+    Pattern(regex::Regex),
+}
+
+impl TitleMatch {
+    fn parse(input: &str) -> anyhow::Result<Self> {
+        if let Some(pattern) = input
+            .strip_prefix('/')
+            .and_then(|pattern| pattern.strip_suffix('/'))
+        {
+            return regex::RegexBuilder::new(pattern)
+                .case_insensitive(true)
+                .build()
+                .map(Self::Pattern)
+                .map_err(|err| anyhow::anyhow!("Invalid title pattern /{}/: {}", pattern, err));
+        }
+
+        match input.strip_prefix('~') {
+            Some(text) if !text.is_empty() => Ok(Self::Contains(normalize_tag(text))),
+            None if !input.is_empty() => Ok(Self::Exact(normalize_tag(input))),
+            _ => anyhow::bail!("title:{} needs text to match", input),
+        }
+    }
+
+    fn matches(&self, title: &str) -> bool {
+        match self {
+            Self::Exact(text) => &normalize_tag(title) == text,
+            Self::Contains(text) => normalize_tag(title).contains(text.as_str()),
+            Self::Pattern(regex) => regex.is_match(title),
+        }
+    }
+}
+
+/// How a number is compared in a term.
+#[derive(Debug, Clone, Copy)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    /// Split a comparison from the start of a value, defaulting to equality.
+    fn parse(input: &str) -> (Self, &str) {
+        for (prefix, comparison) in [
+            ("<=", Self::LessOrEqual),
+            (">=", Self::GreaterOrEqual),
+            ("<", Self::Less),
+            (">", Self::Greater),
+            ("=", Self::Equal),
+        ] {
+            if let Some(value) = input.strip_prefix(prefix) {
+                return (comparison, value);
+            }
+        }
+
+        (Self::Equal, input)
+    }
+
+    fn compare(self, value: usize, target: usize) -> bool {
+        match self {
+            Self::Less => value < target,
+            Self::LessOrEqual => value <= target,
+            Self::Equal => value == target,
+            Self::GreaterOrEqual => value >= target,
+            Self::Greater => value > target,
+        }
+    }
+}
+
+impl Term {
+    /// Parse a single term, failing only for `title:` terms that can't be
+    /// used, as anything else can be searched as a tag.
+    fn parse(input: &str) -> anyhow::Result<Self> {
+        if let Some(term) = Self::parse_site_data(input) {
+            return Ok(term);
+        }
+
+        let term = match input.split_once(':') {
+            Some(("folder", folder)) => Self::Folder(normalize_tag(folder)),
+            Some(("title", title)) => Self::Title(TitleMatch::parse(title)?),
+            Some(("fuzzy", tag)) if !tag.is_empty() => Self::Fuzzy(without_separators(tag)),
+            Some(("local", tag)) => Self::Local(normalize_tag(tag)),
+            Some(("has", "none")) => Self::Untagged,
+            Some(("visibility", value)) => match value.parse() {
+                Ok(visibility) => Self::Visibility(visibility),
+                Err(_) => Self::Tag(normalize_tag(input)),
+            },
+            Some(("missing", namespace)) if !namespace.is_empty() => {
+                Self::Missing(normalize_tag(namespace))
+            }
+            Some(("tagcount", value)) => {
+                let (comparison, count) = Comparison::parse(value);
+                match count.parse() {
+                    Ok(count) => Self::TagCount(comparison, count),
+                    Err(_) => Self::Tag(normalize_tag(input)),
+                }
+            }
+            Some((key @ ("views" | "faves" | "favorites" | "comments"), value)) => {
+                let key = match key {
+                    "views" => "views",
+                    "comments" => "comments",
+                    _ => "favorites",
+                };
+                let (comparison, count) = Comparison::parse(value);
+                match count.parse() {
+                    Ok(count) => Self::Popularity(key, comparison, count),
+                    Err(_) => Self::Tag(normalize_tag(input)),
+                }
+            }
+            Some((namespace, "*")) if !namespace.is_empty() => {
+                Self::Namespace(normalize_tag(namespace))
+            }
+            _ if input.contains('*') => Self::Wildcard(normalize_tag(input)),
+            _ => Self::Tag(normalize_tag(input)),
+        };
+
+        Ok(term)
+    }
+
+    /// Parse a `site.field:value` term, where the site may be shortened to
+    /// `fa` for FurAffinity.
+    fn parse_site_data(input: &str) -> Option<Self> {
+        let (field, value) = input.split_once(':')?;
+        let (site, key) = field.split_once('.')?;
+        let site = match site.to_ascii_lowercase().as_str() {
+            "fa" => SubmissionSite::FurAffinity,
+            site => site.parse().ok()?,
+        };
+
+        Some(Self::SiteData {
+            site,
+            key: normalize_tag(key),
+            value: normalize_tag(value),
+        })
+    }
+
+    fn matches(&self, sub: &Submission, tags: &[String]) -> bool {
+        match self {
+            Self::Tag(tag) => tags.contains(tag),
+            Self::Translated(translations) => translations.iter().any(|tag| tags.contains(tag)),
+            Self::Namespace(namespace) => has_namespace(tags, namespace),
+            Self::Wildcard(pattern) => tags.iter().any(|tag| wildcard_matches(pattern, tag)),
+            Self::Fuzzy(target) => {
+                let allowed = allowed_typos(target);
+                tags.iter()
+                    .any(|tag| edit_distance(&without_separators(tag), target) <= allowed)
+            }
+            Self::Folder(folder) => {
+                sub.folder_id.map(|id| id.to_string()).as_ref() == Some(folder)
+                    || sub.folder_name.as_deref().map(normalize_tag).as_ref() == Some(folder)
+            }
+            Self::Title(title) => title.matches(&sub.title),
+            Self::Local(tag) => sub
+                .local_tags
+                .iter()
+                .any(|local_tag| &normalize_tag(local_tag) == tag),
+            Self::SiteData { site, key, value } => {
+                sub.site == *site
+                    && sub
+                        .site_data
+                        .get(key)
+                        .is_some_and(|data| wildcard_matches(value, &normalize_tag(data)))
+            }
+            Self::Untagged => tags.is_empty(),
+            Self::Missing(namespace) => !has_namespace(tags, namespace),
+            Self::TagCount(comparison, count) => comparison.compare(sub.tags.len(), *count),
+            Self::Visibility(visibility) => sub.visibility == *visibility,
+            Self::Popularity(key, comparison, count) => sub
+                .site_data
+                .get(*key)
+                .and_then(|value| value.parse().ok())
+                .is_some_and(|value| comparison.compare(value, *count)),
+        }
+    }
+}
+
+/// A tag normalized and with underscores and dashes removed, so `ref_sheet`
+/// and `ref-sheet` compare equal to `refsheet`.
+fn without_separators(tag: &str) -> Vec<char> {
+    normalize_tag(tag)
+        .chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .collect()
+}
+
+/// How many edits a fuzzy term allows, fewer for short tags so `fox` doesn't
+/// match `box`.
+fn allowed_typos(tag: &[char]) -> usize {
+    match tag.len() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Number of single character insertions, removals, or substitutions needed
+/// to turn one string into another.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// If any normalized tag is in a namespace.
+fn has_namespace(tags: &[String], namespace: &str) -> bool {
+    tags.iter()
+        .any(|tag| matches!(split_namespace(tag), Some((ns, _value)) if ns == namespace))
+}
+
+/// A parsed search string, where every term must match.
+#[derive(Debug)]
+pub struct Query {
+    terms: Vec<(bool, Term)>,
+}
+
+impl Query {
+    /// Parse a search where tags also match any of their translations. Fails
+    /// for `title:` terms with an invalid pattern or nothing to match.
+    pub fn parse_with(input: &str, translations: &Translations) -> anyhow::Result<Self> {
+        let translate = |term: Term| match term {
+            Term::Tag(tag) if !translations.counterparts(&tag).is_empty() => {
+                let mut tags = vec![tag];
+                tags.extend_from_slice(translations.counterparts(&tags[0]));
+                Term::Translated(tags)
+            }
+            term => term,
+        };
+
+        let terms = split_tags(input)
+            .iter()
+            .map(|term| -> anyhow::Result<_> {
+                match term.strip_prefix('-') {
+                    Some(term) => Ok((true, translate(Term::parse(term)?))),
+                    None => Ok((false, translate(Term::parse(term)?))),
+                }
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { terms })
+    }
+
+    /// Normalized tags every matching submission must have.
+    pub fn required_tags(&self) -> Vec<String> {
+        let mut tags: Vec<_> = self
+            .terms
+            .iter()
+            .filter_map(|(negated, term)| match term {
+                Term::Tag(tag) if !negated => Some(tag.clone()),
+                _ => None,
+            })
+            .collect();
+        tags.sort();
+        tags.dedup();
+
+        tags
+    }
+
+    pub fn matches(&self, sub: &Submission) -> bool {
+        let tags: Vec<_> = sub.tags.iter().map(|tag| normalize_tag(tag)).collect();
+
+        self.terms
+            .iter()
+            .all(|(negated, term)| term.matches(sub, &tags) != *negated)
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    normalize_tag,
+    sites::Submission,
+    web::{escape, page},
+};
+
+/// Number of tags shown in the tag cloud.
+const CLOUD_LIMIT: usize = 100;
+
+/// A tag change made by a recorded batch.
+#[derive(Debug)]
+pub struct HistoryChange {
+    pub batch_id: i64,
+    pub command: String,
+    pub started_at: chrono::NaiveDateTime,
+    pub site: String,
+    pub id: i64,
+    pub old_tags: Vec<String>,
+    pub new_tags: Vec<String>,
+}
+
+/// Filters submission rows as text is typed into the search box.
+const SEARCH_SCRIPT: &str = r##"<script>
+document.getElementById("search").addEventListener("input", (event) => {
+    const terms = event.target.value.toLowerCase().split(/\s+/).filter(Boolean);
+    for (const row of document.querySelectorAll("#submissions tr[data-search]")) {
+        const text = row.dataset.search;
+        row.hidden = !terms.every((term) => text.includes(term));
+    }
+});
+</script>"##;
+
+/// Render a standalone HTML page with every submission, a tag cloud, and
+/// recent tag changes.
+pub fn render(submissions: &[Submission], history: &[HistoryChange]) -> String {
+    let mut body = format!(
+        "<p>{} submissions, generated {}</p>",
+        submissions.len(),
+        chrono::Local::now().format("%Y-%m-%d %H:%M")
+    );
+
+    body.push_str("<h2>Tags</h2>");
+    body.push_str(&tag_cloud(submissions));
+
+    body.push_str("<h2>Recent changes</h2>");
+    body.push_str(&history_table(history));
+
+    body.push_str("<h2>Submissions</h2>");
+    body.push_str(r#"<p><input id="search" type="search" placeholder="Filter by title or tags" size="50"></p>"#);
+    body.push_str(
+        r#"<table id="submissions"><tr><th>Submission</th><th>Posted</th><th>Tags</th></tr>"#,
+    );
+    for sub in submissions {
+        let search = format!("{} {} {}", sub.site, sub.title, sub.tags.join(" ")).to_lowercase();
+        let title = match sub.url() {
+            Some(url) => format!(r#"<a href="{}">{}</a>"#, escape(&url), escape(&sub.title)),
+            None => escape(&sub.title),
+        };
+
+        body.push_str(&format!(
+            r#"<tr data-search="{}"><td>{}-{} {}</td><td>{}</td><td>{}</td></tr>"#,
+            escape(&search),
+            sub.site,
+            sub.id,
+            title,
+            sub.posted_at.format("%Y-%m-%d"),
+            escape(&sub.tags.join(", "))
+        ));
+    }
+    body.push_str("</table>");
+    body.push_str(SEARCH_SCRIPT);
+
+    page("Tag report", &body)
+}
+
+/// The most used tags, sized by how often they are used.
+fn tag_cloud(submissions: &[Submission]) -> String {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for tag in submissions.iter().flat_map(|sub| &sub.tags) {
+        *counts.entry(normalize_tag(tag)).or_default() += 1;
+    }
+
+    let mut tags: Vec<_> = counts.into_iter().collect();
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    tags.truncate(CLOUD_LIMIT);
+    tags.sort();
+
+    let max = tags.iter().map(|(_tag, count)| *count).max().unwrap_or(1);
+
+    let words: Vec<_> = tags
+        .iter()
+        .map(|(tag, count)| {
+            format!(
+                r#"<span title="{count}" style="font-size: {size:.2}em">{tag}</span>"#,
+                count = count,
+                size = 0.8 + 1.6 * *count as f64 / max as f64,
+                tag = escape(tag)
+            )
+        })
+        .collect();
+
+    format!("<p>{}</p>", words.join(" "))
+}
+
+fn history_table(history: &[HistoryChange]) -> String {
+    if history.is_empty() {
+        return "<p>No changes have been recorded.</p>".to_string();
+    }
+
+    let mut out = String::from("<table><tr><th>Batch</th><th>Submission</th><th>Changes</th></tr>");
+
+    for change in history {
+        let tags: Vec<_> = change
+            .new_tags
+            .iter()
+            .filter(|tag| !change.old_tags.contains(tag))
+            .map(|tag| format!("<ins>+{}</ins>", escape(tag)))
+            .chain(
+                change
+                    .old_tags
+                    .iter()
+                    .filter(|tag| !change.new_tags.contains(tag))
+                    .map(|tag| format!("<del>-{}</del>", escape(tag))),
+            )
+            .collect();
+
+        out.push_str(&format!(
+            "<tr><td>{} {} {}</td><td>{}-{}</td><td>{}</td></tr>",
+            change.batch_id,
+            change.started_at.format("%Y-%m-%d %H:%M"),
+            escape(&change.command),
+            escape(&change.site),
+            change.id,
+            tags.join(" ")
+        ));
+    }
+
+    out.push_str("</table>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, HistoryChange};
+    use crate::tests::submission;
+
+    #[test]
+    fn test_render() {
+        let submissions = vec![submission(1, &["wolf", "<b>"]), submission(2, &["wolf"])];
+        let history = vec![HistoryChange {
+            batch_id: 3,
+            command: "apply-tags".to_string(),
+            started_at: chrono::NaiveDate::from_ymd(2021, 12, 18).and_hms(12, 0, 0),
+            site: "FurAffinity".to_string(),
+            id: 2,
+            old_tags: vec!["fox".to_string()],
+            new_tags: vec!["wolf".to_string()],
+        }];
+
+        let html = render(&submissions, &history);
+        assert!(html.contains(r#"<span title="2" style="font-size: 2.40em">wolf</span>"#));
+        assert!(html.contains("&lt;b&gt;"));
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("<ins>+wolf</ins> <del>-fox</del>"));
+        assert!(html.contains(r#"href="https://www.furaffinity.net/view/1/""#));
+    }
+}
@@ -0,0 +1,299 @@
+use std::sync::Mutex;
+
+use crate::{error, sites::Submission};
+
+/// Run this process is recording progress for, if any.
+static CURRENT: Mutex<Option<i64>> = Mutex::new(None);
+
+/// Parts of flag names whose values are credentials, which are never stored.
+const SECRET_FLAGS: &[&str] = &[
+    "cookie",
+    "key",
+    "token",
+    "secret",
+    "password",
+    "passphrase",
+    "session",
+    "totp",
+];
+
+/// A stored run of the tool, with how far it got.
+#[derive(Debug)]
+pub struct Run {
+    pub id: i64,
+    pub command: String,
+    pub args: String,
+    pub started_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    pub status: String,
+    pub error: Option<String>,
+    pub total: i64,
+    pub completed: i64,
+    pub failed: i64,
+    pub last_submission: Option<String>,
+    pub batch_id: Option<i64>,
+}
+
+impl Run {
+    /// If the run never finished, because it was killed, crashed, or is still
+    /// going in another process.
+    pub fn unfinished(&self) -> bool {
+        self.status == "running" && current() != Some(self.id)
+    }
+
+    pub fn describe(&self) -> String {
+        let status = if self.unfinished() {
+            format!(
+                "did not finish, last progress at {}",
+                self.updated_at.format("%Y-%m-%d %H:%M:%S")
+            )
+        } else {
+            self.status.clone()
+        };
+
+        let mut line = format!(
+            "Run {} - {} {}: {}, {} of {} submissions updated, {} failed",
+            self.id,
+            self.started_at.format("%Y-%m-%d %H:%M:%S"),
+            self.command,
+            status,
+            self.completed,
+            self.total,
+            self.failed
+        );
+        if let Some(last_submission) = &self.last_submission {
+            line.push_str(&format!(", last {}", last_submission));
+        }
+        if let Some(batch_id) = self.batch_id {
+            line.push_str(&format!(", batch {}", batch_id));
+        }
+
+        line
+    }
+}
+
+fn current() -> Option<i64> {
+    *CURRENT.lock().unwrap()
+}
+
+/// Arguments with the values of credential flags replaced, so they can be
+/// stored with the run.
+pub fn redact_args<I>(args: I) -> Vec<String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let is_secret = |flag: &str| SECRET_FLAGS.iter().any(|secret| flag.contains(secret));
+
+    let mut redacted = Vec::new();
+    let mut hide_next = false;
+
+    for arg in args {
+        if hide_next {
+            redacted.push("<redacted>".to_string());
+            hide_next = false;
+            continue;
+        }
+
+        match arg.strip_prefix("--") {
+            Some(flag) => match flag.split_once('=') {
+                Some((name, _value)) if is_secret(name) => {
+                    redacted.push(format!("--{}=<redacted>", name));
+                }
+                Some(_) => redacted.push(arg),
+                None => {
+                    hide_next = is_secret(flag);
+                    redacted.push(arg);
+                }
+            },
+            None => redacted.push(arg),
+        }
+    }
+
+    redacted
+}
+
+/// Record that a command started, so it appears in history even if the
+/// process is killed before finishing.
+pub async fn start(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    command: &str,
+    args: &[String],
+    log_id: &str,
+) -> error::Result<()> {
+    let args = args.join(" ");
+    let now = chrono::Utc::now();
+
+    let id = sqlx::query!(
+        "INSERT INTO run (account, command, args, log_id, started_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6)",
+        account,
+        command,
+        args,
+        log_id,
+        now,
+        now
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    *CURRENT.lock().unwrap() = Some(id);
+    tracing::debug!(run_id = id, "Started run");
+
+    Ok(())
+}
+
+/// Record how many more submissions the run is going to update.
+pub async fn add_total(pool: &sqlx::Pool<sqlx::Sqlite>, count: usize) {
+    let id = match current() {
+        Some(id) => id,
+        None => return,
+    };
+    let count = count as i64;
+    let now = chrono::Utc::now();
+
+    let result = sqlx::query!(
+        "UPDATE run SET total = total + $2, updated_at = $3 WHERE id = $1",
+        id,
+        count,
+        now
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(err) = result {
+        tracing::warn!("Could not record run progress: {:?}", err);
+    }
+}
+
+/// Record a submission being updated, or failing to update.
+pub async fn record_progress(pool: &sqlx::Pool<sqlx::Sqlite>, sub: &Submission, ok: bool) {
+    let id = match current() {
+        Some(id) => id,
+        None => return,
+    };
+    let (completed, failed) = if ok { (1, 0) } else { (0, 1) };
+    let last_submission = format!("{}-{}", sub.site, sub.id);
+    let now = chrono::Utc::now();
+
+    let result = sqlx::query!(
+        "UPDATE run SET completed = completed + $2, failed = failed + $3, last_submission = $4, updated_at = $5 WHERE id = $1",
+        id,
+        completed,
+        failed,
+        last_submission,
+        now
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(err) = result {
+        tracing::warn!("Could not record run progress: {:?}", err);
+    }
+}
+
+/// Record the batch the run is currently making changes in.
+pub async fn record_batch(pool: &sqlx::Pool<sqlx::Sqlite>, batch_id: i64) {
+    let id = match current() {
+        Some(id) => id,
+        None => return,
+    };
+    let now = chrono::Utc::now();
+
+    let result = sqlx::query!(
+        "UPDATE run SET batch_id = $2, updated_at = $3 WHERE id = $1",
+        id,
+        batch_id,
+        now
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(err) = result {
+        tracing::warn!("Could not record run batch: {:?}", err);
+    }
+}
+
+/// Record how the run ended.
+pub async fn finish(pool: &sqlx::Pool<sqlx::Sqlite>, result: &anyhow::Result<()>) {
+    let id = match CURRENT.lock().unwrap().take() {
+        Some(id) => id,
+        None => return,
+    };
+    let (status, error) = match result {
+        Ok(()) => ("succeeded", None),
+        Err(err) => ("failed", Some(format!("{:#}", err))),
+    };
+    let now = chrono::Utc::now();
+
+    let result = sqlx::query!(
+        "UPDATE run SET status = $2, error = $3, finished_at = $4, updated_at = $5 WHERE id = $1",
+        id,
+        status,
+        error,
+        now,
+        now
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(err) = result {
+        tracing::warn!("Could not record run result: {:?}", err);
+    }
+}
+
+/// Most recent runs for an account.
+pub async fn recent(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    limit: i64,
+) -> error::Result<Vec<Run>> {
+    let runs = sqlx::query_as!(
+        Run,
+        "SELECT id, command, args, started_at, updated_at, status, error, total, completed, failed, last_submission, batch_id
+            FROM run
+            WHERE account = $1
+            ORDER BY id DESC
+            LIMIT $2",
+        account,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(runs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::redact_args;
+
+    #[test]
+    fn test_redact_args() {
+        let args = [
+            "--furaffinity-cookie-a",
+            "secret-a",
+            "--weasyl-api-key=secret-key",
+            "--profile",
+            "main",
+            "apply-tags",
+            "--search",
+            "wolf",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            redact_args(args),
+            vec![
+                "--furaffinity-cookie-a",
+                "<redacted>",
+                "--weasyl-api-key=<redacted>",
+                "--profile",
+                "main",
+                "apply-tags",
+                "--search",
+                "wolf",
+            ]
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! A fake FurAffinity or Weasyl server that serves recorded pages and keeps
+//! every request it receives, so the exact forms a change would submit can be
+//! checked without live accounts.
+
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+
+/// A request received by a simulated site.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: Method,
+    pub path: String,
+    pub body: String,
+}
+
+/// A recorded response for a method and path.
+struct Fixture {
+    method: Method,
+    path: &'static str,
+    content_type: &'static str,
+    body: &'static str,
+}
+
+#[derive(Default)]
+struct Inner {
+    fixtures: Vec<Fixture>,
+    requests: Mutex<Vec<Request>>,
+}
+
+/// Recorded responses to serve, by method and path. Anything else gets an
+/// empty 404.
+#[derive(Default)]
+pub struct SimulatedSite {
+    fixtures: Vec<Fixture>,
+}
+
+impl SimulatedSite {
+    pub fn html(mut self, method: Method, path: &'static str, body: &'static str) -> Self {
+        self.fixtures.push(Fixture {
+            method,
+            path,
+            content_type: "text/html; charset=utf-8",
+            body,
+        });
+        self
+    }
+
+    pub fn json(mut self, method: Method, path: &'static str, body: &'static str) -> Self {
+        self.fixtures.push(Fixture {
+            method,
+            path,
+            content_type: "application/json",
+            body,
+        });
+        self
+    }
+
+    /// Start serving on a random local port.
+    pub async fn start(self) -> RunningSite {
+        let inner = Arc::new(Inner {
+            fixtures: self.fixtures,
+            ..Default::default()
+        });
+        let app = Router::new().fallback(respond).with_state(inner.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        RunningSite {
+            url: format!("http://{}", address),
+            inner,
+        }
+    }
+}
+
+/// A simulated site accepting requests.
+pub struct RunningSite {
+    pub url: String,
+    inner: Arc<Inner>,
+}
+
+impl RunningSite {
+    /// Every request received with a method, oldest first.
+    pub fn requests(&self, method: Method) -> Vec<Request> {
+        self.inner
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.method == method)
+            .cloned()
+            .collect()
+    }
+}
+
+async fn respond(
+    State(inner): State<Arc<Inner>>,
+    method: Method,
+    uri: Uri,
+    body: Bytes,
+) -> Response {
+    inner.requests.lock().unwrap().push(Request {
+        method: method.clone(),
+        path: uri.path().to_string(),
+        body: String::from_utf8_lossy(&body).into_owned(),
+    });
+
+    match inner
+        .fixtures
+        .iter()
+        .find(|fixture| fixture.method == method && fixture.path == uri.path())
+    {
+        Some(fixture) => {
+            ([(header::CONTENT_TYPE, fixture.content_type)], fixture.body).into_response()
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+
+    use super::SimulatedSite;
+    use crate::{
+        error::Error,
+        sites::{FurAffinity, Site, SubmissionSite, Weasyl},
+    };
+
+    const FA_CHANGEINFO: &str = include_str!("simulation/furaffinity_changeinfo.html");
+    const FA_VIEW: &str = include_str!("simulation/furaffinity_view.html");
+    const WEASYL_SUBMISSION: &str = include_str!("simulation/weasyl_submission.json");
+
+    async fn furaffinity() -> super::RunningSite {
+        SimulatedSite::default()
+            .html(
+                Method::GET,
+                "/controls/submissions/changeinfo/123/",
+                FA_CHANGEINFO,
+            )
+            .html(
+                Method::POST,
+                "/controls/submissions/changeinfo/123/",
+                "<html></html>",
+            )
+            .html(Method::GET, "/view/123/", FA_VIEW)
+            .start()
+            .await
+    }
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_furaffinity_set_tags() {
+        let server = furaffinity().await;
+        let site = FurAffinity::new("a", "b", "user".to_string()).with_base_url(&server.url);
+
+        site.set_tags(123, &tags(&["wolf", "grey fur", "commission"]))
+            .await
+            .unwrap();
+
+        let posts = server.requests(Method::POST);
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].path, "/controls/submissions/changeinfo/123/");
+        assert_eq!(
+            posts[0].body,
+            "update=yes&submit=%2BFinalize&keywords=wolf+grey_fur+commission\
+                &key=0123456789abcdef&cat=1&atype=1&species=5014&gender=2&rating=0\
+                &title=Grey+Wolf+Commission&message=Commission+for+a+friend.\
+                &folder_ids%5B%5D=41"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_furaffinity_set_tags_in_folder() {
+        let server = furaffinity().await;
+        let site = FurAffinity::new("a", "b", "user".to_string()).with_base_url(&server.url);
+
+        site.set_tags_in_folder(123, &tags(&["wolf", "grey_fur", "commission"]), "sketches")
+            .await
+            .unwrap();
+
+        let posts = server.requests(Method::POST);
+        assert!(posts[0]
+            .body
+            .ends_with("&folder_ids%5B%5D=41&folder_ids%5B%5D=42"));
+    }
+
+    #[tokio::test]
+    async fn test_furaffinity_get_submission() {
+        let server = furaffinity().await;
+        let site = FurAffinity::new("a", "b", "user".to_string()).with_base_url(&server.url);
+
+        let sub = site.get_submission(123).await.unwrap();
+        assert_eq!(sub.title, "Grey Wolf Commission");
+        assert_eq!(sub.tags, tags(&["wolf", "grey_fur", "commission"]));
+        assert_eq!(sub.site_data["species"], "Wolf");
+        assert_eq!(sub.site_data["rating"], "general");
+        assert_eq!(sub.site_data["views"], "1204");
+        assert_eq!(sub.site_data["favorites"], "97");
+    }
+
+    #[tokio::test]
+    async fn test_furaffinity_content_filtered() {
+        let server = SimulatedSite::default()
+            .html(
+                Method::GET,
+                "/view/456/",
+                r#"<html><body><div class="notice-message">This submission contains Mature or Adult content. To view this submission you must log in and enable the Mature or Adult content.</div></body></html>"#,
+            )
+            .start()
+            .await;
+        let site = FurAffinity::new("a", "b", "user".to_string()).with_base_url(&server.url);
+
+        // Logging in again can't fix this, so it must not look like an
+        // expired session.
+        let err = site.get_submission(456).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::ContentFiltered {
+                site: SubmissionSite::FurAffinity
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_weasyl_set_tags() {
+        let server = SimulatedSite::default()
+            .html(Method::POST, "/submit/tags", "<html></html>")
+            .json(Method::GET, "/api/submissions/123/view", WEASYL_SUBMISSION)
+            .start()
+            .await;
+        let site = Weasyl::new("key", "user".to_string()).with_base_url(&server.url);
+
+        site.set_tags(123, &tags(&["wolf", "grey fur"]))
+            .await
+            .unwrap();
+
+        let posts = server.requests(Method::POST);
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].path, "/submit/tags");
+        assert_eq!(posts[0].body, "submitid=123&tags=wolf%2C+grey_fur");
+    }
+}
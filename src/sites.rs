@@ -1,16 +1,33 @@
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display, str::FromStr, sync::Mutex};
 
 use async_trait::async_trait;
 
+use crate::error::{CheckStatus, Error, Result};
+
+pub use derpibooru::Derpibooru;
+pub use external::External;
 pub use furaffinity::FurAffinity;
+pub use local_folder::LocalFolder;
+pub use mastodon::Mastodon;
+pub use twitter::Twitter;
 pub use weasyl::Weasyl;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum SubmissionSite {
     FurAffinity,
     Weasyl,
+    Derpibooru,
+    Mastodon,
+    Twitter,
+    /// Image files in a local directory, with tags in sidecar files.
+    LocalFolder,
+    /// A site handled by an external program, by its configured name.
+    External(&'static str),
 }
 
+/// Names of external sites that have been configured.
+static EXTERNAL_SITES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
 impl Display for SubmissionSite {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -20,32 +37,351 @@ impl Display for SubmissionSite {
             SubmissionSite::Weasyl => {
                 write!(f, "Weasyl")
             }
+            SubmissionSite::Derpibooru => {
+                write!(f, "Derpibooru")
+            }
+            SubmissionSite::Mastodon => {
+                write!(f, "Mastodon")
+            }
+            SubmissionSite::Twitter => {
+                write!(f, "Twitter")
+            }
+            SubmissionSite::LocalFolder => {
+                write!(f, "LocalFolder")
+            }
+            SubmissionSite::External(name) => {
+                write!(f, "{}", name)
+            }
         }
     }
 }
 
 impl SubmissionSite {
+    pub const ALL: [SubmissionSite; 6] = [
+        Self::Weasyl,
+        Self::FurAffinity,
+        Self::Derpibooru,
+        Self::Mastodon,
+        Self::Twitter,
+        Self::LocalFolder,
+    ];
+
+    /// Canonical page URL for a submission on this site, if it can be known
+    /// from the ID alone. Derpibooru URLs always use the default booru.
+    pub fn url(&self, id: i64) -> Option<String> {
+        match self {
+            Self::FurAffinity => Some(format!("https://www.furaffinity.net/view/{}/", id)),
+            Self::Weasyl => Some(format!("https://www.weasyl.com/submission/{}", id)),
+            Self::Derpibooru => Some(format!("https://derpibooru.org/images/{}", id)),
+            Self::Mastodon => None,
+            Self::Twitter => Some(format!("https://twitter.com/i/web/status/{}", id)),
+            Self::LocalFolder | Self::External(_) => None,
+        }
+    }
+
+    /// Register the name of an external site so it can be parsed like any
+    /// other site. Names may not be reused from built in sites.
+    pub fn register_external(name: &str) -> anyhow::Result<Self> {
+        if let Ok(site) = name.parse::<Self>() {
+            match site {
+                Self::External(existing) if existing == name => return Ok(site),
+                _ => anyhow::bail!("external site name {} is already used", name),
+            }
+        }
+
+        if name.is_empty() || name.contains(|c: char| c == ':' || c.is_whitespace()) {
+            anyhow::bail!("invalid external site name: {:?}", name);
+        }
+
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        EXTERNAL_SITES.lock().unwrap().push(name);
+
+        Ok(Self::External(name))
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             Self::FurAffinity => "FurAffinity",
             Self::Weasyl => "Weasyl",
+            Self::Derpibooru => "Derpibooru",
+            Self::Mastodon => "Mastodon",
+            Self::Twitter => "Twitter",
+            Self::LocalFolder => "LocalFolder",
+            Self::External(name) => name,
+        }
+    }
+}
+
+impl FromStr for SubmissionSite {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "furaffinity" => Ok(Self::FurAffinity),
+            "weasyl" => Ok(Self::Weasyl),
+            "derpibooru" => Ok(Self::Derpibooru),
+            "mastodon" => Ok(Self::Mastodon),
+            "twitter" => Ok(Self::Twitter),
+            "localfolder" => Ok(Self::LocalFolder),
+            _ => EXTERNAL_SITES
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|name| name.eq_ignore_ascii_case(s))
+                .map(|name| Self::External(name))
+                .ok_or_else(|| anyhow::anyhow!("unknown site: {}", s)),
+        }
+    }
+}
+
+/// Who can see a submission on its site.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    #[default]
+    Public,
+    /// Only visible to the artist's friends.
+    Friends,
+    /// Only visible to the artist.
+    Hidden,
+}
+
+impl Visibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Public => "public",
+            Self::Friends => "friends",
+            Self::Hidden => "hidden",
+        }
+    }
+}
+
+impl FromStr for Visibility {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "public" => Ok(Self::Public),
+            "friends" => Ok(Self::Friends),
+            "hidden" => Ok(Self::Hidden),
+            _ => anyhow::bail!("unknown visibility: {}", s),
         }
     }
 }
 
-#[derive(Debug)]
+impl serde::Serialize for SubmissionSite {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for SubmissionSite {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Fields in [`Submission::site_data`] holding how popular a submission is,
+/// for sites that report it.
+pub const POPULARITY_KEYS: &[&str] = &["views", "favorites", "comments"];
+
+#[derive(Clone, Debug, serde::Serialize)]
 pub struct Submission {
-    pub id: i32,
+    pub id: i64,
     pub site: SubmissionSite,
     pub title: String,
     pub posted_at: chrono::DateTime<chrono::Local>,
     pub tags: Vec<String>,
+    /// URL of the submission's primary file, if available.
+    pub file_url: Option<String>,
+    pub folder_id: Option<i32>,
+    pub folder_name: Option<String>,
+    /// Tags only stored locally, never sent to the site.
+    pub local_tags: Vec<String>,
+    /// Free-form local notes.
+    pub notes: Option<String>,
+    /// When the submission was no longer found on the site.
+    pub deleted_at: Option<chrono::DateTime<chrono::Local>>,
+    /// Fields only some sites have, such as FurAffinity's species, stored as
+    /// JSON and searched as `fa.species:wolf`.
+    pub site_data: BTreeMap<String, String>,
+    /// Who can see the submission, for sites that can restrict it.
+    pub visibility: Visibility,
+}
+
+impl Submission {
+    /// Canonical page URL for this submission, if it can be known.
+    pub fn url(&self) -> Option<String> {
+        self.site.url(self.id)
+    }
+}
+
+/// Changes to a submission other than its tags. Fields that are `None` are
+/// left unchanged.
+#[derive(Clone, Debug, Default)]
+pub struct MetadataChanges {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Rating by name, such as `general` or `mature`, or the site's own code.
+    pub rating: Option<String>,
+    /// Site-specific fields, named like [`Submission::site_data`].
+    pub extras: BTreeMap<String, String>,
+}
+
+impl MetadataChanges {
+    /// Names of every field being changed.
+    pub fn fields(&self) -> Vec<String> {
+        [
+            (self.title.is_some(), "title"),
+            (self.description.is_some(), "description"),
+            (self.rating.is_some(), "rating"),
+        ]
+        .into_iter()
+        .filter(|(changed, _name)| *changed)
+        .map(|(_changed, name)| name.to_string())
+        .chain(self.extras.keys().cloned())
+        .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields().is_empty()
+    }
 }
 
 #[async_trait]
-pub trait Site {
-    async fn get_all_submissions(&self) -> anyhow::Result<Vec<Submission>>;
-    async fn set_tags(&self, id: i32, tags: &[String]) -> anyhow::Result<()>;
+pub trait Site: Send + Sync {
+    async fn get_all_submissions(&self) -> Result<Vec<Submission>>;
+    async fn set_tags(&self, id: i64, tags: &[String]) -> Result<()>;
+
+    /// Load the current state of a single submission from the site.
+    async fn get_submission(&self, _id: i64) -> Result<Submission> {
+        Err(anyhow::anyhow!("site does not support loading individual submissions").into())
+    }
+
+    /// Load only the current tags of a single submission from the site.
+    async fn get_tags(&self, id: i64) -> Result<Vec<String>> {
+        Ok(self.get_submission(id).await?.tags)
+    }
+
+    /// IDs and names of the folders submissions can be moved into.
+    async fn list_folders(&self) -> Result<Vec<(i32, String)>> {
+        Err(anyhow::anyhow!("site does not support folders").into())
+    }
+
+    /// Move a submission into a folder.
+    async fn move_folder(&self, _id: i64, _folder_id: i32) -> Result<()> {
+        Err(anyhow::anyhow!("site does not support folders").into())
+    }
+
+    /// Set a submission's tags and add it to a folder, given by name or ID,
+    /// in a single update.
+    async fn set_tags_in_folder(&self, _id: i64, _tags: &[String], _folder: &str) -> Result<()> {
+        Err(anyhow::anyhow!("site does not support setting folders along with tags").into())
+    }
+
+    /// Change a submission's title, description, rating, or site-specific
+    /// fields, returning the names of fields the site does not support.
+    /// Supported fields are still changed when others are not.
+    async fn update_metadata(&self, _id: i64, changes: MetadataChanges) -> Result<Vec<String>> {
+        Ok(changes.fields())
+    }
+
+    /// Minimum time to wait between updating submissions.
+    fn update_delay(&self) -> std::time::Duration;
+
+    /// Changes that can be made to submissions on this site. Sites without
+    /// any are only imported for searching and cross-referencing.
+    fn capabilities(&self) -> SiteCapabilities;
+
+    /// Ensure the credentials belong to the configured user, so changes are
+    /// never made using someone else's account.
+    async fn verify_ownership(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Number of submissions the site reports for the user, if it shows one,
+    /// to check that loading found all of them.
+    async fn reported_count(&self) -> Result<Option<usize>> {
+        Ok(None)
+    }
+}
+
+/// Changes a site supports making to submissions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SiteCapabilities {
+    pub tags: bool,
+    pub descriptions: bool,
+    pub ratings: bool,
+    pub folders: bool,
+}
+
+impl SiteCapabilities {
+    /// Names of each supported change.
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.tags, "tags"),
+            (self.descriptions, "descriptions"),
+            (self.ratings, "ratings"),
+            (self.folders, "folders"),
+        ]
+        .into_iter()
+        .filter(|(supported, _name)| *supported)
+        .map(|(_supported, name)| name)
+        .collect()
+    }
+}
+
+/// Requested tags missing from the tags a site actually saved.
+fn rejected_tags(requested: &[String], saved: &[String]) -> Vec<String> {
+    let saved: Vec<_> = saved.iter().map(|tag| crate::normalize_tag(tag)).collect();
+
+    requested
+        .iter()
+        .filter(|tag| !saved.contains(&crate::normalize_tag(tag)))
+        .cloned()
+        .collect()
+}
+
+/// All sites configured for the current profile.
+#[derive(Default)]
+pub struct Sites {
+    pub furaffinity: Option<FurAffinity>,
+    pub weasyl: Option<Weasyl>,
+    pub derpibooru: Option<Derpibooru>,
+    pub mastodon: Option<Mastodon>,
+    pub twitter: Option<Twitter>,
+    pub local_folder: Option<LocalFolder>,
+    pub external: Vec<External>,
+}
+
+impl Sites {
+    /// Get the client for a site, failing if it was not configured.
+    pub fn get(&self, site: SubmissionSite) -> anyhow::Result<&dyn Site> {
+        let client: Option<&dyn Site> = match site {
+            SubmissionSite::FurAffinity => self.furaffinity.as_ref().map(|site| site as _),
+            SubmissionSite::Weasyl => self.weasyl.as_ref().map(|site| site as _),
+            SubmissionSite::Derpibooru => self.derpibooru.as_ref().map(|site| site as _),
+            SubmissionSite::Mastodon => self.mastodon.as_ref().map(|site| site as _),
+            SubmissionSite::Twitter => self.twitter.as_ref().map(|site| site as _),
+            SubmissionSite::LocalFolder => self.local_folder.as_ref().map(|site| site as _),
+            SubmissionSite::External(name) => self
+                .external
+                .iter()
+                .find(|external| external.name() == name)
+                .map(|site| site as _),
+        };
+
+        client.ok_or_else(|| anyhow::anyhow!("{} is not configured for this profile", site))
+    }
+
+    /// Every built in site followed by each external site.
+    pub fn all(&self) -> Vec<SubmissionSite> {
+        SubmissionSite::ALL
+            .into_iter()
+            .chain(self.external.iter().map(|external| external.site()))
+            .collect()
+    }
 }
 
 mod furaffinity {
@@ -59,14 +395,19 @@ mod furaffinity {
 
     pub struct FurAffinity {
         client: reqwest::Client,
-        cookies: String,
+        /// Replaced when logging in again after the session expires.
+        cookies: std::sync::RwLock<String>,
 
         user: String,
+        base_url: String,
 
         id_selector: scraper::Selector,
         title_selector: scraper::Selector,
         posted_at_selector: scraper::Selector,
         tag_selector: scraper::Selector,
+        download_selector: scraper::Selector,
+        info_selector: scraper::Selector,
+        rating_selector: scraper::Selector,
 
         date_cleaner: regex::Regex,
     }
@@ -81,14 +422,23 @@ mod furaffinity {
         rating: String,
         title: String,
         message: String,
+        /// Value and name of every folder the submission can be assigned to.
+        folders: Vec<(String, String)>,
+        /// Values of the folders the submission is currently in.
+        folder_ids: Vec<String>,
+        /// Current keywords, only needed when they are not being replaced.
+        keywords: Option<String>,
+        /// Value and name of every option for the category, type, species,
+        /// and gender fields, by field name.
+        options: HashMap<&'static str, Vec<(String, String)>>,
     }
 
+    const KEYWORDS_SELECTOR: &str = r#"textarea[name="keywords"]"#;
+
+    const DEFAULT_URL: &str = "https://www.furaffinity.net";
+
     impl FurAffinity {
         pub fn new(cookie_a: &str, cookie_b: &str, user: String) -> Self {
-            let mut cookies = HashMap::with_capacity(2);
-            cookies.insert("a".to_string(), cookie_a.to_string());
-            cookies.insert("b".to_string(), cookie_b.to_string());
-
             let client = reqwest::Client::default();
 
             let id_selector = scraper::Selector::parse(".submission-list u a").unwrap();
@@ -97,25 +447,56 @@ mod furaffinity {
                 scraper::Selector::parse(".submission-id-sub-container strong span.popup_date")
                     .unwrap();
             let tag_selector = scraper::Selector::parse("section.tags-row a").unwrap();
+            let download_selector = scraper::Selector::parse(".download a").unwrap();
+            let info_selector = scraper::Selector::parse("section.info.text > div").unwrap();
+            let rating_selector = scraper::Selector::parse(".rating .rating-box").unwrap();
 
             let date_cleaner = regex::Regex::new(r"(\d{1,2})(st|nd|rd|th)").unwrap();
 
             Self {
                 client,
-                cookies: Self::cookies(cookies),
+                cookies: std::sync::RwLock::new(Self::cookies(cookie_a, cookie_b)),
 
                 user,
+                base_url: DEFAULT_URL.to_string(),
 
                 id_selector,
                 title_selector,
                 posted_at_selector,
                 tag_selector,
+                download_selector,
+                info_selector,
+                rating_selector,
 
                 date_cleaner,
             }
         }
 
-        fn cookies(cookies: HashMap<String, String>) -> String {
+        /// Name of the user whose submissions are managed.
+        pub fn user(&self) -> &str {
+            &self.user
+        }
+
+        /// Send requests to another server, such as a simulated site.
+        #[cfg(all(test, feature = "site-simulation"))]
+        pub fn with_base_url(mut self, base_url: &str) -> Self {
+            self.base_url = base_url.trim_end_matches('/').to_string();
+            self
+        }
+
+        /// Use new session cookies for all following requests.
+        pub fn set_cookies(&self, cookie_a: &str, cookie_b: &str) {
+            *self.cookies.write().unwrap() = Self::cookies(cookie_a, cookie_b);
+        }
+
+        fn cookies(cookie_a: &str, cookie_b: &str) -> String {
+            let mut cookies = HashMap::with_capacity(3);
+            cookies.insert("a".to_string(), cookie_a.to_string());
+            cookies.insert("b".to_string(), cookie_b.to_string());
+            // The SFW toggle hides mature and adult submissions from the
+            // gallery without any notice, so always browse with it off.
+            cookies.insert("sfw".to_string(), "0".to_string());
+
             cookies
                 .into_iter()
                 .map(|(name, value)| Self::cookie_string(&name, &value))
@@ -127,89 +508,287 @@ mod furaffinity {
             format!("{}={}", name, value)
         }
 
-        fn join_text_nodes(elem: scraper::ElementRef) -> String {
-            elem.text().collect::<Vec<_>>().join("").trim().to_string()
+        /// FurAffinity keywords are separated by spaces, so any spaces within
+        /// a tag must be replaced.
+        fn join_tags(tags: &[String]) -> String {
+            tags.iter()
+                .map(|tag| tag.split_whitespace().collect::<Vec<_>>().join("_"))
+                .collect::<Vec<_>>()
+                .join(" ")
         }
 
-        fn parse_document(page: &str) -> anyhow::Result<EditData> {
-            use scraper::Selector;
+        /// Load a page, failing with [`Error::Unavailable`] if FurAffinity
+        /// returned a maintenance or Cloudflare challenge page instead.
+        async fn load_page(&self, req: reqwest::RequestBuilder) -> anyhow::Result<String> {
+            let cookies = self.cookies.read().unwrap().clone();
+            let resp = req.header(reqwest::header::COOKIE, cookies).send().await?;
+
+            let status = resp.status();
+            let status_error =
+                Error::from_status(SubmissionSite::FurAffinity, status, resp.headers());
+            let challenged = resp
+                .headers()
+                .get("cf-mitigated")
+                .map(|value| value == "challenge")
+                .unwrap_or(false);
+            let url = resp.url().clone();
+            let body = resp.text().await?;
+
+            if let Some(reason) = Self::unavailable_reason(challenged, &body) {
+                return Err(Error::Unavailable {
+                    site: SubmissionSite::FurAffinity,
+                    reason,
+                }
+                .into());
+            }
 
-            let html = scraper::Html::parse_document(page);
+            if let Some(err) = status_error {
+                return Err(err.into());
+            }
 
-            let form = html
-                .select(&Selector::parse(r#"form[name="MsgForm"]"#).unwrap())
-                .next()
-                .context("Page was missing form")?;
+            // Checked first, as the message also asks to log in.
+            if Self::content_filtered(&body) {
+                return Err(Error::ContentFiltered {
+                    site: SubmissionSite::FurAffinity,
+                }
+                .into());
+            }
 
-            let key = form
-                .select(&Selector::parse(r#"input[name="key"]"#).unwrap())
-                .next()
-                .context("Form was missing key element")?
-                .value()
-                .attr("value")
-                .context("Form was missing key value")?
-                .to_string();
+            if Self::logged_out(&url, &body) {
+                return Err(Error::Auth {
+                    site: SubmissionSite::FurAffinity,
+                    reason: "session expired, run login furaffinity or update the cookies"
+                        .to_string(),
+                }
+                .into());
+            }
 
-            let rating = form
-                .select(&Selector::parse(r#"input[name="rating"][checked]"#).unwrap())
-                .next()
-                .context("Form was missing selected rating")?
-                .value()
-                .attr("value")
-                .context("Form was missing selected rating value")?
-                .to_string();
+            if !status.is_success() {
+                anyhow::bail!("FurAffinity returned unexpected status {}", status);
+            }
 
-            let title = form
-                .select(&Selector::parse("#title").unwrap())
-                .next()
-                .context("Form was missing title")?
-                .value()
-                .attr("value")
-                .context("Form was missing title value")?
-                .to_string();
+            Ok(body)
+        }
 
-            let message: String = form
-                .select(&Selector::parse("#JSMessage").unwrap())
-                .next()
-                .context("Form was missing description")?
-                .text()
-                .collect();
+        fn unavailable_reason(challenged: bool, body: &str) -> Option<&'static str> {
+            if challenged || body.contains("challenge-platform") {
+                return Some("requests are being challenged by Cloudflare");
+            }
 
-            let cat = form
-                .select(&Selector::parse(r#"select[name="cat"] option[selected]"#).unwrap())
+            let html = scraper::Html::parse_document(body);
+            let title = html
+                .select(&scraper::Selector::parse("title").unwrap())
                 .next()
-                .context("Form was missing selected category")?
-                .value()
-                .attr("value")
-                .context("Form was missing category value")?
-                .to_string();
+                .map(Self::join_text_nodes)
+                .unwrap_or_default()
+                .to_lowercase();
+
+            if title.contains("just a moment") || title.contains("attention required") {
+                Some("requests are being challenged by Cloudflare")
+            } else if title.contains("maintenance") || title.contains("offline") {
+                Some("the site is in maintenance mode")
+            } else {
+                None
+            }
+        }
 
-            let atype = form
-                .select(&Selector::parse(r#"select[name="atype"] option[selected]"#).unwrap())
-                .next()
-                .context("Form was missing selected atype")?
-                .value()
-                .attr("value")
-                .context("Form was missing selected atype value")?
-                .to_string();
+        /// If FurAffinity refused to show a submission because the account's
+        /// content maturity settings filter it.
+        fn content_filtered(body: &str) -> bool {
+            let html = scraper::Html::parse_document(body);
+            let message = html
+                .select(&scraper::Selector::parse(".notice-message, .redirect-message").unwrap())
+                .map(Self::join_text_nodes)
+                .collect::<String>()
+                .to_lowercase();
+
+            message.contains("mature or adult content")
+        }
+
+        /// If FurAffinity sent a login page or message instead of the
+        /// requested page, which happens once the session cookies expire.
+        fn logged_out(url: &reqwest::Url, body: &str) -> bool {
+            if url.path().starts_with("/login") {
+                return true;
+            }
+
+            let html = scraper::Html::parse_document(body);
+            let message = html
+                .select(&scraper::Selector::parse(".notice-message, .redirect-message").unwrap())
+                .map(Self::join_text_nodes)
+                .collect::<String>()
+                .to_lowercase();
 
-            let species = form
-                .select(&Selector::parse(r#"select[name="species"] option[selected]"#).unwrap())
+            message.contains("log in") || message.contains("logged in")
+        }
+
+        /// Find the first element matching a selector, failing with
+        /// [`Error::ParseFailure`] if there is none.
+        fn select<'a>(
+            elem: scraper::ElementRef<'a>,
+            selector: &'static str,
+        ) -> anyhow::Result<scraper::ElementRef<'a>> {
+            elem.select(&scraper::Selector::parse(selector).unwrap())
                 .next()
-                .context("Form was missing selected species")?
+                .ok_or_else(|| {
+                    Error::ParseFailure {
+                        site: SubmissionSite::FurAffinity,
+                        selector,
+                    }
+                    .into()
+                })
+        }
+
+        /// Find the value attribute of the first element matching a selector.
+        fn select_value(
+            elem: scraper::ElementRef,
+            selector: &'static str,
+        ) -> anyhow::Result<String> {
+            Self::select(elem, selector)?
                 .value()
                 .attr("value")
-                .context("Form was missing selected species value")?
-                .to_string();
+                .map(|value| value.to_string())
+                .ok_or_else(|| {
+                    Error::ParseFailure {
+                        site: SubmissionSite::FurAffinity,
+                        selector,
+                    }
+                    .into()
+                })
+        }
+
+        fn join_text_nodes(elem: scraper::ElementRef) -> String {
+            elem.text().collect::<Vec<_>>().join("").trim().to_string()
+        }
+
+        /// Submission count from the statistics on a user's profile page.
+        fn profile_submission_count(body: &str) -> Option<usize> {
+            let html = scraper::Html::parse_document(body);
+            let text = Self::join_text_nodes(html.root_element());
+            let (_, rest) = text.split_once("Submissions:")?;
+
+            rest.trim_start()
+                .chars()
+                .take_while(|c| c.is_ascii_digit() || *c == ',')
+                .filter(|c| *c != ',')
+                .collect::<String>()
+                .parse()
+                .ok()
+        }
+
+        /// Read the labelled fields next to a submission, such as its
+        /// category and species. Missing fields are left out instead of
+        /// failing, as they are not needed to manage tags.
+        fn site_data(&self, body: &scraper::Html) -> BTreeMap<String, String> {
+            let label_selector = scraper::Selector::parse("strong").unwrap();
+            let value_selector = scraper::Selector::parse("span").unwrap();
+
+            let mut data = BTreeMap::new();
+            for row in body.select(&self.info_selector) {
+                let label = match row.select(&label_selector).next() {
+                    Some(label) => crate::normalize_tag(&Self::join_text_nodes(label)),
+                    None => continue,
+                };
+
+                for value in row.select(&value_selector) {
+                    // The category row has separate category and type names.
+                    let key = match value.value().attr("class") {
+                        Some("type-name") => "type".to_string(),
+                        _ => label.clone(),
+                    };
+                    let value = Self::join_text_nodes(value);
+                    if !value.is_empty() {
+                        data.entry(key).or_insert(value);
+                    }
+                }
+            }
+
+            if let Some(rating) = body.select(&self.rating_selector).next() {
+                let rating = Self::join_text_nodes(rating).trim().to_lowercase();
+                if !rating.is_empty() {
+                    data.insert("rating".to_string(), rating);
+                }
+            }
+
+            for key in POPULARITY_KEYS {
+                let selector =
+                    scraper::Selector::parse(&format!(".stats-container .{} .font-large", key))
+                        .unwrap();
+                let count = body
+                    .select(&selector)
+                    .next()
+                    .map(Self::join_text_nodes)
+                    .and_then(|count| count.trim().replace(',', "").parse::<u64>().ok());
+                if let Some(count) = count {
+                    data.insert(key.to_string(), count.to_string());
+                }
+            }
+
+            data
+        }
+
+        fn parse_document(page: &str) -> anyhow::Result<EditData> {
+            let html = scraper::Html::parse_document(page);
+
+            let form = Self::select(html.root_element(), r#"form[name="MsgForm"]"#)?;
+
+            let key = Self::select_value(form, r#"input[name="key"]"#)?;
+            let rating = Self::select_value(form, r#"input[name="rating"][checked]"#)?;
+            let title = Self::select_value(form, "#title")?;
+            let message: String = Self::select(form, "#JSMessage")?.text().collect();
+            let cat = Self::select_value(form, r#"select[name="cat"] option[selected]"#)?;
+            let atype = Self::select_value(form, r#"select[name="atype"] option[selected]"#)?;
+            let species = Self::select_value(form, r#"select[name="species"] option[selected]"#)?;
+            let gender = Self::select_value(form, r#"select[name="gender"] option[selected]"#)?;
+
+            // Folder checkboxes are labelled either by wrapping them or with a
+            // separate label pointing at their ID.
+            let mut folders = Vec::new();
+            let mut folder_ids = Vec::new();
+            for input in
+                form.select(&scraper::Selector::parse(r#"input[name="folder_ids[]"]"#).unwrap())
+            {
+                let value = match input.value().attr("value") {
+                    Some(value) => value.to_string(),
+                    None => continue,
+                };
+
+                let label = input
+                    .value()
+                    .id()
+                    .and_then(|id| {
+                        scraper::Selector::parse(&format!(r#"label[for="{}"]"#, id)).ok()
+                    })
+                    .and_then(|selector| form.select(&selector).next())
+                    .or_else(|| input.parent().and_then(scraper::ElementRef::wrap))
+                    .map(Self::join_text_nodes)
+                    .unwrap_or_default();
+
+                if input.value().attr("checked").is_some() {
+                    folder_ids.push(value.clone());
+                }
+                folders.push((value, label));
+            }
 
-            let gender = form
-                .select(&Selector::parse(r#"select[name="gender"] option[selected]"#).unwrap())
+            let keywords = form
+                .select(&scraper::Selector::parse(KEYWORDS_SELECTOR).unwrap())
                 .next()
-                .context("Form was missing selected gender")?
-                .value()
-                .attr("value")
-                .context("Form was missing selected gender value")?
-                .to_string();
+                .map(|keywords| keywords.text().collect());
+
+            let mut options = HashMap::new();
+            for name in ["cat", "atype", "species", "gender"] {
+                let selector =
+                    scraper::Selector::parse(&format!(r#"select[name="{}"] option"#, name))
+                        .unwrap();
+                let values = form
+                    .select(&selector)
+                    .filter_map(|option| {
+                        let value = option.value().attr("value")?.to_string();
+                        Some((value, Self::join_text_nodes(option)))
+                    })
+                    .collect();
+                options.insert(name, values);
+            }
 
             Ok(EditData {
                 key,
@@ -220,13 +799,165 @@ mod furaffinity {
                 atype,
                 species,
                 gender,
+                folders,
+                folder_ids,
+                keywords,
+                options,
             })
         }
+
+        fn changeinfo_url(&self, id: i64) -> String {
+            format!("{}/controls/submissions/changeinfo/{}/", self.base_url, id)
+        }
+
+        /// Submit the changeinfo form, sending every field as it is in the
+        /// edit data.
+        async fn submit(&self, id: i64, data: EditData) -> anyhow::Result<()> {
+            let keywords = data.keywords.ok_or(Error::ParseFailure {
+                site: SubmissionSite::FurAffinity,
+                selector: KEYWORDS_SELECTOR,
+            })?;
+
+            let mut body = vec![
+                ("update", "yes".to_string()),
+                ("submit", "+Finalize".to_string()),
+                ("keywords", keywords),
+                ("key", data.key),
+                ("cat", data.cat),
+                ("atype", data.atype),
+                ("species", data.species),
+                ("gender", data.gender),
+                ("rating", data.rating),
+                ("title", data.title),
+                ("message", data.message),
+            ];
+            // Folders left out of the form are removed, so existing ones must
+            // be sent again.
+            body.extend(
+                data.folder_ids
+                    .into_iter()
+                    .map(|value| ("folder_ids[]", value)),
+            );
+
+            self.load_page(self.client.post(self.changeinfo_url(id)).form(&body))
+                .await?;
+
+            Ok(())
+        }
+
+        /// Submit the changeinfo form with new tags, keeping every other field
+        /// unchanged, and check which tags were saved.
+        async fn update(
+            &self,
+            id: i64,
+            tags: &[String],
+            folder: Option<&str>,
+        ) -> anyhow::Result<()> {
+            let page = self
+                .load_page(self.client.get(self.changeinfo_url(id)))
+                .await?;
+
+            let mut data = Self::parse_document(&page)?;
+            data.keywords = Some(Self::join_tags(tags));
+
+            if let Some(folder) = folder {
+                let normalized = folder.to_lowercase();
+                let (value, _name) = data
+                    .folders
+                    .iter()
+                    .find(|(value, name)| value == folder || name.to_lowercase() == normalized)
+                    .with_context(|| format!("Unknown FurAffinity folder: {}", folder))?;
+
+                if !data.folder_ids.contains(value) {
+                    data.folder_ids.push(value.clone());
+                }
+            }
+
+            self.submit(id, data).await?;
+
+            let updated = self
+                .get_submission(id)
+                .await
+                .context("Could not verify updated tags")?;
+            let rejected = rejected_tags(tags, &updated.tags);
+            if rejected.len() == tags.len() && !tags.is_empty() {
+                anyhow::bail!("FurAffinity did not save any of the new tags");
+            } else if !rejected.is_empty() {
+                tracing::warn!("FurAffinity rejected tags: {}", rejected.join(", "));
+            }
+
+            Ok(())
+        }
+
+        /// Code FurAffinity uses for a rating, given by name or code.
+        fn rating_code(rating: &str) -> Option<&'static str> {
+            match rating.to_lowercase().as_str() {
+                "general" | "0" => Some("0"),
+                "adult" | "1" => Some("1"),
+                "mature" | "2" => Some("2"),
+                _ => None,
+            }
+        }
     }
 
     #[async_trait]
     impl Site for FurAffinity {
-        async fn get_all_submissions(&self) -> anyhow::Result<Vec<Submission>> {
+        fn capabilities(&self) -> SiteCapabilities {
+            SiteCapabilities {
+                tags: true,
+                descriptions: true,
+                ratings: true,
+                ..Default::default()
+            }
+        }
+
+        fn update_delay(&self) -> std::time::Duration {
+            std::time::Duration::from_secs(1)
+        }
+
+        async fn verify_ownership(&self) -> Result<()> {
+            let page = self
+                .load_page(
+                    self.client
+                        .get(format!("{}/controls/settings/", self.base_url)),
+                )
+                .await?;
+
+            let html = scraper::Html::parse_document(&page);
+            let logged_in = html
+                .select(&scraper::Selector::parse("#my-username").unwrap())
+                .next()
+                .map(Self::join_text_nodes)
+                .ok_or_else(|| Error::Auth {
+                    site: SubmissionSite::FurAffinity,
+                    reason: "not logged in, cookies may have expired".to_string(),
+                })?;
+            let logged_in = logged_in.trim_start_matches('~');
+
+            if !logged_in.eq_ignore_ascii_case(&self.user) {
+                return Err(Error::Auth {
+                    site: SubmissionSite::FurAffinity,
+                    reason: format!("cookies belong to {}, not {}", logged_in, self.user),
+                });
+            }
+
+            Ok(())
+        }
+
+        /// The profile's count includes scraps, which are not loaded, so it
+        /// may be higher than the number of gallery submissions.
+        async fn reported_count(&self) -> Result<Option<usize>> {
+            let page = self
+                .load_page(
+                    self.client
+                        .get(format!("{}/user/{}/", self.base_url, self.user)),
+                )
+                .await?;
+
+            Ok(Self::profile_submission_count(&page))
+        }
+
+        async fn get_all_submissions(&self) -> Result<Vec<Submission>> {
             let mut ids = Vec::new();
 
             let mut page = 1;
@@ -234,25 +965,19 @@ mod furaffinity {
                 tracing::info!(page, "Loading gallery page");
 
                 let body = self
-                    .client
-                    .get(format!(
-                        "https://www.furaffinity.net/gallery/{}/{}/",
-                        self.user, page
-                    ))
-                    .header(reqwest::header::COOKIE, &self.cookies)
-                    .send()
-                    .await?
-                    .text()
+                    .load_page(
+                        self.client
+                            .get(format!("{}/gallery/{}/{}/", self.base_url, self.user, page)),
+                    )
                     .await?;
 
                 let body = scraper::Html::parse_document(&body);
 
                 let mut new_ids = body
                     .select(&self.id_selector)
-                    .into_iter()
                     .filter_map(|element| element.value().attr("href"))
                     .filter_map(|href| href.split('/').nth(2))
-                    .filter_map(|id| id.parse::<i32>().ok())
+                    .filter_map(|id| id.parse::<i64>().ok())
                     .peekable();
 
                 if new_ids.peek().is_none() {
@@ -272,94 +997,125 @@ mod furaffinity {
             for id in ids {
                 tracing::info!(id, "Loading complete information for submission");
 
-                let submission = self
-                    .client
-                    .get(format!("https://www.furaffinity.net/view/{}/", id))
-                    .header(reqwest::header::COOKIE, &self.cookies)
-                    .send()
-                    .await?
-                    .text()
-                    .await?;
+                submissions.push(self.get_submission(id).await?);
+            }
 
-                let body = scraper::Html::parse_document(&submission);
+            Ok(submissions)
+        }
 
-                let title = Self::join_text_nodes(
-                    body.select(&self.title_selector)
-                        .next()
-                        .context("Submission must have title")?,
-                );
+        async fn get_submission(&self, id: i64) -> Result<Submission> {
+            let submission = self
+                .load_page(self.client.get(format!("{}/view/{}/", self.base_url, id)))
+                .await?;
+
+            let body = scraper::Html::parse_document(&submission);
 
-                let posted_at = body
-                    .select(&self.posted_at_selector)
+            let title = Self::join_text_nodes(
+                body.select(&self.title_selector)
                     .next()
-                    .context("Missing posted at date")?
-                    .value()
-                    .attr("title")
-                    .context("Missing posted at value")?;
-                let posted_at = self.date_cleaner.replace(posted_at, "$1");
-                let posted_at = chrono::Local
-                    .datetime_from_str(&posted_at, "%b %e, %Y %l:%M %p")
-                    .context("Unknown date format")?;
-
-                let tags: Vec<String> = body
-                    .select(&self.tag_selector)
-                    .into_iter()
-                    .map(Self::join_text_nodes)
-                    .collect();
+                    .context("Submission must have title")?,
+            );
 
-                submissions.push(Submission {
-                    site: SubmissionSite::FurAffinity,
-                    id,
-                    title,
-                    posted_at,
-                    tags,
-                });
-            }
+            let posted_at = body
+                .select(&self.posted_at_selector)
+                .next()
+                .context("Missing posted at date")?
+                .value()
+                .attr("title")
+                .context("Missing posted at value")?;
+            let posted_at = self.date_cleaner.replace(posted_at, "$1");
+            let posted_at = chrono::Local
+                .datetime_from_str(&posted_at, "%b %e, %Y %l:%M %p")
+                .context("Unknown date format")?;
+
+            let tags: Vec<String> = body
+                .select(&self.tag_selector)
+                .map(Self::join_text_nodes)
+                .collect();
 
-            Ok(submissions)
+            let file_url = body
+                .select(&self.download_selector)
+                .next()
+                .and_then(|elem| elem.value().attr("href"))
+                .map(|href| format!("https:{}", href));
+
+            Ok(Submission {
+                site: SubmissionSite::FurAffinity,
+                id,
+                title,
+                posted_at,
+                tags,
+                file_url,
+                folder_id: None,
+                folder_name: None,
+                local_tags: Vec::new(),
+                notes: None,
+                deleted_at: None,
+                site_data: self.site_data(&body),
+                visibility: Visibility::Public,
+            })
         }
 
-        async fn set_tags(&self, id: i32, tags: &[String]) -> anyhow::Result<()> {
-            let url = format!(
-                "https://www.furaffinity.net/controls/submissions/changeinfo/{}/",
-                id
-            );
+        async fn set_tags(&self, id: i64, tags: &[String]) -> Result<()> {
+            Ok(self.update(id, tags, None).await?)
+        }
+
+        async fn set_tags_in_folder(&self, id: i64, tags: &[String], folder: &str) -> Result<()> {
+            Ok(self.update(id, tags, Some(folder)).await?)
+        }
+
+        async fn update_metadata(&self, id: i64, changes: MetadataChanges) -> Result<Vec<String>> {
+            let fields = changes.fields().len();
 
             let page = self
-                .client
-                .get(&url)
-                .header(reqwest::header::COOKIE, &self.cookies)
-                .send()
-                .await?
-                .error_for_status()?
-                .text()
+                .load_page(self.client.get(self.changeinfo_url(id)))
                 .await?;
+            let mut data = Self::parse_document(&page)?;
 
-            let data = Self::parse_document(&page)?;
+            if let Some(title) = changes.title {
+                data.title = title;
+            }
+            if let Some(description) = changes.description {
+                data.message = description;
+            }
+            if let Some(rating) = changes.rating {
+                data.rating = Self::rating_code(&rating)
+                    .with_context(|| format!("Unknown FurAffinity rating: {}", rating))?
+                    .to_string();
+            }
 
-            let body = [
-                ("update", "yes".to_string()),
-                ("submit", "+Finalize".to_string()),
-                ("keywords", tags.join(" ")),
-                ("key", data.key),
-                ("cat", data.cat),
-                ("atype", data.atype),
-                ("species", data.species),
-                ("gender", data.gender),
-                ("rating", data.rating),
-                ("title", data.title),
-                ("message", data.message),
-            ];
+            let mut unsupported = Vec::new();
+            for (field, value) in changes.extras {
+                let (name, target) = match field.as_str() {
+                    "category" => ("cat", &mut data.cat),
+                    "type" => ("atype", &mut data.atype),
+                    "species" => ("species", &mut data.species),
+                    "gender" => ("gender", &mut data.gender),
+                    _ => {
+                        unsupported.push(field);
+                        continue;
+                    }
+                };
+
+                // Options may be given by their code or their name.
+                let normalized = crate::normalize_tag(&value);
+                let (code, _name) = data
+                    .options
+                    .get(name)
+                    .and_then(|options| {
+                        options.iter().find(|(code, name)| {
+                            code == &value || crate::normalize_tag(name) == normalized
+                        })
+                    })
+                    .with_context(|| format!("Unknown FurAffinity {}: {}", field, value))?;
+                *target = code.clone();
+            }
 
-            self.client
-                .post(url)
-                .header(reqwest::header::COOKIE, &self.cookies)
-                .form(&body)
-                .send()
-                .await?
-                .error_for_status()?;
+            if unsupported.len() < fields {
+                self.submit(id, data).await?;
+            }
 
-            Ok(())
+            Ok(unsupported)
         }
     }
 }
@@ -374,9 +1130,12 @@ mod weasyl {
 
     use super::*;
 
+    const DEFAULT_URL: &str = "https://www.weasyl.com";
+
     pub struct Weasyl {
         client: reqwest::Client,
         user: String,
+        base_url: String,
     }
 
     impl Weasyl {
@@ -389,23 +1148,203 @@ mod weasyl {
                 .build()
                 .unwrap();
 
-            Self { client, user }
+            Self {
+                client,
+                user,
+                base_url: DEFAULT_URL.to_string(),
+            }
+        }
+
+        /// Create a client authorized with an OAuth2 access token instead of
+        /// an API key.
+        pub fn with_token(token: &str, user: String) -> Self {
+            let mut headers: HeaderMap<HeaderValue> = reqwest::header::HeaderMap::with_capacity(1);
+            headers.insert(
+                reqwest::header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+            );
+
+            let client = reqwest::Client::builder()
+                .default_headers(headers)
+                .build()
+                .unwrap();
+
+            Self {
+                client,
+                user,
+                base_url: DEFAULT_URL.to_string(),
+            }
+        }
+
+        /// Send requests to another server, such as a simulated site.
+        #[cfg(all(test, feature = "site-simulation"))]
+        pub fn with_base_url(mut self, base_url: &str) -> Self {
+            self.base_url = base_url.trim_end_matches('/').to_string();
+            self
+        }
+
+        /// Collect the current values of every field in the submission edit
+        /// form.
+        fn parse_edit_form(page: &str) -> anyhow::Result<Vec<(String, String)>> {
+            use scraper::Selector;
+
+            let html = scraper::Html::parse_document(page);
+
+            let form = html
+                .select(&Selector::parse(r#"form[action="/edit/submission"]"#).unwrap())
+                .next()
+                .context("Page was missing edit form")?;
+
+            let mut fields = Vec::new();
+
+            for input in form.select(&Selector::parse("input[name]").unwrap()) {
+                let input = input.value();
+                let checkable = matches!(input.attr("type"), Some("checkbox") | Some("radio"));
+
+                if checkable && input.attr("checked").is_none() {
+                    continue;
+                }
+
+                let default = if checkable { "on" } else { "" };
+                fields.push((
+                    input.attr("name").unwrap_or_default().to_string(),
+                    input.attr("value").unwrap_or(default).to_string(),
+                ));
+            }
+
+            for select in form.select(&Selector::parse("select[name]").unwrap()) {
+                let selected = select
+                    .select(&Selector::parse("option[selected]").unwrap())
+                    .next()
+                    .and_then(|option| option.value().attr("value"));
+
+                if let Some(value) = selected {
+                    fields.push((
+                        select.value().attr("name").unwrap_or_default().to_string(),
+                        value.to_string(),
+                    ));
+                }
+            }
+
+            for textarea in form.select(&Selector::parse("textarea[name]").unwrap()) {
+                fields.push((
+                    textarea
+                        .value()
+                        .attr("name")
+                        .unwrap_or_default()
+                        .to_string(),
+                    textarea.text().collect(),
+                ));
+            }
+
+            Ok(fields)
+        }
+
+        /// Submit a submission's edit form with some fields replaced and all
+        /// others unchanged.
+        async fn edit(&self, id: i64, fields: Vec<(&str, String)>) -> Result<()> {
+            let page = self
+                .client
+                .get(format!("{}/edit/submission", self.base_url))
+                .query(&[("submitid", id)])
+                .send()
+                .await?
+                .check_status(SubmissionSite::Weasyl)?
+                .text()
+                .await?;
+
+            let mut form = Self::parse_edit_form(&page)?;
+            form.retain(|(name, _value)| !fields.iter().any(|(field, _value)| *field == *name));
+            form.extend(
+                fields
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value)),
+            );
+
+            self.client
+                .post(format!("{}/edit/submission", self.base_url))
+                .form(&form)
+                .send()
+                .await?
+                .check_status(SubmissionSite::Weasyl)?;
+
+            Ok(())
+        }
+
+        async fn view_user(&self) -> Result<WeasylUser> {
+            let user = self
+                .client
+                .get(format!("{}/api/users/{}/view", self.base_url, self.user))
+                .send()
+                .await?
+                .check_status(SubmissionSite::Weasyl)?
+                .json()
+                .await
+                .context("Could not decode user")?;
+
+            Ok(user)
+        }
+
+        /// Code Weasyl uses for a rating, given by name or code.
+        fn rating_code(rating: &str) -> Option<&'static str> {
+            match rating.to_lowercase().as_str() {
+                "general" | "10" => Some("10"),
+                "mature" | "30" => Some("30"),
+                "explicit" | "40" => Some("40"),
+                _ => None,
+            }
+        }
+
+        /// Weasyl splits tags on commas and whitespace, storing multi-word
+        /// tags with underscores.
+        fn join_tags(tags: &[String]) -> String {
+            tags.iter()
+                .map(|tag| tag.split_whitespace().collect::<Vec<_>>().join("_"))
+                .collect::<Vec<_>>()
+                .join(", ")
         }
     }
 
     #[derive(Debug, serde::Deserialize)]
     struct WeasylSubmission {
         submitid: i32,
-        title: String,
-        #[serde(deserialize_with = "datetime_from_weasyl_str")]
-        posted_at: chrono::DateTime<chrono::Utc>,
     }
 
     #[derive(Debug, serde::Deserialize)]
     struct WeasylSubmissionFull {
         submitid: i32,
+        #[serde(deserialize_with = "datetime_from_weasyl_str")]
+        posted_at: chrono::DateTime<chrono::Utc>,
         title: String,
         tags: Vec<String>,
+        media: WeasylSubmissionMedia,
+        #[serde(default)]
+        folderid: Option<i32>,
+        #[serde(default)]
+        folder_name: Option<String>,
+        #[serde(default)]
+        subtype: Option<String>,
+        #[serde(default)]
+        friends_only: bool,
+        #[serde(default)]
+        hidden: bool,
+        #[serde(default)]
+        views: Option<u64>,
+        #[serde(default)]
+        favorites: Option<u64>,
+        #[serde(default)]
+        comments: Option<u64>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct WeasylSubmissionMedia {
+        #[serde(default)]
+        submission: Vec<WeasylMediaFile>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct WeasylMediaFile {
+        url: String,
     }
 
     fn datetime_from_weasyl_str<'de, D>(
@@ -420,9 +1359,34 @@ mod weasyl {
             .map_err(serde::de::Error::custom)
     }
 
+    #[derive(Debug, serde::Deserialize)]
+    struct WeasylWhoami {
+        login: String,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct WeasylUser {
+        #[serde(default)]
+        folders: Vec<WeasylFolder>,
+        #[serde(default)]
+        statistics: Option<WeasylStatistics>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct WeasylStatistics {
+        submissions: usize,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct WeasylFolder {
+        folder_id: i32,
+        title: String,
+        #[serde(default)]
+        subfolders: Vec<WeasylFolder>,
+    }
+
     #[derive(Debug, serde::Deserialize)]
     struct WeasylSubmissionResponse {
-        backid: Option<i32>,
         nextid: Option<i32>,
 
         submissions: Vec<WeasylSubmission>,
@@ -430,7 +1394,105 @@ mod weasyl {
 
     #[async_trait]
     impl Site for Weasyl {
-        async fn get_all_submissions(&self) -> anyhow::Result<Vec<Submission>> {
+        fn capabilities(&self) -> SiteCapabilities {
+            SiteCapabilities {
+                tags: true,
+                descriptions: true,
+                ratings: true,
+                folders: true,
+            }
+        }
+
+        fn update_delay(&self) -> std::time::Duration {
+            std::time::Duration::from_millis(250)
+        }
+
+        async fn verify_ownership(&self) -> Result<()> {
+            let whoami: WeasylWhoami = self
+                .client
+                .get(format!("{}/api/whoami", self.base_url))
+                .send()
+                .await?
+                .check_status(SubmissionSite::Weasyl)?
+                .json()
+                .await?;
+
+            // Login names are usernames with only lowercase letters and numbers.
+            let login: String = self
+                .user
+                .chars()
+                .filter(char::is_ascii_alphanumeric)
+                .map(|c| c.to_ascii_lowercase())
+                .collect();
+
+            if whoami.login != login {
+                return Err(Error::Auth {
+                    site: SubmissionSite::Weasyl,
+                    reason: format!("API key belongs to {}, not {}", whoami.login, self.user),
+                });
+            }
+
+            Ok(())
+        }
+
+        async fn reported_count(&self) -> Result<Option<usize>> {
+            let user = self.view_user().await?;
+
+            Ok(user.statistics.map(|statistics| statistics.submissions))
+        }
+
+        async fn list_folders(&self) -> Result<Vec<(i32, String)>> {
+            let user = self.view_user().await?;
+
+            let mut folders = Vec::new();
+            let mut pending = user.folders;
+            while let Some(folder) = pending.pop() {
+                folders.push((folder.folder_id, folder.title));
+                pending.extend(folder.subfolders);
+            }
+            folders.sort();
+
+            Ok(folders)
+        }
+
+        /// Move a submission into a folder by submitting the edit form with
+        /// all other fields unchanged.
+        async fn move_folder(&self, id: i64, folder_id: i32) -> Result<()> {
+            self.edit(id, vec![("folderid", folder_id.to_string())])
+                .await
+        }
+
+        async fn update_metadata(&self, id: i64, changes: MetadataChanges) -> Result<Vec<String>> {
+            let mut fields = Vec::new();
+
+            if let Some(title) = changes.title {
+                fields.push(("title", title));
+            }
+            if let Some(description) = changes.description {
+                fields.push(("content", description));
+            }
+            if let Some(rating) = changes.rating {
+                let code = Self::rating_code(&rating)
+                    .with_context(|| format!("Unknown Weasyl rating: {}", rating))?;
+                fields.push(("rating", code.to_string()));
+            }
+
+            let mut unsupported = Vec::new();
+            for (field, value) in changes.extras {
+                match field.as_str() {
+                    "subtype" => fields.push(("subtype", value)),
+                    _ => unsupported.push(field),
+                }
+            }
+
+            if !fields.is_empty() {
+                self.edit(id, fields).await?;
+            }
+
+            Ok(unsupported)
+        }
+
+        async fn get_all_submissions(&self) -> Result<Vec<Submission>> {
             let mut submissions = Vec::new();
 
             let mut nextid: Option<i32> = None;
@@ -446,16 +1508,11 @@ mod weasyl {
 
                 let page: WeasylSubmissionResponse = self
                     .client
-                    .get(format!(
-                        "https://www.weasyl.com/api/users/{}/gallery",
-                        self.user
-                    ))
+                    .get(format!("{}/api/users/{}/gallery", self.base_url, self.user))
                     .query(&params)
                     .send()
-                    .await
-                    .context("Could not make request for gallery")?
-                    .error_for_status()
-                    .context("Got bad gallery status code")?
+                    .await?
+                    .check_status(SubmissionSite::Weasyl)?
                     .json()
                     .await
                     .context("Could not decode gallery")?;
@@ -479,42 +1536,1051 @@ mod weasyl {
                     "Loading complete information for submission"
                 );
 
-                let submission: WeasylSubmissionFull = self
-                    .client
-                    .get(format!(
-                        "https://www.weasyl.com/api/submissions/{}/view",
-                        sub.submitid
-                    ))
-                    .send()
-                    .await
-                    .context("Could not make request for submission")?
-                    .error_for_status()
-                    .context("Got bad submission status code")?
-                    .json()
-                    .await
-                    .context("Could not decode submission")?;
-
-                completed_submissions.push(Submission {
-                    site: SubmissionSite::Weasyl,
-                    id: submission.submitid,
-                    title: submission.title,
-                    posted_at: sub.posted_at.into(),
-                    tags: submission.tags,
-                });
+                completed_submissions.push(self.get_submission(sub.submitid.into()).await?);
             }
 
             Ok(completed_submissions)
         }
 
-        async fn set_tags(&self, id: i32, tags: &[String]) -> anyhow::Result<()> {
-            let tags = tags.join(" ");
+        async fn get_submission(&self, id: i64) -> Result<Submission> {
+            let submission: WeasylSubmissionFull = self
+                .client
+                .get(format!("{}/api/submissions/{}/view", self.base_url, id))
+                .send()
+                .await?
+                .check_status(SubmissionSite::Weasyl)?
+                .json()
+                .await
+                .context("Could not decode submission")?;
+
+            let visibility = if submission.hidden {
+                Visibility::Hidden
+            } else if submission.friends_only {
+                Visibility::Friends
+            } else {
+                Visibility::Public
+            };
+
+            Ok(Submission {
+                site: SubmissionSite::Weasyl,
+                id: submission.submitid.into(),
+                title: submission.title,
+                posted_at: submission.posted_at.into(),
+                tags: submission.tags,
+                file_url: submission
+                    .media
+                    .submission
+                    .into_iter()
+                    .next()
+                    .map(|file| file.url),
+                folder_id: submission.folderid,
+                folder_name: submission.folder_name.clone(),
+                local_tags: Vec::new(),
+                notes: None,
+                deleted_at: None,
+                site_data: [
+                    ("subtype", submission.subtype),
+                    ("folder", submission.folder_name),
+                    ("views", submission.views.map(|count| count.to_string())),
+                    (
+                        "favorites",
+                        submission.favorites.map(|count| count.to_string()),
+                    ),
+                    (
+                        "comments",
+                        submission.comments.map(|count| count.to_string()),
+                    ),
+                ]
+                .into_iter()
+                .filter_map(|(key, value)| Some((key.to_string(), value?)))
+                .collect(),
+                visibility,
+            })
+        }
 
+        async fn set_tags(&self, id: i64, tags: &[String]) -> Result<()> {
             self.client
-                .post("https://www.weasyl.com/submit/tags")
-                .form(&[("submitid", id.to_string()), ("tags", tags)])
+                .post(format!("{}/submit/tags", self.base_url))
+                .form(&[
+                    ("submitid", id.to_string()),
+                    ("tags", Self::join_tags(tags)),
+                ])
                 .send()
                 .await?
-                .error_for_status()?;
+                .check_status(SubmissionSite::Weasyl)?;
+
+            // Weasyl silently drops tags it considers invalid.
+            let updated = self
+                .get_submission(id)
+                .await
+                .context("Could not verify updated tags")?;
+            let rejected = rejected_tags(tags, &updated.tags);
+            if !rejected.is_empty() {
+                tracing::warn!("Weasyl rejected tags: {}", rejected.join(", "));
+            }
+
+            Ok(())
+        }
+    }
+}
+
+mod derpibooru {
+    use anyhow::Context;
+    use async_trait::async_trait;
+
+    use super::*;
+
+    const DEFAULT_URL: &str = "https://derpibooru.org";
+
+    /// A Philomena booru, such as Derpibooru.
+    pub struct Derpibooru {
+        client: reqwest::Client,
+        base_url: String,
+        api_key: String,
+        /// Session cookie, needed to edit tags as Philomena's API is read
+        /// only.
+        session: Option<String>,
+    }
+
+    impl Derpibooru {
+        pub fn new(base_url: Option<&str>, api_key: String, session: Option<String>) -> Self {
+            Self {
+                client: reqwest::Client::default(),
+                base_url: base_url
+                    .unwrap_or(DEFAULT_URL)
+                    .trim_end_matches('/')
+                    .to_string(),
+                api_key,
+                session,
+            }
+        }
+
+        /// Philomena splits tags on commas and allows spaces within tags, so
+        /// underscores from other sites are converted back into spaces.
+        fn join_tags(tags: &[String]) -> String {
+            tags.iter()
+                .map(|tag| tag.replace('_', " "))
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct SearchResponse {
+        images: Vec<Image>,
+        total: usize,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct ImageResponse {
+        image: Image,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Image {
+        id: i64,
+        #[serde(default)]
+        name: Option<String>,
+        created_at: chrono::DateTime<chrono::Utc>,
+        tags: Vec<String>,
+        view_url: Option<String>,
+    }
+
+    impl Image {
+        fn into_submission(self) -> Submission {
+            Submission {
+                site: SubmissionSite::Derpibooru,
+                id: self.id,
+                title: self.name.unwrap_or_else(|| format!("#{}", self.id)),
+                posted_at: self.created_at.into(),
+                tags: self.tags,
+                file_url: self.view_url,
+                folder_id: None,
+                folder_name: None,
+                local_tags: Vec::new(),
+                notes: None,
+                deleted_at: None,
+                site_data: BTreeMap::new(),
+                visibility: Visibility::Public,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Site for Derpibooru {
+        fn capabilities(&self) -> SiteCapabilities {
+            SiteCapabilities {
+                tags: true,
+                ..Default::default()
+            }
+        }
+
+        fn update_delay(&self) -> std::time::Duration {
+            std::time::Duration::from_secs(1)
+        }
+
+        async fn get_all_submissions(&self) -> Result<Vec<Submission>> {
+            let mut submissions = Vec::new();
+            let mut page = 1;
+
+            loop {
+                tracing::info!(page, "Loading upload page");
+
+                let resp: SearchResponse = self
+                    .client
+                    .get(format!("{}/api/v1/json/search/images", self.base_url))
+                    .query(&[
+                        ("q", "my:uploads".to_string()),
+                        ("key", self.api_key.clone()),
+                        ("page", page.to_string()),
+                        ("per_page", "50".to_string()),
+                    ])
+                    .send()
+                    .await?
+                    .check_status(SubmissionSite::Derpibooru)?
+                    .json()
+                    .await
+                    .context("Could not decode uploads")?;
+
+                if resp.images.is_empty() {
+                    break;
+                }
+
+                submissions.extend(resp.images.into_iter().map(Image::into_submission));
+
+                if submissions.len() >= resp.total {
+                    break;
+                }
+
+                page += 1;
+            }
+
+            tracing::info!("Discovered {} submissions", submissions.len());
+
+            Ok(submissions)
+        }
+
+        async fn get_submission(&self, id: i64) -> Result<Submission> {
+            let resp: ImageResponse = self
+                .client
+                .get(format!("{}/api/v1/json/images/{}", self.base_url, id))
+                .query(&[("key", &self.api_key)])
+                .send()
+                .await?
+                .check_status(SubmissionSite::Derpibooru)?
+                .json()
+                .await
+                .context("Could not decode image")?;
+
+            Ok(resp.image.into_submission())
+        }
+
+        async fn set_tags(&self, id: i64, tags: &[String]) -> Result<()> {
+            use scraper::Selector;
+
+            let session = self
+                .session
+                .as_deref()
+                .context("Derpibooru session cookie is required to edit tags")?;
+            let cookie = format!("_philomena_key={}", session);
+
+            let page = self
+                .client
+                .get(format!("{}/images/{}", self.base_url, id))
+                .header(reqwest::header::COOKIE, &cookie)
+                .send()
+                .await?
+                .check_status(SubmissionSite::Derpibooru)?
+                .text()
+                .await?;
+
+            let (csrf_token, old_tags) = {
+                let html = scraper::Html::parse_document(&page);
+
+                let csrf_token = html
+                    .select(&Selector::parse(r#"meta[name="csrf-token"]"#).unwrap())
+                    .next()
+                    .and_then(|meta| meta.value().attr("content"))
+                    .context("Page was missing CSRF token")?
+                    .to_string();
+                let old_tags = html
+                    .select(&Selector::parse(r#"[name="image[old_tag_input]"]"#).unwrap())
+                    .next()
+                    .and_then(|input| input.value().attr("value"))
+                    .context("Page was missing tag form")?
+                    .to_string();
+
+                (csrf_token, old_tags)
+            };
+
+            self.client
+                .post(format!("{}/images/{}/tags", self.base_url, id))
+                .header(reqwest::header::COOKIE, &cookie)
+                .form(&[
+                    ("_method", "put".to_string()),
+                    ("_csrf_token", csrf_token),
+                    ("image[old_tag_input]", old_tags),
+                    ("image[tag_input]", Self::join_tags(tags)),
+                ])
+                .send()
+                .await?
+                .check_status(SubmissionSite::Derpibooru)?;
+
+            Ok(())
+        }
+    }
+}
+
+/// Get the first line of a post as a title.
+fn title_from_text(text: &str) -> String {
+    const MAX_LENGTH: usize = 80;
+
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty());
+
+    match line {
+        Some(line) if line.chars().count() > MAX_LENGTH => {
+            format!("{}...", line.chars().take(MAX_LENGTH).collect::<String>())
+        }
+        Some(line) => line.to_string(),
+        None => String::new(),
+    }
+}
+
+mod mastodon {
+    use anyhow::Context;
+    use async_trait::async_trait;
+
+    use super::*;
+
+    /// A Mastodon account, imported read only with hashtags as tags.
+    pub struct Mastodon {
+        client: reqwest::Client,
+        instance: String,
+        token: String,
+    }
+
+    impl Mastodon {
+        pub fn new(instance: &str, token: String) -> Self {
+            let instance = instance.trim_end_matches('/');
+            let instance = if instance.starts_with("http") {
+                instance.to_string()
+            } else {
+                format!("https://{}", instance)
+            };
+
+            Self {
+                client: reqwest::Client::default(),
+                instance,
+                token,
+            }
+        }
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Account {
+        id: String,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Status {
+        id: String,
+        created_at: chrono::DateTime<chrono::Utc>,
+        content: String,
+        tags: Vec<Tag>,
+        media_attachments: Vec<Attachment>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Tag {
+        name: String,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Attachment {
+        #[serde(rename = "type")]
+        media_type: String,
+        url: Option<String>,
+    }
+
+    #[async_trait]
+    impl Site for Mastodon {
+        fn capabilities(&self) -> SiteCapabilities {
+            SiteCapabilities::default()
+        }
+
+        fn update_delay(&self) -> std::time::Duration {
+            std::time::Duration::ZERO
+        }
+
+        async fn get_all_submissions(&self) -> Result<Vec<Submission>> {
+            let account: Account = self
+                .client
+                .get(format!(
+                    "{}/api/v1/accounts/verify_credentials",
+                    self.instance
+                ))
+                .bearer_auth(&self.token)
+                .send()
+                .await?
+                .check_status(SubmissionSite::Mastodon)?
+                .json()
+                .await
+                .context("Could not decode account")?;
+
+            let mut submissions = Vec::new();
+            let mut max_id: Option<String> = None;
+
+            loop {
+                tracing::info!(?max_id, "Loading status page");
+
+                let mut query = vec![
+                    ("only_media", "true".to_string()),
+                    ("exclude_replies", "true".to_string()),
+                    ("exclude_reblogs", "true".to_string()),
+                    ("limit", "40".to_string()),
+                ];
+                if let Some(max_id) = &max_id {
+                    query.push(("max_id", max_id.clone()));
+                }
+
+                let statuses: Vec<Status> = self
+                    .client
+                    .get(format!(
+                        "{}/api/v1/accounts/{}/statuses",
+                        self.instance, account.id
+                    ))
+                    .bearer_auth(&self.token)
+                    .query(&query)
+                    .send()
+                    .await?
+                    .check_status(SubmissionSite::Mastodon)?
+                    .json()
+                    .await
+                    .context("Could not decode statuses")?;
+
+                max_id = match statuses.last() {
+                    Some(status) => Some(status.id.clone()),
+                    None => break,
+                };
+
+                for status in statuses {
+                    let file_url = status
+                        .media_attachments
+                        .into_iter()
+                        .find(|attachment| attachment.media_type == "image")
+                        .and_then(|attachment| attachment.url);
+                    if file_url.is_none() {
+                        continue;
+                    }
+
+                    let text: String =
+                        scraper::Html::parse_fragment(&status.content.replace("</p>", "</p>\n"))
+                            .root_element()
+                            .text()
+                            .collect();
+
+                    submissions.push(Submission {
+                        site: SubmissionSite::Mastodon,
+                        id: status.id.parse().context("Status had invalid ID")?,
+                        title: title_from_text(&text),
+                        posted_at: status.created_at.into(),
+                        tags: status.tags.into_iter().map(|tag| tag.name).collect(),
+                        file_url,
+                        folder_id: None,
+                        folder_name: None,
+                        local_tags: Vec::new(),
+                        notes: None,
+                        deleted_at: None,
+                        site_data: BTreeMap::new(),
+                        visibility: Visibility::Public,
+                    });
+                }
+            }
+
+            tracing::info!("Discovered {} submissions", submissions.len());
+
+            Ok(submissions)
+        }
+
+        async fn set_tags(&self, _id: i64, _tags: &[String]) -> Result<()> {
+            Err(anyhow::anyhow!("Mastodon is read only").into())
+        }
+    }
+}
+
+mod twitter {
+    use std::collections::HashMap;
+
+    use anyhow::Context;
+    use async_trait::async_trait;
+
+    use super::*;
+
+    /// A Twitter account, imported read only with hashtags as tags.
+    pub struct Twitter {
+        client: reqwest::Client,
+        bearer_token: String,
+        user: String,
+    }
+
+    impl Twitter {
+        pub fn new(bearer_token: String, user: String) -> Self {
+            Self {
+                client: reqwest::Client::default(),
+                bearer_token,
+                user,
+            }
+        }
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct UserResponse {
+        data: User,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct User {
+        id: String,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct TweetsResponse {
+        #[serde(default)]
+        data: Vec<Tweet>,
+        #[serde(default)]
+        includes: Includes,
+        meta: Meta,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Tweet {
+        id: String,
+        text: String,
+        created_at: chrono::DateTime<chrono::Utc>,
+        #[serde(default)]
+        entities: Entities,
+        #[serde(default)]
+        attachments: Attachments,
+    }
+
+    #[derive(Debug, Default, serde::Deserialize)]
+    struct Entities {
+        #[serde(default)]
+        hashtags: Vec<Hashtag>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Hashtag {
+        tag: String,
+    }
+
+    #[derive(Debug, Default, serde::Deserialize)]
+    struct Attachments {
+        #[serde(default)]
+        media_keys: Vec<String>,
+    }
+
+    #[derive(Debug, Default, serde::Deserialize)]
+    struct Includes {
+        #[serde(default)]
+        media: Vec<Media>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Media {
+        media_key: String,
+        #[serde(rename = "type")]
+        media_type: String,
+        url: Option<String>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Meta {
+        next_token: Option<String>,
+    }
+
+    #[async_trait]
+    impl Site for Twitter {
+        fn capabilities(&self) -> SiteCapabilities {
+            SiteCapabilities::default()
+        }
+
+        fn update_delay(&self) -> std::time::Duration {
+            std::time::Duration::ZERO
+        }
+
+        async fn get_all_submissions(&self) -> Result<Vec<Submission>> {
+            let user: UserResponse = self
+                .client
+                .get(format!(
+                    "https://api.twitter.com/2/users/by/username/{}",
+                    self.user
+                ))
+                .bearer_auth(&self.bearer_token)
+                .send()
+                .await?
+                .check_status(SubmissionSite::Twitter)?
+                .json()
+                .await
+                .context("Could not decode user")?;
+
+            let mut submissions = Vec::new();
+            let mut pagination_token: Option<String> = None;
+
+            loop {
+                tracing::info!(?pagination_token, "Loading tweet page");
+
+                let mut query = vec![
+                    ("max_results", "100".to_string()),
+                    ("exclude", "retweets,replies".to_string()),
+                    ("expansions", "attachments.media_keys".to_string()),
+                    ("tweet.fields", "created_at,entities".to_string()),
+                    ("media.fields", "type,url".to_string()),
+                ];
+                if let Some(pagination_token) = &pagination_token {
+                    query.push(("pagination_token", pagination_token.clone()));
+                }
+
+                let page: TweetsResponse = self
+                    .client
+                    .get(format!(
+                        "https://api.twitter.com/2/users/{}/tweets",
+                        user.data.id
+                    ))
+                    .bearer_auth(&self.bearer_token)
+                    .query(&query)
+                    .send()
+                    .await?
+                    .check_status(SubmissionSite::Twitter)?
+                    .json()
+                    .await
+                    .context("Could not decode tweets")?;
+
+                let photos: HashMap<_, _> = page
+                    .includes
+                    .media
+                    .into_iter()
+                    .filter(|media| media.media_type == "photo")
+                    .filter_map(|media| Some((media.media_key, media.url?)))
+                    .collect();
+
+                for tweet in page.data {
+                    let file_url = tweet
+                        .attachments
+                        .media_keys
+                        .iter()
+                        .find_map(|key| photos.get(key).cloned());
+                    if file_url.is_none() {
+                        continue;
+                    }
+
+                    submissions.push(Submission {
+                        site: SubmissionSite::Twitter,
+                        id: tweet.id.parse().context("Tweet had invalid ID")?,
+                        title: title_from_text(&tweet.text),
+                        posted_at: tweet.created_at.into(),
+                        tags: tweet
+                            .entities
+                            .hashtags
+                            .into_iter()
+                            .map(|hashtag| hashtag.tag)
+                            .collect(),
+                        file_url,
+                        folder_id: None,
+                        folder_name: None,
+                        local_tags: Vec::new(),
+                        notes: None,
+                        deleted_at: None,
+                        site_data: BTreeMap::new(),
+                        visibility: Visibility::Public,
+                    });
+                }
+
+                match page.meta.next_token {
+                    Some(next_token) => pagination_token = Some(next_token),
+                    None => break,
+                }
+            }
+
+            tracing::info!("Discovered {} submissions", submissions.len());
+
+            Ok(submissions)
+        }
+
+        async fn set_tags(&self, _id: i64, _tags: &[String]) -> Result<()> {
+            Err(anyhow::anyhow!("Twitter is read only").into())
+        }
+    }
+}
+
+mod local_folder {
+    use std::path::{Path, PathBuf};
+
+    use anyhow::Context;
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::xmp;
+
+    /// File extensions treated as submissions.
+    const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+
+    /// A directory of image files, such as an offline archive. Tags are read
+    /// from and written to a JSON sidecar (`image.png.json`) or an XMP
+    /// sidecar (`image.xmp`) next to each file.
+    pub struct LocalFolder {
+        dir: PathBuf,
+    }
+
+    /// Contents of a JSON sidecar. Unknown fields are kept when updating.
+    #[derive(Debug, Default, serde::Deserialize)]
+    struct Sidecar {
+        title: Option<String>,
+        posted_at: Option<chrono::DateTime<chrono::Local>>,
+        #[serde(default)]
+        tags: Vec<String>,
+    }
+
+    impl LocalFolder {
+        pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+            Self {
+                dir: dir.as_ref().to_path_buf(),
+            }
+        }
+
+        /// Every image in the directory and its subdirectories, with the ID
+        /// of each.
+        fn images(&self) -> anyhow::Result<Vec<(i64, PathBuf)>> {
+            let mut images = Vec::new();
+            let mut dirs = vec![self.dir.clone()];
+
+            while let Some(dir) = dirs.pop() {
+                let entries = std::fs::read_dir(&dir)
+                    .with_context(|| format!("Could not read directory {}", dir.display()))?;
+
+                for entry in entries {
+                    let path = entry?.path();
+                    let hidden = path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_none_or(|name| name.starts_with('.'));
+                    if hidden {
+                        continue;
+                    }
+
+                    if path.is_dir() {
+                        dirs.push(path);
+                    } else if is_image(&path) {
+                        images.push((self.id(&path), path));
+                    }
+                }
+            }
+
+            images.sort();
+            Ok(images)
+        }
+
+        /// A stable ID for a file, from a hash of its path within the
+        /// directory.
+        fn id(&self, path: &Path) -> i64 {
+            let relative = path.strip_prefix(&self.dir).unwrap_or(path);
+            let name = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            // FNV-1a, so IDs never change between builds.
+            let hash = name.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            });
+
+            (hash & i64::MAX as u64) as i64
+        }
+
+        fn find(&self, id: i64) -> anyhow::Result<PathBuf> {
+            self.images()?
+                .into_iter()
+                .find(|(image_id, _path)| *image_id == id)
+                .map(|(_id, path)| path)
+                .with_context(|| format!("No file in {} has ID {}", self.dir.display(), id))
+        }
+
+        fn load(&self, id: i64, path: &Path) -> anyhow::Result<Submission> {
+            let json_path = json_sidecar(path);
+            let xmp_path = path.with_extension("xmp");
+
+            let sidecar: Sidecar = if json_path.exists() {
+                let data = std::fs::read_to_string(&json_path)?;
+                serde_json::from_str(&data)
+                    .with_context(|| format!("Invalid sidecar {}", json_path.display()))?
+            } else if xmp_path.exists() {
+                Sidecar {
+                    tags: xmp::read_keywords(&std::fs::read_to_string(&xmp_path)?),
+                    ..Default::default()
+                }
+            } else {
+                Sidecar::default()
+            };
+
+            let posted_at = match sidecar.posted_at {
+                Some(posted_at) => posted_at,
+                None => std::fs::metadata(path)?.modified()?.into(),
+            };
+
+            Ok(Submission {
+                id,
+                site: SubmissionSite::LocalFolder,
+                title: sidecar.title.unwrap_or_else(|| {
+                    path.file_stem()
+                        .map(|stem| stem.to_string_lossy().into_owned())
+                        .unwrap_or_default()
+                }),
+                posted_at,
+                tags: sidecar.tags,
+                file_url: None,
+                folder_id: None,
+                folder_name: None,
+                local_tags: Vec::new(),
+                notes: None,
+                deleted_at: None,
+                site_data: BTreeMap::new(),
+                visibility: Visibility::Public,
+            })
+        }
+    }
+
+    fn is_image(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+            .unwrap_or(false)
+    }
+
+    fn json_sidecar(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".json");
+        PathBuf::from(name)
+    }
+
+    #[async_trait]
+    impl Site for LocalFolder {
+        fn capabilities(&self) -> SiteCapabilities {
+            SiteCapabilities {
+                tags: true,
+                ..Default::default()
+            }
+        }
+
+        fn update_delay(&self) -> std::time::Duration {
+            std::time::Duration::ZERO
+        }
+
+        async fn get_all_submissions(&self) -> Result<Vec<Submission>> {
+            let submissions = self
+                .images()?
+                .into_iter()
+                .map(|(id, path)| self.load(id, &path))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            tracing::info!("Discovered {} submissions", submissions.len());
+
+            Ok(submissions)
+        }
+
+        async fn get_submission(&self, id: i64) -> Result<Submission> {
+            let path = self.find(id)?;
+            Ok(self.load(id, &path)?)
+        }
+
+        /// Write tags to the file's XMP sidecar if it has one, otherwise to
+        /// its JSON sidecar.
+        async fn set_tags(&self, id: i64, tags: &[String]) -> Result<()> {
+            let path = self.find(id)?;
+            let json_path = json_sidecar(&path);
+            let xmp_path = path.with_extension("xmp");
+
+            if xmp_path.exists() && !json_path.exists() {
+                let existing = std::fs::read_to_string(&xmp_path)?;
+                std::fs::write(&xmp_path, xmp::set_keywords(Some(&existing), tags))
+                    .context("Could not write XMP sidecar")?;
+                return Ok(());
+            }
+
+            let mut sidecar: serde_json::Map<String, serde_json::Value> = if json_path.exists() {
+                serde_json::from_str(&std::fs::read_to_string(&json_path)?)
+                    .with_context(|| format!("Invalid sidecar {}", json_path.display()))?
+            } else {
+                Default::default()
+            };
+            sidecar.insert("tags".to_string(), serde_json::json!(tags));
+
+            std::fs::write(&json_path, serde_json::to_string_pretty(&sidecar)?)
+                .context("Could not write JSON sidecar")?;
+
+            Ok(())
+        }
+    }
+}
+
+mod external {
+    use anyhow::Context;
+    use async_trait::async_trait;
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::config;
+
+    /// A site handled by an external program. Each request is written to the
+    /// program's stdin as JSON, and it responds with JSON on stdout.
+    pub struct External {
+        site: SubmissionSite,
+        command: String,
+        args: Vec<String>,
+        read_only: bool,
+    }
+
+    impl External {
+        pub fn new(config: &config::ExternalSite) -> anyhow::Result<Self> {
+            Ok(Self {
+                site: SubmissionSite::register_external(&config.name)?,
+                command: config.command.clone(),
+                args: config.args.clone(),
+                read_only: config.read_only,
+            })
+        }
+
+        pub fn site(&self) -> SubmissionSite {
+            self.site
+        }
+
+        pub fn name(&self) -> &'static str {
+            self.site.as_str()
+        }
+
+        /// Run the program with a single request and decode its response.
+        async fn call<T: serde::de::DeserializeOwned>(
+            &self,
+            request: serde_json::Value,
+        ) -> anyhow::Result<T> {
+            let mut child = tokio::process::Command::new(&self.command)
+                .args(&self.args)
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::piped())
+                .spawn()
+                .with_context(|| format!("Could not start {}", self.command))?;
+
+            let mut stdin = child.stdin.take().context("Program had no stdin")?;
+            stdin
+                .write_all(format!("{}\n", request).as_bytes())
+                .await
+                .context("Could not write request")?;
+            drop(stdin);
+
+            let output = child
+                .wait_with_output()
+                .await
+                .context("Could not read response")?;
+            if !output.status.success() {
+                anyhow::bail!("{} exited with {}", self.command, output.status);
+            }
+
+            match serde_json::from_slice(&output.stdout).context("Could not decode response")? {
+                Response::Error { error } => anyhow::bail!("{} failed: {}", self.site, error),
+                Response::Ok(response) => Ok(response),
+            }
+        }
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    #[serde(untagged)]
+    enum Response<T> {
+        Error { error: String },
+        Ok(T),
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct SubmissionsResponse {
+        submissions: Vec<ExternalSubmission>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct SubmissionResponse {
+        submission: ExternalSubmission,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct ExternalSubmission {
+        id: i64,
+        title: String,
+        posted_at: chrono::DateTime<chrono::Local>,
+        #[serde(default)]
+        tags: Vec<String>,
+        file_url: Option<String>,
+    }
+
+    impl ExternalSubmission {
+        fn into_submission(self, site: SubmissionSite) -> Submission {
+            Submission {
+                id: self.id,
+                site,
+                title: self.title,
+                posted_at: self.posted_at,
+                tags: self.tags,
+                file_url: self.file_url,
+                folder_id: None,
+                folder_name: None,
+                local_tags: Vec::new(),
+                notes: None,
+                deleted_at: None,
+                site_data: BTreeMap::new(),
+                visibility: Visibility::Public,
+            }
+        }
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Empty {}
+
+    #[async_trait]
+    impl Site for External {
+        fn capabilities(&self) -> SiteCapabilities {
+            SiteCapabilities {
+                tags: !self.read_only,
+                ..Default::default()
+            }
+        }
+
+        fn update_delay(&self) -> std::time::Duration {
+            std::time::Duration::ZERO
+        }
+
+        async fn get_all_submissions(&self) -> Result<Vec<Submission>> {
+            let response: SubmissionsResponse = self
+                .call(serde_json::json!({ "method": "list_submissions" }))
+                .await?;
+
+            tracing::info!("Discovered {} submissions", response.submissions.len());
+
+            Ok(response
+                .submissions
+                .into_iter()
+                .map(|sub| sub.into_submission(self.site))
+                .collect())
+        }
+
+        async fn get_submission(&self, id: i64) -> Result<Submission> {
+            let response: SubmissionResponse = self
+                .call(serde_json::json!({ "method": "get_submission", "id": id }))
+                .await?;
+
+            Ok(response.submission.into_submission(self.site))
+        }
+
+        async fn set_tags(&self, id: i64, tags: &[String]) -> Result<()> {
+            let _: Empty = self
+                .call(serde_json::json!({ "method": "set_tags", "id": id, "tags": tags }))
+                .await?;
 
             Ok(())
         }
@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+
+use chrono::Datelike;
+
+use crate::{
+    normalize_tag,
+    sites::Submission,
+    split_namespace,
+    web::{escape, page},
+};
+
+/// Number of submissions listed as most and least tagged.
+const TAGGED_LIMIT: usize = 5;
+
+/// A titled table, with column headers and rows of values.
+type Section = (&'static str, [&'static str; 2], Vec<[String; 2]>);
+
+/// Summary of every stored submission.
+#[derive(Debug, Default)]
+pub struct Stats {
+    submissions: usize,
+    tags: usize,
+    sites: BTreeMap<String, usize>,
+    years: BTreeMap<i32, usize>,
+    namespaces: BTreeMap<String, usize>,
+    /// Site, ID, title, and number of tags for every submission, with the
+    /// most tagged first.
+    tagged: Vec<(String, i64, String, usize)>,
+}
+
+impl Stats {
+    pub fn new(submissions: &[Submission]) -> Self {
+        let mut stats = Self {
+            submissions: submissions.len(),
+            ..Default::default()
+        };
+
+        for sub in submissions {
+            stats.tags += sub.tags.len();
+            *stats.sites.entry(sub.site.to_string()).or_default() += 1;
+            *stats.years.entry(sub.posted_at.year()).or_default() += 1;
+
+            for tag in &sub.tags {
+                if let Some((namespace, _value)) = split_namespace(&normalize_tag(tag)) {
+                    *stats.namespaces.entry(namespace.to_string()).or_default() += 1;
+                }
+            }
+
+            stats.tagged.push((
+                sub.site.to_string(),
+                sub.id,
+                sub.title.clone(),
+                sub.tags.len(),
+            ));
+        }
+
+        stats
+            .tagged
+            .sort_by(|a, b| b.3.cmp(&a.3).then_with(|| (&a.0, a.1).cmp(&(&b.0, b.1))));
+
+        stats
+    }
+
+    fn average_tags(&self) -> f64 {
+        if self.submissions == 0 {
+            0.0
+        } else {
+            self.tags as f64 / self.submissions as f64
+        }
+    }
+
+    fn most_tagged(&self) -> &[(String, i64, String, usize)] {
+        &self.tagged[..self.tagged.len().min(TAGGED_LIMIT)]
+    }
+
+    fn least_tagged(&self) -> &[(String, i64, String, usize)] {
+        &self.tagged[self.tagged.len().saturating_sub(TAGGED_LIMIT)..]
+    }
+
+    fn sections(&self) -> Vec<Section> {
+        let mut total = 0;
+        let years = self
+            .years
+            .iter()
+            .map(|(year, count)| {
+                total += count;
+                [year.to_string(), format!("{} (total {})", count, total)]
+            })
+            .collect();
+
+        let submission = |(site, id, title, tags): &(String, i64, String, usize)| {
+            [format!("{}-{} {}", site, id, title), tags.to_string()]
+        };
+
+        vec![
+            (
+                "Overview",
+                ["", ""],
+                vec![
+                    ["Submissions".to_string(), self.submissions.to_string()],
+                    ["Tags".to_string(), self.tags.to_string()],
+                    [
+                        "Average tags".to_string(),
+                        format!("{:.1}", self.average_tags()),
+                    ],
+                ],
+            ),
+            (
+                "Sites",
+                ["Site", "Submissions"],
+                self.sites
+                    .iter()
+                    .map(|(site, count)| [site.clone(), count.to_string()])
+                    .collect(),
+            ),
+            ("Growth", ["Year", "Submissions"], years),
+            (
+                "Namespaces",
+                ["Namespace", "Tags"],
+                self.namespaces
+                    .iter()
+                    .map(|(namespace, count)| [namespace.clone(), count.to_string()])
+                    .collect(),
+            ),
+            (
+                "Most tagged",
+                ["Submission", "Tags"],
+                self.most_tagged().iter().map(submission).collect(),
+            ),
+            (
+                "Least tagged",
+                ["Submission", "Tags"],
+                self.least_tagged().iter().rev().map(submission).collect(),
+            ),
+        ]
+    }
+
+    /// Render as plain text tables for the terminal.
+    pub fn to_table(&self) -> String {
+        let mut out = String::new();
+
+        for (title, headers, rows) in self.sections() {
+            if rows.is_empty() {
+                continue;
+            }
+
+            let width = rows
+                .iter()
+                .map(|row| row[0].chars().count())
+                .chain(std::iter::once(headers[0].len()))
+                .max()
+                .unwrap_or_default();
+
+            out.push_str(&format!("{}\n", title));
+            if !headers[0].is_empty() {
+                out.push_str(&format!("  {:width$}  {}\n", headers[0], headers[1]));
+            }
+            for [name, value] in rows {
+                out.push_str(&format!("  {:width$}  {}\n", name, value));
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// Render as a standalone HTML page.
+    pub fn to_html(&self) -> String {
+        let mut body = String::new();
+
+        for (title, headers, rows) in self.sections() {
+            if rows.is_empty() {
+                continue;
+            }
+
+            body.push_str(&format!("<h2>{}</h2><table>", escape(title)));
+            if !headers[0].is_empty() {
+                body.push_str(&format!(
+                    "<tr><th>{}</th><th>{}</th></tr>",
+                    escape(headers[0]),
+                    escape(headers[1])
+                ));
+            }
+            for [name, value] in rows {
+                body.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td></tr>",
+                    escape(&name),
+                    escape(&value)
+                ));
+            }
+            body.push_str("</table>");
+        }
+
+        page("Statistics", &body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stats;
+    use crate::tests::submission;
+
+    #[test]
+    fn test_stats() {
+        let mut submissions = vec![
+            submission(1, &["species:wolf", "oc:rex", "blue fur"]),
+            submission(2, &["species:fox"]),
+            submission(3, &[]),
+        ];
+        submissions[0].posted_at =
+            chrono::TimeZone::ymd(&chrono::Local, 2019, 3, 7).and_hms(12, 0, 0);
+        submissions[1].posted_at =
+            chrono::TimeZone::ymd(&chrono::Local, 2020, 1, 1).and_hms(12, 0, 0);
+        submissions[2].posted_at =
+            chrono::TimeZone::ymd(&chrono::Local, 2020, 6, 1).and_hms(12, 0, 0);
+
+        let stats = Stats::new(&submissions);
+        assert_eq!(stats.tags, 4);
+        assert_eq!(stats.namespaces["species"], 2);
+        assert_eq!(stats.years[&2020], 2);
+        assert_eq!(stats.most_tagged()[0].1, 1);
+        assert_eq!(stats.least_tagged().last().unwrap().1, 3);
+
+        let table = stats.to_table();
+        assert!(table.contains("  Average tags  1.3\n"));
+        assert!(table.contains("  2020  2 (total 3)\n"));
+
+        assert!(stats.to_html().contains("<td>species</td><td>2</td>"));
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use crate::{normalize_tag, sites::SubmissionSite};
+
+/// Totals for a run that loads or changes submissions, logged when it
+/// finishes so it is clear what the run did without reading every line.
+#[derive(Debug)]
+pub struct RunSummary {
+    /// What each timed operation is, such as `update`.
+    operation: &'static str,
+    started: Instant,
+    sites: BTreeMap<String, SiteSummary>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct SiteSummary {
+    submissions: usize,
+    added: usize,
+    removed: usize,
+    failures: usize,
+    operations: u32,
+    time: Duration,
+}
+
+impl RunSummary {
+    pub fn new(operation: &'static str) -> Self {
+        Self {
+            operation,
+            started: Instant::now(),
+            sites: BTreeMap::new(),
+        }
+    }
+
+    /// Record how long a request or group of requests to a site took.
+    pub fn record_time(&mut self, site: SubmissionSite, time: Duration) {
+        let summary = self.site(site);
+        summary.operations += 1;
+        summary.time += time;
+    }
+
+    /// Record submissions loaded from a site.
+    pub fn record_loaded(&mut self, site: SubmissionSite, submissions: usize) {
+        self.site(site).submissions += submissions;
+    }
+
+    /// Record a submission's tags being changed.
+    pub fn record_change(&mut self, site: SubmissionSite, old: &[String], new: &[String]) {
+        let old: Vec<_> = old.iter().map(|tag| normalize_tag(tag)).collect();
+        let new: Vec<_> = new.iter().map(|tag| normalize_tag(tag)).collect();
+
+        let summary = self.site(site);
+        summary.submissions += 1;
+        summary.added += new.iter().filter(|tag| !old.contains(tag)).count();
+        summary.removed += old.iter().filter(|tag| !new.contains(tag)).count();
+    }
+
+    pub fn record_failure(&mut self, site: SubmissionSite) {
+        self.site(site).failures += 1;
+    }
+
+    /// Log the totals for the whole run and for each site.
+    pub fn log(&self) {
+        for line in self.lines(self.started.elapsed()) {
+            tracing::info!("{}", line);
+        }
+    }
+
+    fn lines(&self, elapsed: Duration) -> Vec<String> {
+        let total = self
+            .sites
+            .values()
+            .fold(SiteSummary::default(), |total, site| SiteSummary {
+                submissions: total.submissions + site.submissions,
+                added: total.added + site.added,
+                removed: total.removed + site.removed,
+                failures: total.failures + site.failures,
+                operations: total.operations + site.operations,
+                time: total.time + site.time,
+            });
+
+        let mut lines = vec![format!(
+            "Summary: {}, took {}",
+            total.describe(),
+            format_duration(elapsed)
+        )];
+
+        for (site, summary) in &self.sites {
+            let mut line = format!("  {}: {}", site, summary.describe());
+            if summary.operations > 0 {
+                line.push_str(&format!(
+                    ", average {} per {}",
+                    format_duration(summary.time / summary.operations),
+                    self.operation
+                ));
+            }
+
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    fn site(&mut self, site: SubmissionSite) -> &mut SiteSummary {
+        self.sites.entry(site.to_string()).or_default()
+    }
+}
+
+impl SiteSummary {
+    fn describe(&self) -> String {
+        format!(
+            "{} submissions, {} tags added, {} removed, {} failed",
+            self.submissions, self.added, self.removed, self.failures
+        )
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    match duration.as_secs() {
+        secs if secs >= 60 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{:.2}s", duration.as_secs_f64()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RunSummary;
+    use crate::sites::SubmissionSite;
+
+    #[test]
+    fn test_summary_lines() {
+        let tags =
+            |tags: &[&str]| -> Vec<String> { tags.iter().map(|tag| tag.to_string()).collect() };
+
+        let mut summary = RunSummary::new("update");
+        summary.record_change(
+            SubmissionSite::Weasyl,
+            &tags(&["wolf", "sketch"]),
+            &tags(&["Wolf", "canine", "mammal"]),
+        );
+        summary.record_time(SubmissionSite::Weasyl, Duration::from_millis(500));
+        summary.record_time(SubmissionSite::Weasyl, Duration::from_millis(1500));
+        summary.record_failure(SubmissionSite::FurAffinity);
+
+        assert_eq!(
+            summary.lines(Duration::from_secs(75)),
+            vec![
+                "Summary: 1 submissions, 2 tags added, 1 removed, 1 failed, took 1m 15s",
+                "  FurAffinity: 0 submissions, 0 tags added, 0 removed, 1 failed",
+                "  Weasyl: 1 submissions, 2 tags added, 1 removed, 0 failed, average 1.00s per update",
+            ]
+        );
+    }
+}
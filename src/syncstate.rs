@@ -0,0 +1,279 @@
+use crate::{error, pending::PendingChange, sites::SubmissionSite};
+
+/// Queued changes and change history from one database, to be imported into
+/// another so changes planned on one machine can be applied on another.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct SyncState {
+    #[serde(default)]
+    pub pending: Vec<PendingChange>,
+    #[serde(default)]
+    pub batches: Vec<Batch>,
+}
+
+/// A batch of applied changes.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Batch {
+    pub command: String,
+    pub search: Option<String>,
+    pub changes: Option<String>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub submissions: Vec<BatchChange>,
+}
+
+/// A single submission's tags changed by a batch.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct BatchChange {
+    pub site: SubmissionSite,
+    pub id: i64,
+    pub old_tags: Vec<String>,
+    pub new_tags: Vec<String>,
+    pub changed_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// How much of a sync state was new to the database.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Imported {
+    pub batches: usize,
+    pub pending: usize,
+    /// Queued changes removed because an imported batch already applied
+    /// them.
+    pub cleared: u64,
+}
+
+fn utc(time: chrono::NaiveDateTime) -> chrono::DateTime<chrono::Utc> {
+    chrono::DateTime::<chrono::Utc>::from_utc(time, chrono::Utc)
+}
+
+/// Collect every queued change and batch for an account.
+pub async fn export(pool: &sqlx::Pool<sqlx::Sqlite>, account: &str) -> error::Result<SyncState> {
+    let pending = crate::pending::load(pool, account).await?;
+
+    let rows = sqlx::query!(
+        "SELECT id, command, search, changes, started_at FROM batch WHERE account = $1 ORDER BY id",
+        account
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut batches = Vec::with_capacity(rows.len());
+    for row in rows {
+        let changes = sqlx::query!(
+            "SELECT site, id, old_tags, new_tags, changed_at FROM batch_change WHERE batch_id = $1 ORDER BY changed_at",
+            row.id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut submissions = Vec::with_capacity(changes.len());
+        for change in changes {
+            submissions.push(BatchChange {
+                site: change.site.parse()?,
+                id: change.id,
+                old_tags: serde_json::from_str(&change.old_tags)?,
+                new_tags: serde_json::from_str(&change.new_tags)?,
+                changed_at: utc(change.changed_at),
+            });
+        }
+
+        batches.push(Batch {
+            command: row.command,
+            search: row.search,
+            changes: row.changes,
+            started_at: utc(row.started_at),
+            submissions,
+        });
+    }
+
+    Ok(SyncState { pending, batches })
+}
+
+/// Add batches and queued changes from another database. Batches that were
+/// already imported are skipped, queued changes replace older ones for the
+/// same submission, and queued changes an imported batch applied after they
+/// were queued are removed.
+pub async fn import(
+    pool: &sqlx::Pool<sqlx::Sqlite>,
+    account: &str,
+    state: &SyncState,
+) -> error::Result<Imported> {
+    let mut imported = Imported::default();
+    let mut tx = pool.begin().await?;
+
+    for batch in &state.batches {
+        let existing = sqlx::query!(
+            "SELECT id FROM batch WHERE account = $1 AND command = $2 AND started_at = $3",
+            account,
+            batch.command,
+            batch.started_at
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        if existing.is_some() {
+            continue;
+        }
+
+        let batch_id = sqlx::query!(
+            "INSERT INTO batch (account, command, search, changes, started_at) VALUES ($1, $2, $3, $4, $5)",
+            account,
+            batch.command,
+            batch.search,
+            batch.changes,
+            batch.started_at
+        )
+        .execute(&mut tx)
+        .await?
+        .last_insert_rowid();
+
+        for change in &batch.submissions {
+            let site = change.site.as_str();
+            let old_tags = serde_json::to_string(&change.old_tags)?;
+            let new_tags = serde_json::to_string(&change.new_tags)?;
+
+            sqlx::query!(
+                "INSERT OR REPLACE INTO batch_change (batch_id, site, id, old_tags, new_tags, changed_at) VALUES ($1, $2, $3, $4, $5, $6)",
+                batch_id,
+                site,
+                change.id,
+                old_tags,
+                new_tags,
+                change.changed_at
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        imported.batches += 1;
+    }
+
+    for change in &state.pending {
+        let site = change.site.as_str();
+        let old_tags = serde_json::to_string(&change.old_tags)?;
+        let new_tags = serde_json::to_string(&change.new_tags)?;
+
+        imported.pending += sqlx::query!(
+            "INSERT INTO pending_change (account, site, id, old_tags, new_tags, changes, queued_at) VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (account, site, id) DO UPDATE SET new_tags = excluded.new_tags, changes = excluded.changes, queued_at = excluded.queued_at
+                WHERE excluded.queued_at > pending_change.queued_at",
+            account,
+            site,
+            change.id,
+            old_tags,
+            new_tags,
+            change.changes,
+            change.queued_at
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected() as usize;
+    }
+
+    imported.cleared = sqlx::query!(
+        "DELETE FROM pending_change WHERE account = $1 AND EXISTS (
+            SELECT 1 FROM batch_change JOIN batch ON batch.id = batch_change.batch_id
+            WHERE batch.account = pending_change.account AND batch_change.site = pending_change.site AND batch_change.id = pending_change.id
+                AND batch_change.changed_at > pending_change.queued_at)",
+        account
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected();
+
+    tx.commit().await?;
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export, import, Imported};
+    use crate::{pending, tests::submission};
+
+    async fn open() -> sqlx::Pool<sqlx::Sqlite> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_sync_state_round_trip() {
+        let laptop = open().await;
+        let server = open().await;
+
+        let tags =
+            |tags: &[&str]| -> Vec<String> { tags.iter().map(|tag| tag.to_string()).collect() };
+        pending::queue(
+            &laptop,
+            "default",
+            &submission(1, &["wolf"]),
+            &tags(&["wolf", "canine"]),
+            "canine",
+        )
+        .await
+        .unwrap();
+        pending::queue(
+            &laptop,
+            "default",
+            &submission(2, &["fox"]),
+            &tags(&["fox", "canine"]),
+            "canine",
+        )
+        .await
+        .unwrap();
+
+        let state = export(&laptop, "default").await.unwrap();
+        assert_eq!(
+            import(&server, "default", &state).await.unwrap(),
+            Imported {
+                batches: 0,
+                pending: 2,
+                cleared: 0
+            }
+        );
+
+        // Flushing on the server applies one change in a batch.
+        let batch_id = crate::start_batch(&server, "default", "flush", None, None)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO batch_change (batch_id, site, id, old_tags, new_tags, changed_at) VALUES ($1, 'FurAffinity', 1, '[\"wolf\"]', '[\"wolf\",\"canine\"]', $2)",
+        )
+        .bind(batch_id)
+        .bind(chrono::Utc::now())
+        .execute(&server)
+        .await
+        .unwrap();
+        pending::clear_applied(&server, "default", batch_id)
+            .await
+            .unwrap();
+
+        let state = export(&server, "default").await.unwrap();
+        assert_eq!(state.batches.len(), 1);
+        assert_eq!(
+            state.batches[0].submissions[0].new_tags,
+            tags(&["wolf", "canine"])
+        );
+
+        assert_eq!(
+            import(&laptop, "default", &state).await.unwrap(),
+            Imported {
+                batches: 1,
+                pending: 0,
+                cleared: 1
+            }
+        );
+        let remaining = pending::load(&laptop, "default").await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, 2);
+
+        // Importing again changes nothing.
+        assert_eq!(
+            import(&laptop, "default", &state).await.unwrap(),
+            Imported::default()
+        );
+    }
+}
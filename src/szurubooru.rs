@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+/// A client for uploading posts to a szurubooru instance.
+pub struct Szurubooru {
+    client: reqwest::Client,
+    url: String,
+    auth: String,
+}
+
+/// Content rating of a post.
+#[derive(Clone, Copy, Debug, serde::Serialize, clap::ArgEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Safety {
+    Safe,
+    Sketchy,
+    Unsafe,
+}
+
+/// The parts of a post needed to update it.
+#[derive(Debug, serde::Deserialize)]
+pub struct Post {
+    pub id: i64,
+    pub version: i64,
+}
+
+/// Error returned by the API, with a description of what went wrong.
+#[derive(Debug, serde::Deserialize)]
+struct ApiError {
+    title: String,
+    description: String,
+}
+
+impl Szurubooru {
+    /// Create a client using a login token, which can be created from the
+    /// account page of the instance.
+    pub fn new(url: &str, user: &str, token: &str) -> Self {
+        let credentials = base64::Engine::encode(
+            &base64::engine::general_purpose::STANDARD,
+            format!("{}:{}", user, token),
+        );
+
+        Self {
+            client: reqwest::Client::default(),
+            url: url.trim_end_matches('/').to_string(),
+            auth: format!("Token {}", credentials),
+        }
+    }
+
+    /// Link to view a post on the instance.
+    pub fn post_url(&self, id: i64) -> String {
+        format!("{}/post/{}", self.url, id)
+    }
+
+    /// Upload a file as a new post. Tags that don't exist yet are created.
+    pub async fn create_post(
+        &self,
+        path: &Path,
+        tags: &[String],
+        source: Option<&str>,
+        safety: Safety,
+    ) -> anyhow::Result<Post> {
+        let content = tokio::fs::read(path)
+            .await
+            .context("Could not read submission file")?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "file".to_string());
+
+        let metadata = serde_json::json!({
+            "tags": tags,
+            "source": source.unwrap_or_default(),
+            "safety": safety,
+        });
+
+        let form = reqwest::multipart::Form::new()
+            .text("metadata", metadata.to_string())
+            .part(
+                "content",
+                reqwest::multipart::Part::bytes(content).file_name(file_name),
+            );
+
+        let req = self
+            .client
+            .post(format!("{}/api/posts/", self.url))
+            .multipart(form);
+
+        self.send(req).await
+    }
+
+    /// Replace the tags and source of an existing post.
+    pub async fn update_post(
+        &self,
+        id: i64,
+        tags: &[String],
+        source: Option<&str>,
+    ) -> anyhow::Result<Post> {
+        let url = format!("{}/api/post/{}", self.url, id);
+
+        // Updates must include the current version to detect conflicting
+        // edits.
+        let post: Post = self.send(self.client.get(&url)).await?;
+
+        let req = self.client.put(&url).json(&serde_json::json!({
+            "version": post.version,
+            "tags": tags,
+            "source": source.unwrap_or_default(),
+        }));
+
+        self.send(req).await
+    }
+
+    async fn send<T: serde::de::DeserializeOwned>(
+        &self,
+        req: reqwest::RequestBuilder,
+    ) -> anyhow::Result<T> {
+        let resp = req
+            .header("authorization", &self.auth)
+            .header("accept", "application/json")
+            .send()
+            .await
+            .context("Could not make request to szurubooru")?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            return match resp.json::<ApiError>().await {
+                Ok(err) => Err(anyhow::anyhow!("{}: {}", err.title, err.description)),
+                Err(_) => Err(anyhow::anyhow!(
+                    "Got bad szurubooru status code: {}",
+                    status
+                )),
+            };
+        }
+
+        resp.json()
+            .await
+            .context("Could not decode szurubooru response")
+    }
+}
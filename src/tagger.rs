@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+/// A tag suggested by an external tagger.
+#[derive(Debug, PartialEq)]
+pub struct Suggestion {
+    pub tag: String,
+    pub confidence: f32,
+}
+
+/// Run a tagger command against a file and collect its suggestions.
+pub async fn run_tagger(cmd: &str, path: &Path) -> anyhow::Result<Vec<Suggestion>> {
+    let args = crate::split_tags(cmd);
+    let (program, args) = args.split_first().context("Tagger command was empty")?;
+
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .arg(path)
+        .output()
+        .await
+        .context("Could not run tagger command")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "tagger command failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(parse_suggestions(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse tagger output, where each line is a tag optionally followed by a
+/// confidence. Tags without a confidence are always suggested.
+fn parse_suggestions(output: &str) -> Vec<Suggestion> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let scored = line
+                .rsplit_once([',', '\t', ' '])
+                .and_then(|(tag, confidence)| Some((tag.trim(), confidence.parse().ok()?)));
+
+            match scored {
+                Some((tag, confidence)) => Suggestion {
+                    tag: tag.to_string(),
+                    confidence,
+                },
+                None => Suggestion {
+                    tag: line.to_string(),
+                    confidence: 1.0,
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_suggestions, Suggestion};
+
+    #[test]
+    fn test_parse_suggestions() {
+        let suggestions = parse_suggestions("wolf 0.95\nblue fur\t0.4\n\nsolo\nmale,0.7\n");
+
+        assert_eq!(
+            suggestions,
+            vec![
+                Suggestion {
+                    tag: "wolf".to_string(),
+                    confidence: 0.95
+                },
+                Suggestion {
+                    tag: "blue fur".to_string(),
+                    confidence: 0.4
+                },
+                Suggestion {
+                    tag: "solo".to_string(),
+                    confidence: 1.0
+                },
+                Suggestion {
+                    tag: "male".to_string(),
+                    confidence: 0.7
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use crate::{normalize_tag, sites::Submission};
+
+/// How often tags are used, and how often each pair of tags is used on the
+/// same submission.
+#[derive(Debug, Default)]
+pub struct TagGraph {
+    nodes: BTreeMap<String, usize>,
+    edges: BTreeMap<(String, String), usize>,
+}
+
+impl TagGraph {
+    pub fn new<'a, I>(submissions: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Submission>,
+    {
+        let mut graph = Self::default();
+
+        for sub in submissions {
+            let mut tags: Vec<_> = sub.tags.iter().map(|tag| normalize_tag(tag)).collect();
+            tags.sort();
+            tags.dedup();
+
+            for (index, tag) in tags.iter().enumerate() {
+                *graph.nodes.entry(tag.clone()).or_default() += 1;
+
+                for other in &tags[index + 1..] {
+                    *graph.edges.entry((tag.clone(), other.clone())).or_default() += 1;
+                }
+            }
+        }
+
+        graph
+    }
+
+    /// Remove edges used fewer than `min_count` times, and any tags left
+    /// without edges.
+    pub fn prune(&mut self, min_count: usize) {
+        self.edges.retain(|_tags, count| *count >= min_count);
+
+        let edges = &self.edges;
+        self.nodes
+            .retain(|tag, _count| edges.keys().any(|(a, b)| a == tag || b == tag));
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("graph tags {\n");
+
+        for (tag, count) in &self.nodes {
+            out.push_str(&format!("  \"{}\" [weight={}];\n", escape_dot(tag), count));
+        }
+
+        for ((a, b), count) in &self.edges {
+            out.push_str(&format!(
+                "  \"{}\" -- \"{}\" [weight={}];\n",
+                escape_dot(a),
+                escape_dot(b),
+                count
+            ));
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            "\n",
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#,
+            "\n",
+            r#"  <key id="count" for="node" attr.name="count" attr.type="int"/>"#,
+            "\n",
+            r#"  <key id="weight" for="edge" attr.name="weight" attr.type="int"/>"#,
+            "\n",
+            r#"  <graph id="tags" edgedefault="undirected">"#,
+            "\n",
+        ));
+
+        for (tag, count) in &self.nodes {
+            out.push_str(&format!(
+                "    <node id=\"{}\"><data key=\"count\">{}</data></node>\n",
+                escape_xml(tag),
+                count
+            ));
+        }
+
+        for ((a, b), count) in &self.edges {
+            out.push_str(&format!(
+                "    <edge source=\"{}\" target=\"{}\"><data key=\"weight\">{}</data></edge>\n",
+                escape_xml(a),
+                escape_xml(b),
+                count
+            ));
+        }
+
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+}
+
+fn escape_dot(tag: &str) -> String {
+    tag.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_xml(tag: &str) -> String {
+    tag.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TagGraph;
+    use crate::tests::submission;
+
+    #[test]
+    fn test_tag_graph() {
+        let submissions = vec![
+            submission(1, &["Fox", "wolf"]),
+            submission(2, &["fox", "wolf", "cat"]),
+            submission(3, &["cat"]),
+        ];
+
+        let mut graph = TagGraph::new(&submissions);
+        assert_eq!(graph.nodes["fox"], 2);
+        assert_eq!(graph.nodes["cat"], 2);
+        assert_eq!(graph.edges[&("fox".to_string(), "wolf".to_string())], 2);
+        assert_eq!(graph.edges[&("cat".to_string(), "fox".to_string())], 1);
+
+        graph.prune(2);
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.nodes.len(), 2);
+
+        assert!(graph.to_dot().contains(r#""fox" -- "wolf" [weight=2];"#));
+        assert!(graph
+            .to_graphml()
+            .contains(r#"<edge source="fox" target="wolf">"#));
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{
+    config::{SortOrder, TagSort},
+    normalize_tag, split_namespace,
+};
+
+/// Reorder tags according to the configured sort policy.
+pub fn sort_tags(tags: &mut [String], sort: &TagSort) {
+    match sort.order {
+        SortOrder::None => (),
+        SortOrder::Alphabetical => tags.sort_by_cached_key(|tag| normalize_tag(tag)),
+        SortOrder::Category => tags.sort_by_cached_key(|tag| {
+            let normalized = normalize_tag(tag);
+
+            // Configured categories come first in their listed order, then any
+            // other categories, then tags without a category.
+            let group = match split_namespace(&normalized) {
+                Some((category, _value)) => match sort
+                    .categories
+                    .iter()
+                    .position(|known| normalize_tag(known) == category)
+                {
+                    Some(position) => (0, position, category.to_string()),
+                    None => (1, 0, category.to_string()),
+                },
+                None => (2, 0, String::new()),
+            };
+
+            (group, normalized)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sort_tags;
+    use crate::config::{SortOrder, TagSort};
+
+    #[test]
+    fn test_sort_tags() {
+        let tags = ["wolf", "species:wolf", "Blue Fur", "oc:rex", "art:sketch"];
+        let mut sort = TagSort {
+            order: SortOrder::None,
+            categories: vec!["oc".to_string(), "species".to_string()],
+            ..Default::default()
+        };
+
+        let mut sorted: Vec<_> = tags.iter().map(|tag| tag.to_string()).collect();
+        sort_tags(&mut sorted, &sort);
+        assert_eq!(sorted, tags);
+
+        sort.order = SortOrder::Alphabetical;
+        sort_tags(&mut sorted, &sort);
+        assert_eq!(
+            sorted,
+            ["art:sketch", "Blue Fur", "oc:rex", "species:wolf", "wolf"]
+        );
+
+        sort.order = SortOrder::Category;
+        sort_tags(&mut sorted, &sort);
+        assert_eq!(
+            sorted,
+            ["oc:rex", "species:wolf", "art:sketch", "Blue Fur", "wolf"]
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Context;
+
+use crate::normalize_tag;
+
+/// Tags that mean the same thing in different languages.
+#[derive(Debug, Default)]
+pub struct Translations {
+    counterparts: HashMap<String, Vec<String>>,
+    /// Each normalized tag as it was written in the file.
+    spellings: HashMap<String, String>,
+}
+
+impl Translations {
+    /// Parse a TOML file where each entry is a tag and the tags it translates
+    /// to, such as `wolf = ["オオカミ", "狼"]`. Every tag in an entry is a
+    /// translation of every other.
+    pub fn parse(data: &str) -> anyhow::Result<Self> {
+        let entries: BTreeMap<String, Vec<String>> =
+            toml::from_str(data).context("Could not parse translations")?;
+
+        let mut counterparts: HashMap<String, Vec<String>> = HashMap::new();
+        let mut spellings = HashMap::new();
+        for (tag, translations) in entries {
+            let group: Vec<_> = std::iter::once(tag)
+                .chain(translations)
+                .map(|tag| {
+                    let normalized = normalize_tag(&tag);
+                    spellings.entry(normalized.clone()).or_insert(tag);
+                    normalized
+                })
+                .collect();
+
+            for tag in &group {
+                let others = counterparts.entry(tag.clone()).or_default();
+                for other in &group {
+                    if other != tag && !others.contains(other) {
+                        others.push(other.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            counterparts,
+            spellings,
+        })
+    }
+
+    /// Read translations from a file.
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(path).context("Could not read translations file")?;
+        let translations = Self::parse(&data)?;
+        tracing::debug!(
+            path,
+            tags = translations.counterparts.len(),
+            "Loaded translations"
+        );
+
+        Ok(translations)
+    }
+
+    /// Normalized translations of a normalized tag.
+    pub fn counterparts(&self, tag: &str) -> &[String] {
+        self.counterparts
+            .get(tag)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// A normalized tag as it was written in the translations file.
+    pub fn spelling<'a>(&'a self, tag: &'a str) -> &'a str {
+        self.spellings.get(tag).map(String::as_str).unwrap_or(tag)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counterparts.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Translations;
+    use crate::{query::Query, tests::submission};
+
+    #[test]
+    fn test_translations() {
+        let translations = Translations::parse(
+            r#"
+            wolf = ["オオカミ", "狼"]
+            "Grey Fur" = ["灰色の毛"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(translations.counterparts("wolf"), ["オオカミ", "狼"]);
+        assert_eq!(translations.counterparts("狼"), ["wolf", "オオカミ"]);
+        assert_eq!(translations.counterparts("灰色の毛"), ["grey_fur"]);
+        assert_eq!(translations.spelling("grey_fur"), "Grey Fur");
+        assert_eq!(translations.spelling("fox"), "fox");
+        assert!(translations.counterparts("fox").is_empty());
+
+        let submissions = [
+            submission(1, &["オオカミ"]),
+            submission(2, &["wolf"]),
+            submission(3, &["fox"]),
+        ];
+        let matched = |search: &str| {
+            let query = Query::parse_with(search, &translations).unwrap();
+            submissions
+                .iter()
+                .filter(|sub| query.matches(sub))
+                .map(|sub| sub.id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(matched("wolf"), vec![1, 2]);
+        assert_eq!(matched("狼"), vec![1, 2]);
+        assert_eq!(matched("-wolf"), vec![3]);
+        assert!(Query::parse_with("wolf", &translations)
+            .unwrap()
+            .required_tags()
+            .is_empty());
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Frame, Terminal,
+};
+
+use crate::{
+    config::{Bundles, TagSort},
+    query::Query,
+    sites::Submission,
+    translate::Translations,
+};
+
+const HELP: &str = "/ search  a/r add/remove tag  A/R for all matches  u unstage  w apply  q quit";
+
+enum Mode {
+    Normal,
+    Search,
+    AddTag { all: bool },
+    RemoveTag { all: bool },
+}
+
+struct App<'a> {
+    submissions: &'a [Submission],
+    translations: &'a Translations,
+    bundles: &'a Bundles,
+    sort: &'a TagSort,
+    matches: Vec<usize>,
+    list_state: ListState,
+
+    staged: HashMap<usize, Vec<String>>,
+
+    mode: Mode,
+    search: String,
+    /// Why the current search could not be used, if it couldn't.
+    search_error: Option<String>,
+    input: String,
+}
+
+impl<'a> App<'a> {
+    fn new(
+        submissions: &'a [Submission],
+        translations: &'a Translations,
+        bundles: &'a Bundles,
+        sort: &'a TagSort,
+    ) -> Self {
+        let mut app = Self {
+            submissions,
+            translations,
+            bundles,
+            sort,
+            matches: Vec::new(),
+            list_state: ListState::default(),
+            staged: HashMap::new(),
+            mode: Mode::Normal,
+            search: String::new(),
+            search_error: None,
+            input: String::new(),
+        };
+        app.update_matches();
+
+        app
+    }
+
+    fn update_matches(&mut self) {
+        self.search_error = None;
+        self.matches = if self.search.trim().is_empty() {
+            (0..self.submissions.len()).collect()
+        } else {
+            match Query::parse_with(&self.search, self.translations) {
+                Ok(query) => self
+                    .submissions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_index, sub)| query.matches(sub))
+                    .map(|(index, _sub)| index)
+                    .collect(),
+                Err(err) => {
+                    self.search_error = Some(err.to_string());
+                    Vec::new()
+                }
+            }
+        };
+
+        self.list_state.select(if self.matches.is_empty() {
+            None
+        } else {
+            Some(0)
+        });
+    }
+
+    fn selected(&self) -> Option<usize> {
+        self.list_state
+            .selected()
+            .and_then(|index| self.matches.get(index).copied())
+    }
+
+    fn current_tags(&self, index: usize) -> &[String] {
+        self.staged
+            .get(&index)
+            .unwrap_or(&self.submissions[index].tags)
+    }
+
+    /// Stage a change string against the selected submission, or every
+    /// matched submission.
+    fn stage(&mut self, changes: &str, all: bool) {
+        let indexes = if all {
+            self.matches.clone()
+        } else {
+            self.selected().into_iter().collect()
+        };
+
+        for index in indexes {
+            let new_tags = crate::update_tags(
+                &self.submissions[index],
+                self.current_tags(index),
+                changes,
+                self.bundles,
+                self.sort,
+            );
+
+            if new_tags == self.submissions[index].tags {
+                self.staged.remove(&index);
+            } else {
+                self.staged.insert(index, new_tags);
+            }
+        }
+    }
+
+    fn move_selection(&mut self, offset: isize) {
+        if self.matches.is_empty() {
+            return;
+        }
+
+        let current = self.list_state.selected().unwrap_or(0) as isize;
+        let next = (current + offset).clamp(0, self.matches.len() as isize - 1);
+        self.list_state.select(Some(next as usize));
+    }
+
+    /// Handle a key press, returning if the application should exit and if
+    /// staged changes should be applied.
+    fn handle_key(&mut self, code: KeyCode) -> Option<bool> {
+        match self.mode {
+            Mode::Normal => match code {
+                KeyCode::Char('q') | KeyCode::Esc => return Some(false),
+                KeyCode::Char('w') => return Some(true),
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+                KeyCode::PageDown => self.move_selection(10),
+                KeyCode::PageUp => self.move_selection(-10),
+                KeyCode::Char('/') => {
+                    self.input = self.search.clone();
+                    self.mode = Mode::Search;
+                }
+                KeyCode::Char('a') => self.mode = Mode::AddTag { all: false },
+                KeyCode::Char('A') => self.mode = Mode::AddTag { all: true },
+                KeyCode::Char('r') => self.mode = Mode::RemoveTag { all: false },
+                KeyCode::Char('R') => self.mode = Mode::RemoveTag { all: true },
+                KeyCode::Char('u') => {
+                    if let Some(index) = self.selected() {
+                        self.staged.remove(&index);
+                    }
+                }
+                _ => (),
+            },
+            _ => match code {
+                KeyCode::Esc => {
+                    self.input.clear();
+                    self.mode = Mode::Normal;
+                }
+                KeyCode::Backspace => {
+                    self.input.pop();
+                }
+                KeyCode::Char(c) => self.input.push(c),
+                KeyCode::Enter => {
+                    let input = std::mem::take(&mut self.input);
+
+                    match std::mem::replace(&mut self.mode, Mode::Normal) {
+                        Mode::Search => {
+                            self.search = input;
+                            self.update_matches();
+                        }
+                        Mode::AddTag { all } => {
+                            let changes: Vec<_> = crate::split_tags(&input)
+                                .into_iter()
+                                .map(|tag| crate::quote_tag(tag.trim_start_matches('-')))
+                                .collect();
+                            self.stage(&changes.join(" "), all);
+                        }
+                        Mode::RemoveTag { all } => {
+                            let changes: Vec<_> = crate::split_tags(&input)
+                                .into_iter()
+                                .map(|tag| format!("-{}", crate::quote_tag(&tag)))
+                                .collect();
+                            self.stage(&changes.join(" "), all);
+                        }
+                        Mode::Normal => unreachable!(),
+                    }
+                }
+                _ => (),
+            },
+        }
+
+        None
+    }
+
+    fn draw(&mut self, f: &mut Frame) {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(1), Constraint::Length(3)])
+            .split(f.size());
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(rows[0]);
+
+        let items: Vec<_> = self
+            .matches
+            .iter()
+            .map(|index| {
+                let sub = &self.submissions[*index];
+                let marker = if self.staged.contains_key(index) {
+                    "* "
+                } else {
+                    "  "
+                };
+
+                ListItem::new(format!("{}{}-{} {}", marker, sub.site, sub.id, sub.title))
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(format!(
+                "Submissions ({} matched, {} staged)",
+                self.matches.len(),
+                self.staged.len()
+            )))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        f.render_stateful_widget(list, columns[0], &mut self.list_state);
+
+        let detail = match self.selected() {
+            Some(index) => self.detail(index),
+            None => vec![Line::from("No submission selected")],
+        };
+        let detail = Paragraph::new(detail)
+            .block(Block::default().borders(Borders::ALL).title("Details"))
+            .wrap(Wrap { trim: false });
+        f.render_widget(detail, columns[1]);
+
+        let (title, text) = match self.mode {
+            Mode::Normal => match &self.search_error {
+                Some(err) => ("Search error", err.clone()),
+                None => ("Help", HELP.to_string()),
+            },
+            Mode::Search => ("Search", self.input.clone()),
+            Mode::AddTag { all: false } => ("Add tags", self.input.clone()),
+            Mode::AddTag { all: true } => ("Add tags to all matches", self.input.clone()),
+            Mode::RemoveTag { all: false } => ("Remove tags", self.input.clone()),
+            Mode::RemoveTag { all: true } => ("Remove tags from all matches", self.input.clone()),
+        };
+        let prompt =
+            Paragraph::new(text).block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(prompt, rows[1]);
+    }
+
+    fn detail(&self, index: usize) -> Vec<Line<'static>> {
+        let sub = &self.submissions[index];
+        let new_tags = self.current_tags(index);
+
+        let mut lines = vec![
+            Line::from(Span::styled(
+                sub.title.clone(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Line::from(format!(
+                "{}-{}, posted {}",
+                sub.site,
+                sub.id,
+                sub.posted_at.format("%Y-%m-%d")
+            )),
+            Line::from(""),
+        ];
+
+        for tag in new_tags {
+            if sub.tags.contains(tag) {
+                lines.push(Line::from(format!("  {}", tag)));
+            } else {
+                lines.push(Line::from(Span::styled(
+                    format!("+ {}", tag),
+                    Style::default().fg(Color::Green),
+                )));
+            }
+        }
+
+        for tag in sub.tags.iter().filter(|tag| !new_tags.contains(tag)) {
+            lines.push(Line::from(Span::styled(
+                format!("- {}", tag),
+                Style::default().fg(Color::Red),
+            )));
+        }
+
+        lines
+    }
+}
+
+/// Run an interactive session for browsing submissions and staging tag
+/// changes, returning the staged changes if they should be applied.
+pub fn run<'a>(
+    submissions: &'a [Submission],
+    translations: &Translations,
+    bundles: &Bundles,
+    sort: &TagSort,
+) -> anyhow::Result<Vec<(&'a Submission, Vec<String>)>> {
+    enable_raw_mode()?;
+    execute!(std::io::stdout(), EnterAlternateScreen)?;
+
+    let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stdout()))?;
+    let mut app = App::new(submissions, translations, bundles, sort);
+
+    let result = event_loop(&mut terminal, &mut app);
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+
+    if !result? {
+        return Ok(Vec::new());
+    }
+
+    let mut changes: Vec<_> = app.staged.into_iter().collect();
+    changes.sort_by_key(|(index, _tags)| *index);
+
+    Ok(changes
+        .into_iter()
+        .map(|(index, tags)| (&submissions[index], tags))
+        .collect())
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
+    app: &mut App,
+) -> anyhow::Result<bool> {
+    loop {
+        terminal.draw(|f| app.draw(f))?;
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            if let Some(apply) = app.handle_key(key.code) {
+                return Ok(apply);
+            }
+        }
+    }
+}
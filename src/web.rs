@@ -0,0 +1,654 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Extension, Form, Router,
+};
+use base64::Engine;
+
+use crate::{
+    blocklist::Blocklist,
+    sites::{Sites, Submission},
+};
+
+pub struct AppState {
+    pub pool: sqlx::Pool<sqlx::Sqlite>,
+    pub sites: Sites,
+    pub account: String,
+    pub blocklist: Blocklist,
+    pub translations: crate::translate::Translations,
+    pub bundles: crate::config::Bundles,
+    pub sort: crate::config::TagSort,
+}
+
+type SharedState = State<Arc<AppState>>;
+
+/// Wrapper to convert any error into a response.
+struct WebError(anyhow::Error);
+
+impl<E: Into<anyhow::Error>> From<E> for WebError {
+    fn from(err: E) -> Self {
+        Self(err.into())
+    }
+}
+
+impl IntoResponse for WebError {
+    fn into_response(self) -> Response {
+        tracing::error!("Request failed: {:?}", self.0);
+
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Html(page(
+                "Error",
+                &format!("<p>{}</p>", escape(&self.0.to_string())),
+            )),
+        )
+            .into_response()
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct PlanParams {
+    #[serde(default)]
+    search: String,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    csrf: String,
+}
+
+/// Random token for this process, embedded in forms and required when they
+/// are submitted.
+#[derive(Clone)]
+struct CsrfToken(Arc<str>);
+
+/// Checks made on every request before it reaches a handler.
+struct Guard {
+    /// Values allowed in the `Host` header, or empty to allow any.
+    hosts: Vec<String>,
+    /// Password required with HTTP basic auth, if any.
+    password: Option<String>,
+}
+
+impl Guard {
+    fn new(address: SocketAddr, password: Option<String>) -> anyhow::Result<Self> {
+        if !address.ip().is_loopback() && password.is_none() {
+            anyhow::bail!(
+                "refusing to listen on {} without a password, use --password or a loopback address",
+                address
+            );
+        }
+
+        // A server listening on every interface can be reached by any name,
+        // so only the password protects it.
+        let hosts = if address.ip().is_unspecified() {
+            vec![]
+        } else if address.ip().is_loopback() {
+            vec![address.to_string(), format!("localhost:{}", address.port())]
+        } else {
+            vec![address.to_string()]
+        };
+
+        Ok(Self { hosts, password })
+    }
+
+    /// Reject requests for another host, which a page on another site could
+    /// make by pointing its own name at this address, and requests without
+    /// the password when one is set.
+    fn check(&self, headers: &HeaderMap) -> Result<(), StatusCode> {
+        let header =
+            |name: header::HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+
+        if !self.hosts.is_empty() {
+            let host = header(header::HOST).ok_or(StatusCode::FORBIDDEN)?;
+            if !self.hosts.iter().any(|allowed| allowed == host) {
+                return Err(StatusCode::FORBIDDEN);
+            }
+
+            if let Some(origin) = header(header::ORIGIN) {
+                if !self
+                    .hosts
+                    .iter()
+                    .any(|allowed| origin == format!("http://{}", allowed))
+                {
+                    return Err(StatusCode::FORBIDDEN);
+                }
+            }
+        }
+
+        if let Some(password) = &self.password {
+            let given = header(header::AUTHORIZATION)
+                .and_then(|value| value.strip_prefix("Basic "))
+                .and_then(|value| base64::engine::general_purpose::STANDARD.decode(value).ok())
+                .and_then(|value| String::from_utf8(value).ok());
+
+            match given.as_deref().and_then(|value| value.split_once(':')) {
+                Some((_username, given)) if given == password => (),
+                _ => return Err(StatusCode::UNAUTHORIZED),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn guard(State(guard): State<Arc<Guard>>, req: Request, next: Next) -> Response {
+    match guard.check(req.headers()) {
+        Ok(()) => next.run(req).await,
+        Err(StatusCode::UNAUTHORIZED) => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, r#"Basic realm="batch-tagger""#)],
+        )
+            .into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+/// Serve the web interface, with the JSON API available under `/api`. If
+/// `api_only` is set, the JSON API is served from the root instead.
+///
+/// Only loopback addresses may be used unless a password is given.
+pub async fn serve(
+    address: SocketAddr,
+    state: AppState,
+    api_only: bool,
+    password: Option<String>,
+) -> anyhow::Result<()> {
+    let guard_state = Arc::new(Guard::new(address, password)?);
+
+    let app = if api_only {
+        api::router()
+    } else {
+        Router::new()
+            .route("/", get(index))
+            .route("/apply", post(apply))
+            .nest("/api", api::router())
+    };
+    let app = app
+        .with_state(Arc::new(state))
+        .layer(Extension(CsrfToken(
+            uuid::Uuid::new_v4().simple().to_string().into(),
+        )))
+        .layer(middleware::from_fn_with_state(guard_state, guard));
+
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    tracing::info!("Listening on http://{}", address);
+
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+async fn index(
+    State(state): SharedState,
+    Extension(csrf): Extension<CsrfToken>,
+    Query(params): Query<PlanParams>,
+) -> Result<Html<String>, WebError> {
+    let mut body = format!(
+        r#"<form method="get" action="/">
+            <label>Search <input name="search" value="{search}"></label>
+            <label>Changes <input name="tags" value="{tags}"></label>
+            <button type="submit">Preview</button>
+        </form>"#,
+        search = escape(&params.search),
+        tags = escape(&params.tags),
+    );
+
+    if params.search.trim().is_empty() {
+        return Ok(Html(page("Submissions", &body)));
+    }
+
+    let matched = crate::search_submissions(
+        &state.pool,
+        &state.account,
+        &state.translations,
+        &params.search,
+    )
+    .await?;
+
+    body.push_str(&format!("<p>{} matched submissions</p>", matched.len()));
+
+    if !params.tags.trim().is_empty() && !matched.is_empty() {
+        body.push_str(&format!(
+            r#"<form method="post" action="/apply">
+                <input type="hidden" name="search" value="{search}">
+                <input type="hidden" name="tags" value="{tags}">
+                <input type="hidden" name="csrf" value="{csrf}">
+                <button type="submit">Apply changes to {count} submissions</button>
+            </form>"#,
+            search = escape(&params.search),
+            tags = escape(&params.tags),
+            csrf = csrf.0,
+            count = matched.len(),
+        ));
+    }
+
+    body.push_str("<table><tr><th>Submission</th><th>Posted</th><th>Tags</th></tr>");
+    for sub in &matched {
+        let new_tags =
+            crate::update_tags(sub, &sub.tags, &params.tags, &state.bundles, &state.sort);
+        body.push_str(&submission_row(sub, &new_tags));
+    }
+    body.push_str("</table>");
+
+    Ok(Html(page("Submissions", &body)))
+}
+
+async fn apply(
+    State(state): SharedState,
+    Extension(csrf): Extension<CsrfToken>,
+    Form(params): Form<PlanParams>,
+) -> Result<Response, WebError> {
+    if params.csrf != *csrf.0 {
+        return Ok((
+            StatusCode::FORBIDDEN,
+            Html(page(
+                "Error",
+                "<p>This form has expired, go back and submit it again.</p>",
+            )),
+        )
+            .into_response());
+    }
+
+    if params.search.trim().is_empty() {
+        return Err(anyhow::anyhow!("search must not be empty").into());
+    }
+
+    crate::check_placeholders(&params.tags)?;
+    crate::check_bundles(&params.tags, &state.bundles)?;
+
+    let matched = crate::search_submissions(
+        &state.pool,
+        &state.account,
+        &state.translations,
+        &params.search,
+    )
+    .await?;
+
+    let mut changes = Vec::with_capacity(matched.len());
+    for sub in &matched {
+        let new_tags =
+            crate::update_tags(sub, &sub.tags, &params.tags, &state.bundles, &state.sort);
+        state.blocklist.check(&sub.tags, &new_tags)?;
+        changes.push((sub, new_tags));
+    }
+
+    let batch_id = crate::start_batch(
+        &state.pool,
+        &state.account,
+        "serve",
+        Some(&params.search),
+        Some(&params.tags),
+    )
+    .await?;
+
+    let mut body = String::from("<table><tr><th>Submission</th><th>Posted</th><th>Tags</th></tr>");
+    for (sub, new_tags) in &changes {
+        body.push_str(&submission_row(sub, new_tags));
+    }
+
+    crate::apply_changes(
+        &state.pool,
+        &state.sites,
+        &state.account,
+        &state.sort,
+        batch_id,
+        changes,
+    )
+    .await?;
+
+    body.push_str(r#"</table><p><a href="/">Back</a></p>"#);
+
+    Ok(Html(page("Applied changes", &body)).into_response())
+}
+
+fn submission_row(sub: &Submission, new_tags: &[String]) -> String {
+    let mut tags: Vec<_> = new_tags
+        .iter()
+        .map(|tag| {
+            if sub.tags.contains(tag) {
+                escape(tag)
+            } else {
+                format!(r#"<ins>{}</ins>"#, escape(tag))
+            }
+        })
+        .collect();
+    tags.extend(
+        sub.tags
+            .iter()
+            .filter(|tag| !new_tags.contains(tag))
+            .map(|tag| format!(r#"<del>{}</del>"#, escape(tag))),
+    );
+
+    format!(
+        "<tr><td>{}-{} {}</td><td>{}</td><td>{}</td></tr>",
+        sub.site,
+        sub.id,
+        escape(&sub.title),
+        sub.posted_at.format("%Y-%m-%d"),
+        tags.join(", ")
+    )
+}
+
+pub(crate) fn page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>{title} - batch-tagger</title>
+    <style>
+        body {{ font-family: sans-serif; margin: 2em; }}
+        table {{ border-collapse: collapse; }}
+        td, th {{ border-bottom: 1px solid #ddd; padding: 0.25em 0.5em; text-align: left; }}
+        ins {{ color: green; }}
+        del {{ color: red; }}
+    </style>
+</head>
+<body>
+    <h1>{title}</h1>
+    {body}
+</body>
+</html>"#,
+        title = escape(title),
+        body = body
+    )
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+mod api {
+    use axum::Json;
+
+    use super::*;
+    use crate::sites::SubmissionSite;
+
+    /// Wrapper to convert any error into a JSON response.
+    struct ApiError(StatusCode, anyhow::Error);
+
+    impl<E: Into<anyhow::Error>> From<E> for ApiError {
+        fn from(err: E) -> Self {
+            Self(StatusCode::INTERNAL_SERVER_ERROR, err.into())
+        }
+    }
+
+    impl IntoResponse for ApiError {
+        fn into_response(self) -> Response {
+            tracing::error!("API request failed: {:?}", self.1);
+
+            let body = serde_json::json!({ "error": self.1.to_string() });
+            (self.0, Json(body)).into_response()
+        }
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct QueryRequest {
+        search: String,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct SubmissionId {
+        site: SubmissionSite,
+        id: i64,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct ApplyRequest {
+        search: Option<String>,
+        ids: Option<Vec<SubmissionId>>,
+        tags: String,
+        #[serde(default)]
+        dry_run: bool,
+    }
+
+    #[derive(Debug, serde::Serialize)]
+    struct ApplyResult {
+        site: SubmissionSite,
+        id: i64,
+        old_tags: Vec<String>,
+        new_tags: Vec<String>,
+    }
+
+    pub fn router() -> Router<Arc<AppState>> {
+        Router::new()
+            .route("/submissions", get(submissions))
+            .route("/query", post(query))
+            .route("/apply", post(apply))
+    }
+
+    async fn submissions(State(state): SharedState) -> Result<Json<Vec<Submission>>, ApiError> {
+        let submissions = crate::get_submissions(&state.pool, &state.account).await?;
+
+        Ok(Json(submissions))
+    }
+
+    async fn query(
+        State(state): SharedState,
+        Json(req): Json<QueryRequest>,
+    ) -> Result<Json<Vec<Submission>>, ApiError> {
+        let submissions = crate::search_submissions(
+            &state.pool,
+            &state.account,
+            &state.translations,
+            &req.search,
+        )
+        .await?;
+
+        Ok(Json(submissions))
+    }
+
+    async fn apply(
+        State(state): SharedState,
+        Json(req): Json<ApplyRequest>,
+    ) -> Result<Json<Vec<ApplyResult>>, ApiError> {
+        if let Err(err) = crate::check_placeholders(&req.tags)
+            .and_then(|_| crate::check_bundles(&req.tags, &state.bundles))
+        {
+            return Err(ApiError(StatusCode::BAD_REQUEST, err));
+        }
+
+        if req.tags.trim().is_empty() {
+            return Err(ApiError(
+                StatusCode::BAD_REQUEST,
+                anyhow::anyhow!("tags must not be empty"),
+            ));
+        }
+
+        let submissions;
+        let matched = match (&req.search, req.ids) {
+            (Some(search), None) if search.trim().is_empty() => {
+                return Err(ApiError(
+                    StatusCode::BAD_REQUEST,
+                    anyhow::anyhow!("search must not be empty"),
+                ))
+            }
+            (Some(search), None) => {
+                submissions = crate::search_submissions(
+                    &state.pool,
+                    &state.account,
+                    &state.translations,
+                    search,
+                )
+                .await?;
+                submissions.iter().collect()
+            }
+            (None, Some(ids)) => {
+                let ids: Vec<_> = ids.into_iter().map(|id| (id.site, id.id)).collect();
+
+                submissions = crate::filter_submissions(&state.pool, &state.account, |sub| {
+                    ids.contains(&(sub.site, sub.id))
+                })
+                .await?;
+                crate::find_submissions(&submissions, &ids)
+            }
+            _ => {
+                return Err(ApiError(
+                    StatusCode::BAD_REQUEST,
+                    anyhow::anyhow!("exactly one of search or ids must be provided"),
+                ))
+            }
+        };
+
+        let mut changes = Vec::with_capacity(matched.len());
+        for sub in matched {
+            let new_tags =
+                crate::update_tags(sub, &sub.tags, &req.tags, &state.bundles, &state.sort);
+            if let Err(err) = state.blocklist.check(&sub.tags, &new_tags) {
+                return Err(ApiError(StatusCode::BAD_REQUEST, err));
+            }
+            changes.push((sub, new_tags));
+        }
+
+        let results = changes
+            .iter()
+            .map(|(sub, new_tags)| ApplyResult {
+                site: sub.site,
+                id: sub.id,
+                old_tags: sub.tags.clone(),
+                new_tags: new_tags.clone(),
+            })
+            .collect();
+
+        if !req.dry_run {
+            let batch_id = crate::start_batch(
+                &state.pool,
+                &state.account,
+                "api",
+                req.search.as_deref(),
+                Some(&req.tags),
+            )
+            .await?;
+
+            crate::apply_changes(
+                &state.pool,
+                &state.sites,
+                &state.account,
+                &state.sort,
+                batch_id,
+                changes,
+            )
+            .await?;
+        }
+
+        Ok(Json(results))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn test_apply_rejects_empty_requests() {
+            let pool = crate::open_memory_database(false).await.unwrap();
+            sqlx::migrate!().run(&pool).await.unwrap();
+
+            let state = Arc::new(AppState {
+                pool,
+                sites: Default::default(),
+                account: "test".to_string(),
+                blocklist: Default::default(),
+                translations: Default::default(),
+                bundles: Default::default(),
+                sort: Default::default(),
+            });
+
+            let request = |search: &str, tags: &str| ApplyRequest {
+                search: Some(search.to_string()),
+                ids: None,
+                tags: tags.to_string(),
+                dry_run: true,
+            };
+
+            for req in [
+                request("", "tag"),
+                request("  ", "tag"),
+                request("tag", " "),
+            ] {
+                match apply(State(state.clone()), Json(req)).await {
+                    Err(ApiError(status, _)) => assert_eq!(status, StatusCode::BAD_REQUEST),
+                    Ok(_) => panic!("empty request was applied"),
+                }
+            }
+
+            let Json(results) = apply(State(state), Json(request("tag", "other")))
+                .await
+                .map_err(|err| err.1)
+                .unwrap();
+            assert!(results.is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+
+    use super::Guard;
+
+    fn headers(values: &[(header::HeaderName, &'static str)]) -> HeaderMap {
+        values
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn test_guard() {
+        assert!(Guard::new("0.0.0.0:3000".parse().unwrap(), None).is_err());
+
+        let guard = Guard::new("127.0.0.1:3000".parse().unwrap(), None).unwrap();
+        assert_eq!(
+            guard.check(&headers(&[(header::HOST, "127.0.0.1:3000")])),
+            Ok(())
+        );
+        assert_eq!(
+            guard.check(&headers(&[
+                (header::HOST, "localhost:3000"),
+                (header::ORIGIN, "http://localhost:3000"),
+            ])),
+            Ok(())
+        );
+        assert_eq!(
+            guard.check(&headers(&[(header::HOST, "attacker.example:3000")])),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            guard.check(&headers(&[
+                (header::HOST, "127.0.0.1:3000"),
+                (header::ORIGIN, "http://attacker.example"),
+            ])),
+            Err(StatusCode::FORBIDDEN)
+        );
+
+        // "user:hunter2" and "user:wrong"
+        let guard =
+            Guard::new("0.0.0.0:3000".parse().unwrap(), Some("hunter2".to_string())).unwrap();
+        assert_eq!(
+            guard.check(&headers(&[
+                (header::HOST, "tagger.lan:3000"),
+                (header::AUTHORIZATION, "Basic dXNlcjpodW50ZXIy"),
+            ])),
+            Ok(())
+        );
+        assert_eq!(
+            guard.check(&headers(&[
+                (header::HOST, "tagger.lan:3000"),
+                (header::AUTHORIZATION, "Basic dXNlcjp3cm9uZw=="),
+            ])),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            guard.check(&headers(&[(header::HOST, "tagger.lan:3000")])),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+    }
+}
@@ -0,0 +1,349 @@
+/// Namespace keywords are stored in, as `dc:subject`.
+const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+
+const JPEG_SOI: &[u8] = &[0xff, 0xd8];
+/// Start of an APP1 segment payload containing XMP.
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// Keyword of the `iTXt` chunk containing XMP.
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp\0";
+
+/// Keywords stored in an XMP packet's `dc:subject` bag.
+pub fn read_keywords(xmp: &str) -> Vec<String> {
+    let subject = regex::Regex::new(r"(?s)<dc:subject>(.*?)</dc:subject>").unwrap();
+    let item = regex::Regex::new(r"(?s)<rdf:li[^>]*>(.*?)</rdf:li>").unwrap();
+
+    let subject = match subject.captures(xmp) {
+        Some(captures) => captures.get(1).unwrap().as_str(),
+        None => return Vec::new(),
+    };
+
+    item.captures_iter(subject)
+        .map(|captures| unescape(captures[1].trim()))
+        .filter(|keyword| !keyword.is_empty())
+        .collect()
+}
+
+/// Replace the keywords in an XMP packet, keeping everything else. A new
+/// packet is created when there is no existing one to update.
+pub fn set_keywords(xmp: Option<&str>, keywords: &[String]) -> String {
+    let items: String = keywords
+        .iter()
+        .map(|keyword| format!("<rdf:li>{}</rdf:li>", escape(keyword)))
+        .collect();
+    let subject = format!("<dc:subject><rdf:Bag>{}</rdf:Bag></dc:subject>", items);
+
+    let existing = regex::Regex::new(r"(?s)<dc:subject>.*?</dc:subject>").unwrap();
+    let description = regex::Regex::new(r"(?s)<rdf:Description([^>]*?)(/?)>").unwrap();
+
+    match xmp {
+        Some(xmp) if existing.is_match(xmp) => existing
+            .replace(xmp, regex::NoExpand(&subject))
+            .into_owned(),
+        Some(xmp) if description.is_match(xmp) => {
+            let captures = description.captures(xmp).unwrap();
+            let mut attributes = captures[1].to_string();
+            if !xmp.contains("xmlns:dc=") {
+                attributes.push_str(&format!(r#" xmlns:dc="{}""#, DC_NAMESPACE));
+            }
+
+            let replacement = if &captures[2] == "/" {
+                format!(
+                    "<rdf:Description{}>{}</rdf:Description>",
+                    attributes, subject
+                )
+            } else {
+                format!("<rdf:Description{}>{}", attributes, subject)
+            };
+
+            let range = captures.get(0).unwrap().range();
+            format!(
+                "{}{}{}",
+                &xmp[..range.start],
+                replacement,
+                &xmp[range.end..]
+            )
+        }
+        _ => format!(
+            concat!(
+                "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n",
+                "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n",
+                "  <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n",
+                "    <rdf:Description rdf:about=\"\" xmlns:dc=\"{}\">{}</rdf:Description>\n",
+                "  </rdf:RDF>\n",
+                "</x:xmpmeta>\n",
+                "<?xpacket end=\"w\"?>\n",
+            ),
+            DC_NAMESPACE, subject
+        ),
+    }
+}
+
+/// The XMP packet embedded in a JPEG or PNG file, if it has one.
+pub fn read_embedded(data: &[u8]) -> Option<String> {
+    if data.starts_with(JPEG_SOI) {
+        jpeg_segments(data)
+            .into_iter()
+            .find_map(|(start, end)| jpeg_xmp(&data[start..end]))
+            .map(|xmp| String::from_utf8_lossy(xmp).into_owned())
+    } else if data.starts_with(PNG_SIGNATURE) {
+        png_chunks(data)
+            .into_iter()
+            .find_map(|(start, end)| png_xmp(&data[start..end]))
+            .map(|xmp| String::from_utf8_lossy(xmp).into_owned())
+    } else {
+        None
+    }
+}
+
+/// Replace the XMP packet embedded in a JPEG or PNG file, or add one if it
+/// has none, returning the updated file.
+pub fn embed(data: &[u8], xmp: &str) -> anyhow::Result<Vec<u8>> {
+    if data.starts_with(JPEG_SOI) {
+        embed_jpeg(data, xmp)
+    } else if data.starts_with(PNG_SIGNATURE) {
+        Ok(embed_png(data, xmp))
+    } else {
+        anyhow::bail!("only JPEG and PNG files can contain XMP")
+    }
+}
+
+/// Start and end of each JPEG segment before the image data.
+fn jpeg_segments(data: &[u8]) -> Vec<(usize, usize)> {
+    let mut segments = Vec::new();
+    let mut pos = JPEG_SOI.len();
+
+    while pos + 4 <= data.len() && data[pos] == 0xff {
+        let marker = data[pos + 1];
+        // Image data starts after the start of scan segment.
+        if marker == 0xda || marker == 0xd9 {
+            break;
+        }
+
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        let end = (pos + 2 + len).min(data.len());
+        segments.push((pos, end));
+        pos = end;
+    }
+
+    segments
+}
+
+fn jpeg_xmp(segment: &[u8]) -> Option<&[u8]> {
+    if segment.get(1) != Some(&0xe1) {
+        return None;
+    }
+
+    segment.get(4..)?.strip_prefix(JPEG_XMP_HEADER)
+}
+
+fn embed_jpeg(data: &[u8], xmp: &str) -> anyhow::Result<Vec<u8>> {
+    let len = 2 + JPEG_XMP_HEADER.len() + xmp.len();
+    if len > u16::MAX as usize {
+        anyhow::bail!("XMP packet is too large for a JPEG segment");
+    }
+
+    let mut segment = vec![0xff, 0xe1];
+    segment.extend_from_slice(&(len as u16).to_be_bytes());
+    segment.extend_from_slice(JPEG_XMP_HEADER);
+    segment.extend_from_slice(xmp.as_bytes());
+
+    let segments = jpeg_segments(data);
+    // Keep JFIF and EXIF segments first, as some readers expect them there.
+    let insert_at = segments
+        .iter()
+        .find(|(start, _end)| !matches!(data[start + 1], 0xe0 | 0xe1))
+        .map(|(start, _end)| *start)
+        .unwrap_or_else(|| segments.last().map_or(JPEG_SOI.len(), |(_start, end)| *end));
+
+    let mut out = Vec::with_capacity(data.len() + segment.len());
+    out.extend_from_slice(JPEG_SOI);
+    for (start, end) in segments.iter().copied() {
+        if start == insert_at {
+            out.extend_from_slice(&segment);
+        }
+        if jpeg_xmp(&data[start..end]).is_none() {
+            out.extend_from_slice(&data[start..end]);
+        }
+    }
+    let rest = segments.last().map_or(JPEG_SOI.len(), |(_start, end)| *end);
+    if rest == insert_at {
+        out.extend_from_slice(&segment);
+    }
+    out.extend_from_slice(&data[rest..]);
+
+    Ok(out)
+}
+
+/// Start and end of each PNG chunk, including its length and CRC.
+fn png_chunks(data: &[u8]) -> Vec<(usize, usize)> {
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+
+    while pos + 12 <= data.len() {
+        let len = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        let end = (pos + 12 + len).min(data.len());
+        chunks.push((pos, end));
+        pos = end;
+    }
+
+    chunks
+}
+
+fn png_xmp(chunk: &[u8]) -> Option<&[u8]> {
+    if chunk.get(4..8) != Some(b"iTXt") {
+        return None;
+    }
+
+    let text = chunk
+        .get(8..chunk.len() - 4)?
+        .strip_prefix(PNG_XMP_KEYWORD)?;
+    // Compressed text is not supported.
+    if text.first() != Some(&0) {
+        return None;
+    }
+
+    // Skip the compression method, language tag, and translated keyword.
+    let mut parts = text[2..].splitn(3, |byte| *byte == 0);
+    parts.next()?;
+    parts.next()?;
+    parts.next()
+}
+
+fn embed_png(data: &[u8], xmp: &str) -> Vec<u8> {
+    let mut body = b"iTXt".to_vec();
+    body.extend_from_slice(PNG_XMP_KEYWORD);
+    // Uncompressed, with no language tag or translated keyword.
+    body.extend_from_slice(&[0, 0, 0, 0]);
+    body.extend_from_slice(xmp.as_bytes());
+
+    let mut crc = flate2::Crc::new();
+    crc.update(&body);
+
+    let mut chunk = ((body.len() - 4) as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(&body);
+    chunk.extend_from_slice(&crc.sum().to_be_bytes());
+
+    let mut out = Vec::with_capacity(data.len() + chunk.len());
+    out.extend_from_slice(PNG_SIGNATURE);
+    for (index, (start, end)) in png_chunks(data).into_iter().enumerate() {
+        if png_xmp(&data[start..end]).is_none() {
+            out.extend_from_slice(&data[start..end]);
+        }
+        // The header chunk must always come first.
+        if index == 0 {
+            out.extend_from_slice(&chunk);
+        }
+    }
+
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{embed, read_embedded, read_keywords, set_keywords};
+
+    fn keywords(keywords: &[&str]) -> Vec<String> {
+        keywords.iter().map(|keyword| keyword.to_string()).collect()
+    }
+
+    #[test]
+    fn test_new_packet() {
+        let xmp = set_keywords(None, &keywords(&["wolf", "R&D"]));
+        assert!(xmp.contains("<rdf:li>R&amp;D</rdf:li>"));
+        assert_eq!(read_keywords(&xmp), vec!["wolf", "R&D"]);
+    }
+
+    #[test]
+    fn test_update_packet() {
+        let existing = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+<rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmp:Rating="4">
+  <dc:subject>
+    <rdf:Bag>
+      <rdf:li>fox</rdf:li>
+      <rdf:li>sketch</rdf:li>
+    </rdf:Bag>
+  </dc:subject>
+</rdf:Description></rdf:RDF></x:xmpmeta>"#;
+        assert_eq!(read_keywords(existing), vec!["fox", "sketch"]);
+
+        let updated = set_keywords(Some(existing), &keywords(&["wolf"]));
+        assert!(updated.contains(r#"xmp:Rating="4""#));
+        assert_eq!(read_keywords(&updated), vec!["wolf"]);
+
+        let empty = r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"><rdf:Description rdf:about=""/></rdf:RDF>"#;
+        let updated = set_keywords(Some(empty), &keywords(&["wolf"]));
+        assert!(updated.contains("xmlns:dc="));
+        assert!(updated.contains("</rdf:Description></rdf:RDF>"));
+        assert_eq!(read_keywords(&updated), vec!["wolf"]);
+    }
+
+    #[test]
+    fn test_embed_jpeg() {
+        let jpeg = [
+            &[0xff, 0xd8][..],
+            &[0xff, 0xe0, 0x00, 0x04, 0x4a, 0x46],
+            &[0xff, 0xdb, 0x00, 0x03, 0x01],
+            &[0xff, 0xda, 0x00, 0x02, 0x12, 0x34, 0xff, 0xd9],
+        ]
+        .concat();
+        assert_eq!(read_embedded(&jpeg), None);
+
+        let xmp = set_keywords(None, &keywords(&["fox"]));
+        let embedded = embed(&jpeg, &xmp).unwrap();
+        assert_eq!(read_embedded(&embedded).as_deref(), Some(xmp.as_str()));
+        assert!(embedded.ends_with(&jpeg[12..]));
+        assert_eq!(embedded[2..8], jpeg[2..8]);
+
+        let xmp = set_keywords(Some(&xmp), &keywords(&["wolf"]));
+        let replaced = embed(&embedded, &xmp).unwrap();
+        assert_eq!(
+            read_keywords(&read_embedded(&replaced).unwrap()),
+            vec!["wolf"]
+        );
+        assert_eq!(replaced.len(), embedded.len() + 1);
+    }
+
+    #[test]
+    fn test_embed_png() {
+        let png = [
+            &b"\x89PNG\r\n\x1a\n"[..],
+            &[0, 0, 0, 1],
+            b"IHDR",
+            &[0],
+            &[0, 0, 0, 0],
+            &[0, 0, 0, 0],
+            b"IEND",
+            &[0xae, 0x42, 0x60, 0x82],
+        ]
+        .concat();
+        assert_eq!(read_embedded(&png), None);
+
+        let xmp = set_keywords(None, &keywords(&["fox"]));
+        let embedded = embed(&png, &xmp).unwrap();
+        assert_eq!(read_embedded(&embedded).as_deref(), Some(xmp.as_str()));
+        assert!(embedded.starts_with(&png[..21]));
+        assert!(embedded.ends_with(&png[21..]));
+
+        let replaced = embed(&embedded, &xmp).unwrap();
+        assert_eq!(replaced, embedded);
+
+        assert!(embed(b"GIF89a", &xmp).is_err());
+    }
+}